@@ -32,10 +32,10 @@ function clearTypeSubscription() {
 // for each key press run a sequence of commands associated with `mode`
 // (ala `mode.whenNoBinding.run`)
 export async function runCommandsForMode(mode: Mode) {
-    if (mode.name !== 'capture') {
+    if (!mode.capture) {
         clearTypeSubscription();
     }
-    if (mode.whenNoBinding() === WhenNoBindingHeader.Run) {
+    if (mode.captures_typing()) {
         if (!typeSubscription) {
             try {
                 typeSubscription = vscode.commands.registerCommand('type', onType);
@@ -49,8 +49,16 @@ export async function runCommandsForMode(mode: Mode) {
             }
         }
         onTypeFn = async (typed: string) => {
+            // `whenNoBinding` rules can treat some keys differently
+            const action = mode.typed_action(typed);
+            if (action === WhenNoBindingHeader.InsertCharacters) {
+                await vscode.commands.executeCommand('default:type', { text: typed });
+                return;
+            } else if (action === WhenNoBindingHeader.IgnoreCharacters) {
+                return;
+            }
             state.set(CAPTURE, typed);
-            const binding = mode.run_commands(bindings);
+            const binding = mode.run_commands_for(typed, bindings);
             if (!showExpressionErrors(binding)) {
                 for (let i = 0; i < binding.n_commands(); i++) {
                     const resolved_command = binding.resolve_command(i, bindings);
@@ -87,14 +95,17 @@ export async function runCommandsForMode(mode: Mode) {
 
 // TODO: `onUpdate` is needlessly complicated. We can handle updating the text inside
 // `captureKeys`, we can have an `acceptsAfter` implemented in `captureKeys` and we can
-// handle cancel keys via the ability to define commands inside the `capture` mode.
+// handle cancel keys via the ability to define commands inside the capture mode.
 type UpdateFn = (captured: string, nextChar: string) => [string, boolean];
-export async function captureKeys(onUpdate: UpdateFn): Promise<string> {
+export async function captureKeys(
+    onUpdate: UpdateFn,
+    captureMode: string = 'capture',
+): Promise<string> {
     const oldMode = state.get<string>(MODE)!;
     if (!typeSubscription) {
         try {
             typeSubscription = vscode.commands.registerCommand('type', onType);
-            state.set(MODE, 'capture');
+            state.set(MODE, captureMode);
             state.resolve();
         } catch (_) {
             vscode.window.
@@ -117,7 +128,7 @@ export async function captureKeys(onUpdate: UpdateFn): Promise<string> {
     // if a keybinding is defined that changes the key mode, we want to stop capturing keys
     // at this point
     onSet(MODE, (mode) => {
-        if (mode !== 'capture') {
+        if (mode !== captureMode) {
             clearTypeSubscription();
             if (!isCaptured) {
                 isCaptured = true;
@@ -138,7 +149,7 @@ export async function captureKeys(onUpdate: UpdateFn): Promise<string> {
             state.set(MODE, oldMode);
             state.resolve();
 
-            // if the old mode wasn't 'capture', `captureValue` will have already been
+            // if the old mode wasn't `captureMode`, `captureValue` will have already been
             // called (in the `onSet` block above)
             if (!isCaptured) {
                 isCaptured = true;
@@ -150,9 +161,22 @@ export async function captureKeys(onUpdate: UpdateFn): Promise<string> {
     return returnValue;
 }
 
+// the capture mode named by a command's `mode` argument (defaults to `capture`)
+export function validCaptureMode(name: string | undefined): string {
+    if (name === undefined) {
+        return 'capture';
+    }
+    if (!bindings.mode(name)?.capture) {
+        vscode.window.showErrorMessage(`There is no capture mode named '${name}'.`);
+        return 'capture';
+    }
+    return name;
+}
+
 const captureKeysArgs = z.object({
     text: z.string().optional(),
     acceptAfter: z.number().min(1),
+    mode: z.string().optional(),
 });
 
 /**
@@ -166,6 +190,8 @@ const captureKeysArgs = z.object({
  *
  * **Arguments**
  * - `acceptAfter`: The number of keys to capture
+ * - `mode` (default="capture"): The [capture mode](/bindings/mode) to use while capturing
+ *   keys
  *
  * > [!NOTE] Implementation detail
  * > The command also accepts a second, optional argument called `text`, which can
@@ -194,7 +220,7 @@ async function captureKeysCmd(args_: unknown): Promise<CommandResult> {
                     }
                 }
                 return [result, stop];
-            });
+            }, validCaptureMode(args.mode));
         }
         if (!text) {
             return 'cancel';
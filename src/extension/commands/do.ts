@@ -307,6 +307,13 @@ export async function doCommandsCmd(args_: unknown): Promise<CommandResult> {
                     // here is where we clear the key sequence displayed by setting `PREFIX`
                     // above by calling `reset()`
                     state.reset();
+                    // leave a mode with `returnAfter` once enough bindings have run
+                    // (unless the binding itself changed modes)
+                    const mode: string = state.get(MODE) || bindings.default_mode();
+                    const returnTo = bindings.complete_binding(toRun, mode);
+                    if (returnTo) {
+                        state.set(MODE, returnTo);
+                    }
                     state.resolve();
                 } else {
                     state.resolve();
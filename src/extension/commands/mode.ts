@@ -1,11 +1,16 @@
 import * as vscode from 'vscode';
 import z from 'zod';
 import { onResolve } from '../state';
-import { updateCursorAppearance, validateInput } from '../utils';
+import { updateCursorAppearance, updateLineNumbers, validateInput } from '../utils';
 import { state, recordedCommand, CommandResult } from '../state';
 import { runCommandsForMode } from './capture';
+import { showExpressionErrors, showExpressionMessages } from './do';
 import { onSetBindings } from '../keybindings/config';
-import { CursorShape, KeyFileResult } from '../../rust/parsing/lib/parsing';
+import {
+    CursorShape,
+    KeyFileResult,
+    ReifiedBinding,
+} from '../../rust/parsing/lib/parsing';
 import { bindings } from '../keybindings/config';
 
 export const MODE = 'mode';
@@ -36,8 +41,10 @@ async function setMode(args_: unknown): Promise<CommandResult> {
 }
 
 export function restoreModesCursorState() {
-    const shape = bindings.mode(currentResolvedMode)?.cursorShape || CursorShape.Line;
-    updateCursorAppearance(vscode.window.activeTextEditor, shape);
+    const mode = bindings.mode(currentResolvedMode);
+    const editor = vscode.window.activeTextEditor;
+    updateCursorAppearance(editor, mode?.cursorShape || CursorShape.Line);
+    updateLineNumbers(editor, mode?.lineNumbers);
 }
 
 async function updateModes(bindings: KeyFileResult) {
@@ -47,6 +54,49 @@ async function updateModes(bindings: KeyFileResult) {
     state.resolve();
 }
 
+// run the commands of a mode's `onEnter` or `onExit` field
+async function runModeCommands(binding: ReifiedBinding) {
+    if (showExpressionErrors(binding)) {
+        return;
+    }
+    for (let i = 0; i < binding.n_commands(); i++) {
+        const resolved_command = binding.resolve_command(i, bindings);
+        showExpressionMessages(resolved_command);
+        showExpressionErrors(resolved_command);
+        if (resolved_command.command !== 'master-key.ignore') {
+            await vscode.commands.executeCommand(
+                resolved_command.command,
+                resolved_command.args,
+            );
+        }
+    }
+}
+
+// true while the `onExit` and `onEnter` commands of a mode change are running
+let changingModes = false;
+
+async function changeModes(fromName: string, toName: string) {
+    const from = bindings.mode(fromName);
+    const to = bindings.mode(toName);
+    if (from) {
+        await runModeCommands(from.exit_commands(bindings));
+    }
+
+    // the new mode is only recorded once the old mode has been exited
+    const editor = vscode.window.activeTextEditor;
+    updateCursorAppearance(editor, to?.cursorShape || CursorShape.Line);
+    updateLineNumbers(editor, to?.lineNumbers);
+    if (to) {
+        runCommandsForMode(to);
+    }
+    bindings.enter_mode(fromName, toName);
+    currentResolvedMode = toName;
+
+    if (to) {
+        await runModeCommands(to.enter_commands(bindings));
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////////////////
 // activation
 
@@ -59,8 +109,9 @@ export async function activate(_context: vscode.ExtensionContext) {
     onSetBindings(updateModes);
 
     vscode.window.onDidChangeActiveTextEditor((e) => {
-        const shape = bindings.mode(currentResolvedMode)?.cursorShape || CursorShape.Line;
-        updateCursorAppearance(e, shape);
+        const mode = bindings.mode(currentResolvedMode);
+        updateCursorAppearance(e, mode?.cursorShape || CursorShape.Line);
+        updateLineNumbers(e, mode?.lineNumbers);
     });
 
     const defaultMode = bindings.default_mode() || 'default';
@@ -75,14 +126,26 @@ export async function activate(_context: vscode.ExtensionContext) {
         // async that I don't understand???)
         // TODO: this might not be necessary now that we've changed how `state.ts` works
         const newMode = <string>state.get(MODE) || bindings.default_mode() || 'default';
-        const mode = bindings.mode(newMode);
-        if (_currentMode !== newMode) {
-            const shape = (mode?.cursorShape || CursorShape.Line);
-            updateCursorAppearance(vscode.window.activeTextEditor, shape);
-            if (mode) {
-                runCommandsForMode(mode);
-            }
-            currentResolvedMode = newMode;
+        // a mode change made while the hooks of another run is undone below: following
+        // it could run hooks forever (e.g. two modes whose `onEnter` enter each other)
+        if (!changingModes && _currentMode !== newMode) {
+            changingModes = true;
+            changeModes(_currentMode, newMode)
+                .catch((e) => {
+                    const change = `from mode '${_currentMode}' to '${newMode}'`;
+                    showExpressionErrors({ errors: [`While changing ${change}: ${e}`] });
+                })
+                .finally(() => {
+                    changingModes = false;
+                    if (state.get(MODE) !== newMode) {
+                        vscode.window.showErrorMessage(
+                            `The mode can't change while the \`onExit\` and \`onEnter\` ` +
+                            `commands of mode '${newMode}' run.`,
+                        );
+                        state.set(MODE, newMode);
+                        state.resolve();
+                    }
+                });
         }
         return true;
     });
@@ -1,12 +1,9 @@
 import * as vscode from 'vscode';
-import { getRequiredMode, getRequiredPrefixCode, prettifyPrefix } from '../utils';
+import { getRequiredMode, getRequiredPrefixCode } from '../utils';
 import { state, onResolve } from '../state';
 import { bindings, onSetBindings } from '../keybindings/config';
 import { PREFIX_CODE } from './prefix';
 import { MODE } from './mode';
-import {
-    simplifyLayoutIndependentString,
-} from '../keybindings/layout';
 import { BindingDoc, KeyFileResult } from '../../rust/parsing/lib/parsing';
 import { doCommandsCmd, paletteEnabled } from './do';
 import { isEqual } from 'lodash';
@@ -229,9 +226,9 @@ function updateKeys(bindings: KeyFileResult) {
             continue;
         }
         const { binding, docs } = candidate;
-        const key = normalizeKey(candidate.rawKey);
+        const key = normalizeKey(bindings, candidate.rawKey);
         const combinedKey = candidate.rawCombinedKey ?
-                normalizeKey(candidate.rawCombinedKey) :
+                normalizeKey(bindings, candidate.rawCombinedKey) :
             '';
         const context = bindingContext(binding);
 
@@ -257,7 +254,7 @@ function updateKeys(bindings: KeyFileResult) {
             continue;
         }
         const { binding } = candidate;
-        const key = normalizeKey(binding.key);
+        const key = normalizeKey(bindings, binding.key);
         const context = bindingContext(binding);
 
         // Skip if an explicit entry already claims this key
@@ -343,9 +340,9 @@ function bindingContext(binding: PaletteKeyBinding): string {
     return `${getRequiredPrefixCode(binding.when)}:${getRequiredMode(binding.when)}`;
 }
 
-/** Normalize a key string for palette display. */
-function normalizeKey(raw: string): string {
-    return simplifyLayoutIndependentString(prettifyPrefix(raw), { noBrackets: true });
+/** Normalize a key string for palette display (see `display_keys` in `file.rs`). */
+function normalizeKey(keyFile: KeyFileResult, raw: string): string {
+    return keyFile.display_keys(raw);
 }
 
 /** Insert or merge a palette entry into the per-context map. */
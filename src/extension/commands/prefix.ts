@@ -25,6 +25,9 @@ const prefixArgs = z.
         fromDo: z.boolean().default(true),
         key: z.string(),
         binding_hash: z.string().default(''),
+        // documentation from `[[prefix]]`
+        name: z.string().optional(),
+        description: z.string().optional(),
         cursor: z.enum([
             'Line',
             'Block',
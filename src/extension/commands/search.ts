@@ -3,7 +3,7 @@ import z from 'zod';
 import { validateInput, wrappedTranslate } from '../utils';
 import { state as keyState, CommandResult, recordedCommand } from '../state';
 import { MODE } from './mode';
-import { captureKeys } from './capture';
+import { captureKeys, validCaptureMode } from './capture';
 import { bindings } from '../keybindings/config';
 import { onCommandComplete } from './do';
 
@@ -56,6 +56,8 @@ import { onCommandComplete } from './do';
  *    jump. If you have multiple search commands you can use registers to avoid the two
  *    commands using a shared search state.
  * - `skip` (default=0): the number of matches to skip before stopping.
+ * - `mode` (default="capture"): The [capture mode](/bindings/mode) to use while the search
+ *   string is requested from the user.
  */
 
 const offsets = z.enum([
@@ -80,6 +82,7 @@ export const searchArgs = z.
         })),
         register: z.string().default('default'),
         skip: z.number().optional().default(0),
+        mode: z.string().optional(),
     }).
     strict();
 export type SearchArgs = z.infer<typeof searchArgs>;
@@ -242,7 +245,8 @@ class SearchState {
     // where to start searching from next time
     _searchFrom: readonly vscode.Selection[] = [];
     // the keybinding mode prior to executing a search the mode during search execution may
-    // be changed to `captured`, but when we're done searching we should revert to `oldMode`
+    // be changed to a capture mode, but when we're done searching we should revert to
+    // `oldMode`
     oldMode: string;
     // has the state changed? we use this to determine when to clear search decorators
     modified = false;
@@ -290,7 +294,7 @@ function getSearchState(
         return searchState;
     } else {
         const state = statesForEditor[register];
-        state.oldMode = mode !== 'capture' ? mode : state.oldMode;
+        state.oldMode = bindings.mode(mode)?.capture ? state.oldMode : mode;
         return state;
     }
 }
@@ -564,13 +568,13 @@ async function search(args_: unknown[]): Promise<CommandResult> {
                     // the arguments on every keypress
                 }
                 return [result, stop];
-            });
+            }, validCaptureMode(state.args.mode));
             if (!state.text) {
                 return 'cancel';
             }
             navigatePastSkippedMatches(state, editor);
         } else {
-            keyState.set(MODE, 'capture');
+            keyState.set(MODE, validCaptureMode(state.args.mode));
             keyState.resolve();
             let accepted = false;
             const inputResult = new Promise<string>((resolve, reject) => {
@@ -2,14 +2,12 @@ import { CommandState } from '../state';
 import { state, onResolve } from '../state';
 import * as vscode from 'vscode';
 import { MODE } from './mode';
-import { simplifyLayoutIndependentString } from '../keybindings/layout';
-import { onSetBindings } from '../keybindings/config';
+import { bindings, onSetBindings } from '../keybindings/config';
 import { PREFIX_CODE } from './prefix';
 import {
     getRequiredMode,
     getRequiredPrefixCode,
     modifierKey,
-    prettifyPrefix,
 } from '../utils';
 import { KeyFileResult } from '../../rust/parsing/lib/parsing';
 
@@ -18,7 +16,8 @@ import { KeyFileResult } from '../../rust/parsing/lib/parsing';
 
 // properties of each key board key
 interface IKeyTemplate {
-    // the key name (e.g. A)
+    // the key name, as in `keybindings.json` (e.g. a); modifier keys are labeled by their
+    // name as is
     name?: string;
     // the length (e.g. space is longer)
     length?: string;
@@ -46,19 +45,19 @@ interface IKey {
 // define the keyboard
 const keyRowsTemplate: IKeyTemplate[][] = [
     [
-        { name: 'ESC', height: '0-5', firstRow: true },
-        { name: 'F1', height: '0-5', firstRow: true },
-        { name: 'F2', height: '0-5', firstRow: true },
-        { name: 'F3', height: '0-5', firstRow: true },
-        { name: 'F4', height: '0-5', firstRow: true },
-        { name: 'F5', height: '0-5', firstRow: true },
-        { name: 'F6', height: '0-5', firstRow: true },
-        { name: 'F7', height: '0-5', firstRow: true },
-        { name: 'F8', height: '0-5', firstRow: true },
-        { name: 'F9', height: '0-5', firstRow: true },
-        { name: 'F10', height: '0-5', firstRow: true },
-        { name: 'F11', height: '0-5', firstRow: true },
-        { name: 'F12', height: '0-5', firstRow: true },
+        { name: 'escape', height: '0-5', firstRow: true },
+        { name: 'f1', height: '0-5', firstRow: true },
+        { name: 'f2', height: '0-5', firstRow: true },
+        { name: 'f3', height: '0-5', firstRow: true },
+        { name: 'f4', height: '0-5', firstRow: true },
+        { name: 'f5', height: '0-5', firstRow: true },
+        { name: 'f6', height: '0-5', firstRow: true },
+        { name: 'f7', height: '0-5', firstRow: true },
+        { name: 'f8', height: '0-5', firstRow: true },
+        { name: 'f9', height: '0-5', firstRow: true },
+        { name: 'f10', height: '0-5', firstRow: true },
+        { name: 'f11', height: '0-5', firstRow: true },
+        { name: 'f12', height: '0-5', firstRow: true },
     ],
     [
         { name: '`' },
@@ -74,48 +73,48 @@ const keyRowsTemplate: IKeyTemplate[][] = [
         { name: '0' },
         { name: '-' },
         { name: '=' },
-        { name: 'DELETE', length: '1-5' },
+        { name: 'backspace', length: '1-5' },
     ],
     [
-        { name: 'TAB', length: '1-5' },
-        { name: 'Q' },
-        { name: 'W' },
-        { name: 'E' },
-        { name: 'R' },
-        { name: 'T' },
-        { name: 'Y' },
-        { name: 'U' },
-        { name: 'I' },
-        { name: 'O' },
-        { name: 'P' },
+        { name: 'tab', length: '1-5' },
+        { name: 'q' },
+        { name: 'w' },
+        { name: 'e' },
+        { name: 'r' },
+        { name: 't' },
+        { name: 'y' },
+        { name: 'u' },
+        { name: 'i' },
+        { name: 'o' },
+        { name: 'p' },
         { name: '[' },
         { name: ']' },
         { name: '\\' },
     ],
     [
         { name: 'CAPS LOCK', length: '1-75', modifier: true },
-        { name: 'A' },
-        { name: 'S' },
-        { name: 'D' },
-        { name: 'F' },
-        { name: 'G' },
-        { name: 'H' },
-        { name: 'J' },
-        { name: 'K' },
-        { name: 'L' },
+        { name: 'a' },
+        { name: 's' },
+        { name: 'd' },
+        { name: 'f' },
+        { name: 'g' },
+        { name: 'h' },
+        { name: 'j' },
+        { name: 'k' },
+        { name: 'l' },
         { name: ';' },
         { name: '\'' },
-        { name: 'ENTER', length: '1-75' },
+        { name: 'enter', length: '1-75' },
     ],
     [
         { name: 'SHIFT', length: '2-25', modifier: true },
-        { name: 'Z' },
-        { name: 'X' },
-        { name: 'C' },
-        { name: 'V' },
-        { name: 'B' },
-        { name: 'N' },
-        { name: 'M' },
+        { name: 'z' },
+        { name: 'x' },
+        { name: 'c' },
+        { name: 'v' },
+        { name: 'b' },
+        { name: 'n' },
+        { name: 'm' },
         { name: ',' },
         { name: '.' },
         { name: '/' },
@@ -126,7 +125,7 @@ const keyRowsTemplate: IKeyTemplate[][] = [
         {},
         {},
         { length: '1-25' },
-        { length: '5', name: 'SPACE' },
+        { length: '5', name: 'space' },
         { length: '1-25' },
         {},
         {},
@@ -135,23 +134,29 @@ const keyRowsTemplate: IKeyTemplate[][] = [
     ],
 ];
 
+// the label of `key` pressed with `modifiers`, as shown elsewhere (see `display_keys`)
+function keyLabel(modifiers: readonly string[], key: string) {
+    const press = modifiers.filter(x => x).concat([key]).join('+');
+    return bindings ? bindings.display_keys(press) : press;
+}
+
 // given the modifiers for each row, generate the keys
 function keyRows(
-    topModifier?: readonly string[],
-    bottomModifier?: readonly string[],
+    topModifier: readonly string[] = [],
+    bottomModifier: readonly string[] = [],
 ): IKey[][] {
     return keyRowsTemplate.map(row =>
         row.map((key) => {
             if (key.name && !key.modifier && !key.firstRow) {
                 return {
-                    top: topModifier?.join() + key.name,
-                    bottom: bottomModifier?.join() + key.name,
+                    top: keyLabel(topModifier, key.name),
+                    bottom: keyLabel(bottomModifier, key.name),
                     length: key.length,
                     height: key.height,
                 };
             } else {
                 return {
-                    bottom: key.name,
+                    bottom: key.name && !key.modifier ? keyLabel([], key.name) : key.name,
                     length: key.length,
                     height: key.height,
                 };
@@ -224,7 +229,7 @@ export class DocViewProvider implements vscode.WebviewViewProvider {
     // the modifiers that should show up on the top of the key
     public get topModifier(): readonly string[] {
         // get modifiers for current context
-        const modifiers = this._modifierOrder || [[''], ['⇧']];
+        const modifiers = this._modifierOrder || [[''], ['shift']];
         const aModifiers = modifiers[this._modifierIndex % modifiers.length];
         const bModifiers = modifiers[(this._modifierIndex + 1) % modifiers.length];
         const aLength = aModifiers.map(x => x.length).reduce((x, y) => x + y);
@@ -239,7 +244,7 @@ export class DocViewProvider implements vscode.WebviewViewProvider {
     // the modifiers that should show up on the bottom of the key
     public get bottomModifier(): readonly string[] {
         // get modifiers for current context
-        const modifiers = this._modifierOrder || [[''], ['⇧']];
+        const modifiers = this._modifierOrder || [[''], ['shift']];
         const aModifiers = modifiers[this._modifierIndex % modifiers.length];
         const bModifiers = modifiers[(this._modifierIndex + 1) % modifiers.length];
         const aLength = aModifiers.map(x => x.length).reduce((x, y) => x + y);
@@ -282,7 +287,7 @@ export class DocViewProvider implements vscode.WebviewViewProvider {
             const key = modifierKey(binding.key).sort().join('.');
             const countKey = `${prefixCode}:${mode}`;
             const countsForContext =
-                modifierCounts[countKey] || { '': 0, '⇧': 0 };
+                modifierCounts[countKey] || { '': 0, 'shift': 0 };
             countsForContext[key] = get(countsForContext, key, 0) + 1;
             modifierCounts[countKey] = countsForContext;
         }
@@ -307,8 +312,7 @@ export class DocViewProvider implements vscode.WebviewViewProvider {
             if (binding.command === 'master-key.ignore') {
                 continue;
             }
-            let label = prettifyPrefix(binding.key);
-            label = simplifyLayoutIndependentString(label, { noBrackets: true });
+            const label = bindings.display_keys(binding.key);
             const prefixCode = getRequiredPrefixCode(binding.when);
             const mode = getRequiredMode(binding.when);
             const key = `${prefixCode}:${mode}`;
@@ -322,8 +326,8 @@ export class DocViewProvider implements vscode.WebviewViewProvider {
                 };
             } else if (binding.command === 'master-key.prefix') {
                 mapping[label] = {
-                    name: oldKey.name || 'prefix',
-                    description: oldKey.description || '',
+                    name: binding.args.name || oldKey.name || 'prefix',
+                    description: binding.args.description || oldKey.description || '',
                     kind: oldKey.kind || '',
                 };
             }
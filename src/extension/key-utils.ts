@@ -1,12 +1,9 @@
 import replaceAll from 'string.prototype.replaceall';
 
-// splits out the modifier key
+// splits out the modifier keys (e.g. `ctrl+shift+a` has `ctrl` and `shift`)
 export function modifierKey(str: string) {
     if (str.match(/\+/)) {
-        return str.
-            split('+').
-            slice(0, -1).
-            map(x => prettifyPrefix(x));
+        return str.split('+').slice(0, -1);
     }
     return [''];
 }
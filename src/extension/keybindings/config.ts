@@ -139,6 +139,7 @@ export class KeyFileData {
                         data,
                         await sourceData.bindings(),
                     );
+                    result.set_key_platform(process.platform);
                     this._parsed = result;
                     return result;
                 } else {
@@ -150,6 +151,7 @@ export class KeyFileData {
                 }
             } else {
                 const result = parse_keybinding_bytes(data);
+                result.set_key_platform(process.platform);
                 this._parsed = result;
                 return result;
             }
@@ -21,7 +21,7 @@ import {
     KeyFileResult,
     ErrorLevel,
 } from '../../rust/parsing/lib';
-import { prettifyPrefix, replaceMatchesWith, clean } from '../utils';
+import { replaceMatchesWith, clean } from '../utils';
 import { commandMutex } from '../commands/do';
 
 ////////////////////////////////////////////////////////////////////////////////////////////
@@ -346,11 +346,8 @@ async function getWebviewContent(
 async function showTextDocumentation(context: vscode.ExtensionContext) {
     const content = bindings.text_docs();
     if (content) {
-        const regex = /<key-bind>(.*?)<\/key-bind>/gs;
-        const prettyKey = replaceMatchesWith(content, regex, (str) => {
-            return prettifyPrefix(str);
-        });
-        const html = await marked(prettyKey);
+        // NOTE: `text_docs` renders keys for the current platform (see `set_key_platform`)
+        const html = await marked(content);
         const header = /<h[1-3]>(.*?)<\/h[1-3]>/gs;
         const headerAnchors = replaceMatchesWith(html, header, (str) => {
             return `
@@ -3,8 +3,7 @@ import { onSet } from '../state';
 import { PREFIX } from '../commands/prefix';
 import { COUNT } from '../commands/count';
 import { state } from '../state';
-import { prettifyPrefix } from '../utils';
-import { simplifyLayoutIndependentString } from '../keybindings/layout';
+import { bindings } from '../keybindings/config';
 
 let keyStatusBar: vscode.StatusBarItem | undefined = undefined;
 
@@ -18,9 +17,10 @@ function updateKeyStatus(_: unknown) {
         // the count (e.g. 12× )
         const count = <number>state.get(COUNT) || 0;
         let plannedUpdate = count ? count + '× ' : '';
-        // the description of the current key prefix, e.g. SPACE, C
-        const keyseq = simplifyLayoutIndependentString(<string>state.get(PREFIX) || '');
-        plannedUpdate += prettifyPrefix(keyseq);
+        // the description of the current key prefix, rendered for the current platform
+        // (e.g. ⌃K on macOS or Ctrl+K on Windows)
+        const keyseq = <string>state.get(PREFIX) || '';
+        plannedUpdate += bindings ? bindings.display_keys(keyseq) : keyseq;
         if (plannedUpdate.length > 0) {
             keyStatusBar.text = plannedUpdate;
             keyStatusBar.accessibilityInformation = {
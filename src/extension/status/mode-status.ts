@@ -11,11 +11,20 @@ function updateModeStatus() {
     if (modeStatusBar) {
         // the name of the mode
         const mode = <string>state.get(MODE) || bindings.default_mode();
+        const modeInfo = bindings.mode(mode);
         // the coloring of the mode name
-        const highlight = bindings.mode(mode)?.highlight || 'NoHighlight';
+        const highlight = modeInfo?.highlight || 'NoHighlight';
         // an indicator of whether `master-key.record === true`
         const rec = state.get<boolean>(RECORD) || false;
-        modeStatusBar.text = (rec ? 'rec: ' : '') + mode;
+        const text = modeInfo ? modeInfo.status_text(bindings) : mode;
+        modeStatusBar.text = (rec ? 'rec: ' : '') + text;
+        const color = modeInfo?.color;
+        if (color) {
+            modeStatusBar.color =
+                color.startsWith('#') ? color : new vscode.ThemeColor(color);
+        } else {
+            modeStatusBar.color = undefined;
+        }
         modeStatusBar.accessibilityInformation = {
             label: 'Keybinding Mode: ' + modeStatusBar.text,
         };
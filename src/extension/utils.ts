@@ -1,8 +1,8 @@
 import * as vscode from 'vscode';
 import z from 'zod';
 import { fromZodError } from 'zod-validation-error';
-export { modifierKey, prettifyPrefix, replaceMatchesWith } from './key-utils';
-import { CursorShape } from '../rust/parsing/lib/parsing';
+export { modifierKey, replaceMatchesWith } from './key-utils';
+import { CursorShape, LineNumbers } from '../rust/parsing/lib/parsing';
 
 // function validateInput(command: string, args_: unknown,
 //     using: z.ZodUn);
@@ -106,3 +106,34 @@ export function updateCursorAppearance(
             CURSOR_STYLES[cursorShape] || vscode.TextEditorCursorStyle.Line;
     }
 }
+
+const LINE_NUMBER_STYLES = {
+    [LineNumbers.On]: vscode.TextEditorLineNumbersStyle.On,
+    [LineNumbers.Off]: vscode.TextEditorLineNumbersStyle.Off,
+    [LineNumbers.Relative]: vscode.TextEditorLineNumbersStyle.Relative,
+    [LineNumbers.Interval]: vscode.TextEditorLineNumbersStyle.Interval,
+};
+
+const SETTING_TO_LINE_NUMBERS: Record<string, vscode.TextEditorLineNumbersStyle> = {
+    on: vscode.TextEditorLineNumbersStyle.On,
+    off: vscode.TextEditorLineNumbersStyle.Off,
+    relative: vscode.TextEditorLineNumbersStyle.Relative,
+    interval: vscode.TextEditorLineNumbersStyle.Interval,
+};
+
+// modes without a `lineNumbers` field use the `editor.lineNumbers` setting
+export function updateLineNumbers(
+    editor: vscode.TextEditor | undefined,
+    lineNumbers: LineNumbers | undefined,
+) {
+    if (editor) {
+        if (lineNumbers === undefined) {
+            const setting = vscode.workspace.getConfiguration('editor').
+                get<string>('lineNumbers') || 'on';
+            editor.options.lineNumbers = SETTING_TO_LINE_NUMBERS[setting] ??
+                vscode.TextEditorLineNumbersStyle.On;
+        } else {
+            editor.options.lineNumbers = LINE_NUMBER_STYLES[lineNumbers];
+        }
+    }
+}
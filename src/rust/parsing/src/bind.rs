@@ -16,6 +16,7 @@ use wasm_bindgen::prelude::*;
 pub mod command;
 pub mod foreach;
 pub mod prefix;
+pub mod tree;
 pub mod validation;
 
 use crate::bind::command::{Command, CommandOutput, regularize_commands};
@@ -25,6 +26,9 @@ use crate::error::{ErrorContext, ParseError, Result, ResultVec, err};
 use crate::expression::Scope;
 use crate::expression::value::{Expanding, Expression, TypedValue, Value};
 use crate::file::KeyFileResult;
+use crate::key::notation::KeyNotation;
+use crate::key::{canonical_press, canonical_sequence};
+use crate::mode::{Mode, Modes};
 use crate::resolve;
 use crate::util::{Merging, Plural, Required, Resolving};
 use crate::{err, note, wrn};
@@ -88,10 +92,44 @@ pub struct BindingInput {
     ///
     /// - ❗`key`: the
     ///   [keybinding](https://code.visualstudio.com/docs/getstarted/keybindings) that
-    ///   triggers `command`.
+    ///   triggers `command`. Modifiers can be listed in any order and case: `Shift+Ctrl+A`
+    ///   and `ctrl+shift+a` are the same key press. As in VSCode, `cmd`, `win` and `meta`
+    ///   are all the same modifier. The key is written to `keybindings.json` as
+    ///   `ctrl+shift+a` (or `meta+a` for `cmd+a`) but is shown in the documentation as
+    ///   written.
     #[serde(default = "span_required_default")]
     pub key: Spanned<Required<KeyBinding>>,
 
+    /// @forBindingField bind
+    ///
+    /// - `keyNotation`: (defaults to `header.keyNotation`) the notation `key` is written
+    ///   in:
+    ///   - `"vscode"`: VSCode's notation (e.g. `ctrl+x ctrl+f`)
+    ///   - `"vim"`: Vim's notation (e.g. `<C-w>h` or `gg`); each character is a key press,
+    ///     so `G` is `shift+g` and `:` is `shift+;` (characters are those of a US keyboard).
+    ///     Keys with modifiers or names are written in brackets: `<C-a>`, `<M-x>`
+    ///     (`alt+x`), `<D-s>` (`cmd+s`), `<S-Tab>`, `<CR>`, `<Esc>`, `<Space>`, `<F1>`
+    ///     etc.
+    ///   - `"emacs"`: Emacs' notation (e.g. `C-x C-f`): key presses are separated by
+    ///     spaces and modifiers are `C-` (`ctrl`), `M-` (`alt`), `S-` (`shift`) and `s-`
+    ///     (`meta`). Named keys are written `RET`, `SPC`, `TAB`, `ESC` and `DEL`
+    ///     (`backspace`) or in brackets (e.g. `<f1>` or `<up>`).
+    ///
+    ///   In Vim and Emacs notation, `<leader>` stands for the key sequence
+    ///   of the variable `leader` (see [`define.val`](/bindings/define)), which is written
+    ///   in the same notation.
+    ///
+    ///   ```toml
+    ///   [[define.val]]
+    ///   leader = "<Space>"
+    ///
+    ///   [[bind]]
+    ///   keyNotation = "vim"
+    ///   key = "<leader>w<C-h>"  # space w ctrl+h
+    ///   command = "workbench.action.focusLeftGroup"
+    ///   ```
+    pub keyNotation: Option<Spanned<KeyNotation>>,
+
     /// @forBindingField bind
     ///
     /// - ❗`command`: A string denoting the command to execute. This is a command defined by
@@ -115,6 +153,19 @@ pub struct BindingInput {
     pub when: Option<Spanned<TypedValue<String>>>,
     /// @forBindingField bind
     ///
+    /// - `focus`: (defaults to `header.focus`) where a binding with a key press that lacks
+    ///   a modifier (e.g. `j`, `shift+j` or the `s` of `ctrl+k s`) is active. Such keys
+    ///   would otherwise be captured while the user types text, so a context is added to
+    ///   `when`:
+    ///   - `"editor"`: (the default) the text editor has focus and no suggestions are shown
+    ///   - `"terminal"`: the terminal has focus
+    ///   - `"notebook"`: a notebook has focus and no cell is being edited
+    ///   - `"list"`: a list or tree (e.g. the file explorer) has focus and no text is being
+    ///     entered
+    ///   - `"any"`: no context is added; the binding is active wherever `when` holds
+    pub focus: Option<Spanned<Focus>>,
+    /// @forBindingField bind
+    ///
     /// - `mode`: The key mode (or modes) for which the binding is active. Can be a string
     ///   or an array of strings. The default mode is used when this field is not specified.
     ///   When using an [expression](/expressions/index) there are two available functions
@@ -127,7 +178,8 @@ pub struct BindingInput {
     ///   inserted into `keybinding.json`; determines which bindings trigger for a given key
     ///   press. Higher priorities trigger over lower priorities because they are occur
     ///   later in `keybindings.json`. Bindings with same priority are follow the same order
-    ///   as provided in the master key TOML file.
+    ///   as provided in the master key TOML file. When left unset, the `priority` of the
+    ///   binding's [mode](/bindings/mode) is used.
     pub priority: Option<Spanned<TypedValue<f64>>>,
     /// @forBindingField bind
     ///
@@ -247,7 +299,9 @@ impl BindingInput {
             command: self.command.clone(),
             args: self.args.clone(),
             key: self.key.clone(),
+            keyNotation: self.keyNotation.clone(),
             when: self.when.clone(),
+            focus: self.focus.clone(),
             mode: self.mode.clone(),
             priority: self.priority.clone(),
             default: self.default.clone(),
@@ -261,6 +315,18 @@ impl BindingInput {
         };
     }
 
+    // translates `key` (the resolved value of `self.key`) from the notation of this binding
+    // (see `KeyNotation`) to VSCode's notation and validates it
+    pub(crate) fn translate_key(&self, key: &str, scope: &Scope) -> Result<String> {
+        let notation = match &self.keyNotation {
+            Some(x) => *x.as_ref(),
+            Option::None => scope.key_notation,
+        };
+        let literal = matches!(self.key.as_ref(), Required::Value(k) if k.constant().is_some());
+        let key = Spanned::new(self.key.span(), key.to_string());
+        return notation.translate_key(&key, literal, scope);
+    }
+
     // it is not an error to include additional fields; this makes parsing forwards
     // compatible so long as the file `version` is non-breaking
     pub(crate) fn check_other_fields(&self, warnings: &mut Vec<ParseError>) {
@@ -291,7 +357,9 @@ impl Merging for BindingInput {
             after: self.after.coalesce(y.after),
             args: self.args.merge(y.args),
             key: self.key.coalesce(y.key),
+            keyNotation: self.keyNotation.coalesce(y.keyNotation),
             when: self.when.coalesce(y.when),
+            focus: self.focus.coalesce(y.focus),
             mode: self.mode.coalesce(y.mode),
             priority: self.priority.coalesce(y.priority),
             default: self.default.coalesce(y.default),
@@ -317,7 +385,9 @@ impl Expanding for BindingInput {
             self.command.is_constant(),
             self.args.is_constant(),
             self.key.is_constant(),
+            self.keyNotation.is_constant(),
             self.when.is_constant(),
+            self.focus.is_constant(),
             self.mode.is_constant(),
             self.priority.is_constant(),
             self.default.is_constant(),
@@ -362,10 +432,18 @@ impl Expanding for BindingInput {
                 errors.append(&mut e.errors);
                 Spanned::new(UNKNOWN_RANGE, Required::DefaultValue)
             }),
+            keyNotation: self.keyNotation.map_expressions(f).unwrap_or_else(|mut e| {
+                errors.append(&mut e.errors);
+                None
+            }),
             when: self.when.map_expressions(f).unwrap_or_else(|mut e| {
                 errors.append(&mut e.errors);
                 None
             }),
+            focus: self.focus.map_expressions(f).unwrap_or_else(|mut e| {
+                errors.append(&mut e.errors);
+                None
+            }),
             mode: self.mode.map_expressions(f).unwrap_or_else(|mut e| {
                 errors.append(&mut e.errors);
                 None
@@ -805,11 +883,16 @@ pub struct Binding {
     // the `section` of a binding is computed using `assign_binding_headings` in `docs.rs`
     // this value is initially empty
     pub section: Option<BindSection>,
+    // the canonical form of each key press (see `canonical_press`), used for duplicate
+    // detection and `keybindings.json`
     pub key: Vec<String>,
+    // the key presses as written in the file, shown in the documentation
+    pub(crate) original_key: Vec<String>,
     pub(crate) commands: Vec<Command>,
     pub when: Option<String>,
     pub mode: Vec<String>,
-    pub priority: f64,
+    // unset priorities fall back to the `priority` of each mode (see `outputs`)
+    pub priority: Option<f64>,
     pub(crate) prefixes: Prefix,
     pub finalKey: bool,
     pub(crate) repeat: TypedValue<i32>,
@@ -827,9 +910,71 @@ pub struct BindSection {
 
 pub const TEXT_FOCUS_CONDITION: &str = "(editorTextFocus && !suggestionWidgetVisible)";
 
+/// Where bindings with key presses that lack a modifier are active (see `bind.focus`)
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Focus {
+    #[default]
+    Editor,
+    Terminal,
+    Notebook,
+    List,
+    Any,
+}
+
+impl Focus {
+    // the context added to the `when` clause of such bindings
+    fn condition(&self) -> Option<&'static str> {
+        return match self {
+            Focus::Editor => Some(TEXT_FOCUS_CONDITION),
+            Focus::Terminal => Some("terminalFocus"),
+            Focus::Notebook => Some("(notebookEditorFocused && !inputFocus)"),
+            Focus::List => Some("(listFocus && !inputFocus)"),
+            Focus::Any => None,
+        };
+    }
+
+    /// Restricts `when` to this focus
+    pub(crate) fn restrict(&self, when: Option<String>) -> Option<String> {
+        return match (self.condition(), when) {
+            (Some(c), Some(w)) => Some(format!("({w}) && {c}")),
+            (Some(c), Option::None) => Some(c.to_string()),
+            (Option::None, w) => w,
+        };
+    }
+}
+
+// see `util.rs`
+impl Merging for Focus {
+    fn merge(self, new: Self) -> Self {
+        return new;
+    }
+    fn coalesce(self, new: Self) -> Self {
+        return new;
+    }
+}
+
+// see `value.rs`
+impl Expanding for Focus {
+    fn is_constant(&self) -> bool {
+        return true;
+    }
+    fn map_expressions<F>(self, _f: &mut F) -> ResultVec<Self>
+    where
+        F: FnMut(Expression) -> Result<Value>,
+    {
+        return Ok(self);
+    }
+}
+
+// commands that capture keys typed by the user, in the capture mode named by `args.mode`
+const CAPTURE_COMMANDS: [&str; 2] = ["master-key.captureKeys", "master-key.search"];
+
 lazy_static! {
     static ref WHITESPACE: Regex = Regex::new(r"\s+").unwrap();
-    static ref KEY_WITH_MODIFIER: Regex = Regex::new(r"(?i)Ctrl|Alt|Cmd|Win|Meta").unwrap();
+    // a key press that is not bare (see `Focus`)
+    pub(crate) static ref KEY_WITH_MODIFIER: Regex =
+        Regex::new(r"(?i)Ctrl|Alt|Cmd|Win|Meta").unwrap();
 }
 
 impl Binding {
@@ -934,6 +1079,10 @@ impl Binding {
             panic!("`foreach` included unresolved variables"); // LCOV_EXCL_LINE
         }
 
+        // translate the keybinding from its notation
+        let key_string: String = input.key.clone().resolve("key", scope)?;
+        let key_string = input.translate_key(&key_string, scope)?;
+
         // finalKey validation
         let has_prefix = commands.iter().any(|c| c.command == "master-key.prefix");
         let final_key_result: Option<bool> = resolve!(input, finalKey, scope)?;
@@ -950,11 +1099,9 @@ impl Binding {
             Some(ref mode) => (mode.span().clone(), mode.clone().resolve("mode", scope)?),
             Option::None => (UNKNOWN_RANGE, vec![scope.default_mode.clone()]),
         };
-        // NOTE: capture is an automated mode, it shouldn't usually be used,
-        // but some special keys (e.g. escape) may want to define a command for it
         let undefined_modes: Vec<_> = mode
             .iter()
-            .filter(|x| !scope.modes.contains(x.as_str()) && x.as_str() != "capture")
+            .filter(|x| !scope.modes.contains(x.as_str()))
             .collect();
         if undefined_modes.len() > 0 {
             return Err(err!(
@@ -968,34 +1115,63 @@ impl Binding {
             .with_range(&mode_span)?;
         }
 
+        // commands that capture keys must use a capture mode
+        for command in &commands {
+            if CAPTURE_COMMANDS.contains(&command.command.as_str())
+                && let Value::Table(args, _) = &command.args
+                && let Some(Value::String(capture_mode)) = args.get("mode")
+                && !scope.capture_modes.contains(capture_mode)
+            {
+                return Err(err!(
+                    "`{}` expects `mode` to be a mode with `capture = true`, but `{}` is not",
+                    command.command,
+                    capture_mode
+                ))?;
+            }
+        }
+
         // resolve the keybinding
-        let key_string: String = resolve!(input, key, scope)?;
-        let key: Vec<_> = WHITESPACE.split(&key_string).map(String::from).collect();
+        let original_key: Vec<_> = WHITESPACE.split(&key_string).map(String::from).collect();
+        let key: Vec<_> = original_key.iter().map(|k| canonical_press(k)).collect();
         let mut when: Option<String> = resolve!(input, when, scope)?;
+        let prefixes = match resolve!(input, prefixes, scope)? {
+            Prefix::AnyOf(x) => Prefix::AnyOf(x.iter().map(|k| canonical_sequence(k)).collect()),
+            Prefix::AllBut(x) => Prefix::AllBut(x.iter().map(|k| canonical_sequence(k)).collect()),
+            other @ Prefix::Any(_) => other,
+        };
 
-        // require that bare keybindings (those without a modifier key) be specific to
-        // `textEditorFocus` by adding text focus if the binding doesn't have a modifier, we
-        // cannot expect reliable behavior outside of a editor window for such binding
-        let has_modifier = KEY_WITH_MODIFIER.is_match(&key[0]);
-        when = if !has_modifier {
-            if let Some(w) = when {
-                Some(format!("({}) && {TEXT_FOCUS_CONDITION}", w))
-            } else {
-                Some(TEXT_FOCUS_CONDITION.to_string())
-            }
-        } else {
-            when
+        // require that bare keybindings (those with a key press that lacks a modifier key)
+        // be specific to a focus (by default `editorTextFocus`); we cannot expect reliable
+        // behavior outside of such a context for these bindings. Every press of the
+        // sequence, including those of explicit prefixes, is checked, so that `ctrl+k s`
+        // and `s` with prefix `ctrl+k` are treated the same
+        let explicit_prefixes = match &prefixes {
+            Prefix::AnyOf(x) => x.as_slice(),
+            _ => &[],
         };
+        let is_bare = key
+            .iter()
+            .map(String::as_str)
+            .chain(explicit_prefixes.iter().flat_map(|p| p.split_whitespace()))
+            .any(|press| !KEY_WITH_MODIFIER.is_match(press));
+        if is_bare {
+            let focus = match &input.focus {
+                Some(x) => *x.as_ref(),
+                Option::None => scope.focus,
+            };
+            when = focus.restrict(when);
+        }
 
         // resolve all keys to appropriate types
         let result = Binding {
             section: None,
             commands: commands,
             key,
+            original_key,
             when,
             mode,
             priority: resolve!(input, priority, scope)?,
-            prefixes: resolve!(input, prefixes, scope)?,
+            prefixes,
             finalKey,
             implicit: false,
             repeat: resolve!(input, repeat, scope)?,
@@ -1124,6 +1300,19 @@ impl Binding {
             return Ok(binds);
         }
     }
+
+    // rewrite the key presses of `key` and of the prefixes listed in `prefixes`; `f` maps
+    // a sequence of key presses (see `[[remap]]` and `header.layout`)
+    pub(crate) fn map_keys(&mut self, f: impl Fn(&str) -> String) {
+        self.key = self.key.iter().map(|k| f(k)).collect();
+        self.original_key = self.original_key.iter().map(|k| f(k)).collect();
+        let map_all = |x: &Vec<String>| x.iter().map(|k| f(k)).collect();
+        self.prefixes = match &self.prefixes {
+            Prefix::AnyOf(x) => Prefix::AnyOf(map_all(x)),
+            Prefix::AllBut(x) => Prefix::AllBut(map_all(x)),
+            other @ Prefix::Any(_) => other.clone(),
+        };
+    }
 }
 //
 // ---------------- `bind.doc` object ----------------
@@ -1353,6 +1542,11 @@ pub struct PrefixArgs {
     pub(crate) mode: String,
     // a hash used to identify the file used to define the current binding set
     pub(crate) binding_hash: String,
+    // documentation of the prefix (see `[[prefix]]`)
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub(crate) name: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub(crate) description: String,
     // NOTE: there are other arguments to `master-key.prefix` but they are not used by
     // automatically generated bindings, which is what this type is for
 }
@@ -1431,6 +1625,18 @@ impl BindingCodes {
         return self.prefixes[&key];
     }
 
+    // all prefix codes for `mode`, in the order they were created
+    pub(crate) fn prefix_codes(&self, mode: &str) -> Vec<i32> {
+        let mut result: Vec<_> = self
+            .prefixes
+            .iter()
+            .filter(|(id, _)| id.mode == mode)
+            .map(|(_, code)| *code)
+            .collect();
+        result.sort();
+        return result;
+    }
+
     // the key code is used to differentiate unique pairings of key
     // press, mode and when clause
     pub(crate) fn key_code(
@@ -1595,12 +1801,15 @@ impl Binding {
     /// Generates the `BindingOutput` items that will be stored in `keybindings.json`
     ///
     /// For each `Binding` item there are actually many implied `keybinding.json` entries.
-    /// We have to define duplicates for each `mode`, and each `prefix` element.
+    /// We have to define duplicates for each `mode`, and each `prefix` element. The
+    /// defaults of each mode (`when`, `priority` and `doc.kind`) only apply to the entries
+    /// for that mode, so a binding in several modes merges with each mode independently.
     pub(crate) fn outputs(
         &self,
         command_id: i32,
         binding_hash: &[u8; 32],
         scope: &Scope,
+        modes: &Modes,
         is_source: bool,
         span: Option<Range<usize>>,
         codes: &mut BindingCodes,
@@ -1610,9 +1819,9 @@ impl Binding {
         let binding_hash_str = hex::encode(binding_hash);
 
         // create a distinct binding for each mode...
-        let mut modes = self.mode.clone();
-        modes.sort();
-        for mode in modes {
+        let mut binding_modes = self.mode.clone();
+        binding_modes.sort();
+        for mode in binding_modes {
             let mut when_with_mode = match &self.when {
                 Some(when) => vec![when.clone()],
                 Option::None => vec![],
@@ -1622,6 +1831,13 @@ impl Binding {
             } else {
                 when_with_mode.push(format!("!master-key.mode || master-key.mode == '{mode}'"))
             }
+            let defaults = modes.map.get(&mode);
+            if let Some(Mode {
+                when: Some(when), ..
+            }) = defaults
+            {
+                when_with_mode.push(when.clone());
+            }
             let prefixes = match &self.prefixes {
                 Prefix::AnyOf(x) => x,
                 x @ _ => panic!("Unexpected, unresolved prefix: {x:?}"),
@@ -1634,6 +1850,7 @@ impl Binding {
                     is_source,
                     &span,
                     &mode,
+                    defaults,
                     &prefix,
                     &when_with_mode,
                     codes,
@@ -1687,6 +1904,7 @@ impl Binding {
         is_source: bool,
         span: &Option<Range<usize>>,
         mode: &str,
+        defaults: Option<&Mode>,
         explicit_prefix: &str,
         when_with_mode: &Vec<String>,
         codes: &mut BindingCodes,
@@ -1746,6 +1964,8 @@ impl Binding {
                         old_prefix_id: old_prefix_code,
                         mode: mode.to_string(),
                         key: new_prefix_str.clone(),
+                        name: String::new(),
+                        description: String::new(),
                     },
                 });
             }
@@ -1796,12 +2016,16 @@ impl Binding {
                 prefix_id: prefix_code,
                 old_prefix_id: old_prefix_code,
                 mode: mode.to_string(),
-                priority: self.priority,
+                priority: self
+                    .priority
+                    .or(defaults.and_then(|x| x.priority))
+                    .unwrap_or_default(),
                 prefix: old_prefix_str,
                 name: self.doc.name.clone(),
-                kind: match &self.doc.kind {
-                    Some(x) => x.clone(),
-                    Option::None => String::new(),
+                kind: match (&self.doc.kind, defaults.and_then(|x| x.kind.as_ref())) {
+                    (Some(x), _) => x.clone(),
+                    (Option::None, Some(x)) => x.as_ref().clone(),
+                    (Option::None, Option::None) => String::new(),
                 },
                 implicit: self.implicit,
                 description: self.doc.description.clone(),
@@ -2103,7 +2327,7 @@ mod tests {
         let args = result.args.unwrap().into_inner();
         assert_eq!(
             unwrap_table(&args),
-            HashMap::from([
+            IndexMap::from([
                 ("a".into(), Value::String("2".into())),
                 ("b".into(), Value::Integer(3))
             ])
@@ -2186,7 +2410,7 @@ mod tests {
         );
         assert_eq!(
             unwrap_table(&result.args.unwrap().into_inner()),
-            HashMap::from([("to".into(), Value::String("left".into()))])
+            IndexMap::from([("to".into(), Value::String("left".into()))])
         );
 
         let when: Option<String> = resolve!(result, when, &mut scope).unwrap();
@@ -2239,7 +2463,7 @@ mod tests {
 
         assert_eq!(
             unwrap_table(&left.args.unwrap().into_inner()),
-            HashMap::from([("to".into(), Value::String("left".into()))])
+            IndexMap::from([("to".into(), Value::String("left".into()))])
         );
 
         let prefixes: Prefix = resolve!(left, prefixes, &mut scope).unwrap();
@@ -2,8 +2,10 @@
 use log::info;
 
 use core::ops::Range;
+use indexmap::IndexMap;
 use rhai::{CustomType, TypeBuilder};
 use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
 use std::collections::HashMap;
 use toml::Spanned;
 use wasm_bindgen::{JsValue, prelude::wasm_bindgen};
@@ -14,7 +16,7 @@ use crate::{
     error::{ErrorContext, ParseError, Result, ResultVec, err, flatten_errors},
     expression::{
         Scope,
-        value::{Expanding, Expression, TypedValue, Value},
+        value::{Expanding, Expression, TypedValue, Value, toml_to_js},
     },
     file::KeyFileResult,
     resolve,
@@ -139,7 +141,7 @@ impl CommandInput {
 
 impl From<CommandInput> for Value {
     fn from(value: CommandInput) -> Self {
-        let mut entries = HashMap::new();
+        let mut entries = IndexMap::new();
         let command = value.command.into_inner();
         if let Required::Value(command_value) = command {
             entries.insert("command".to_string(), command_value.into());
@@ -187,6 +189,28 @@ impl<'a> CommandInputLike for CommandValue<'a> {
     }
 }
 
+// a CommandInput can itself use `runCommands` (e.g. within `[[mode]] onEnter`)
+impl CommandInputLike for CommandInput {
+    fn command(&self, scope: &mut Scope) -> ResultVec<String> {
+        if let Some(_) = self.id {
+            // id field should only exist when defining commands through `[[define]]`
+            return Err(err("`id` field is reserved"))?;
+        }
+        return Ok(self.command.clone().resolve("command", scope)?);
+    }
+
+    fn args(&self) -> Option<Spanned<Value>> {
+        return self.args.clone();
+    }
+
+    fn skipWhen(&self) -> TypedValue<bool> {
+        return match &self.skipWhen {
+            Some(x) => x.as_ref().clone(),
+            Option::None => TypedValue::Constant(false),
+        };
+    }
+}
+
 // a BindingInput is itself command like, because some bindings have just a single command
 impl CommandInputLike for BindingInput {
     fn command(&self, scope: &mut Scope) -> ResultVec<String> {
@@ -210,7 +234,7 @@ impl CommandInputLike for Command {
     }
 }
 
-fn maybe_span(spans: Option<HashMap<String, Range<usize>>>, key: &str) -> Option<Range<usize>> {
+fn maybe_span(spans: Option<IndexMap<String, Range<usize>>>, key: &str) -> Option<Range<usize>> {
     if let Some(spans) = spans {
         if let Some(span) = spans.get(key) {
             return Some(span.clone());
@@ -232,7 +256,7 @@ pub(crate) fn regularize_commands(
         let commands = vec![Command {
             command,
             args: match input.args() {
-                None => Value::Table(HashMap::new(), None),
+                None => Value::Table(IndexMap::new(), None),
                 Some(spanned) => spanned.as_ref().clone(),
             },
             skipWhen: input.skipWhen(),
@@ -277,7 +301,7 @@ pub(crate) fn regularize_commands(
             let (command, args, skipWhen) = match command {
                 Value::String(str) => (
                     str.to_owned(),
-                    Value::Table(HashMap::new(), None),
+                    Value::Table(IndexMap::new(), None),
                     TypedValue::default(),
                 ),
                 Value::Table(kv, spans) => {
@@ -333,7 +357,7 @@ pub(crate) fn regularize_commands(
                         continue;
                     } else {
                         let result = match kv.get("args") {
-                            Option::None => &Value::Table(HashMap::new(), None),
+                            Option::None => &Value::Table(IndexMap::new(), None),
                             Some(x) => x,
                         };
                         let args = match result {
@@ -359,7 +383,7 @@ pub(crate) fn regularize_commands(
                 x @ Value::Exp(_) => (
                     "runCommands".to_string(),
                     Value::Table(
-                        HashMap::from([("commands".to_string(), Value::Array(vec![x]))]),
+                        IndexMap::from([("commands".to_string(), Value::Array(vec![x]))]),
                         None,
                     ),
                     TypedValue::Constant(false),
@@ -408,7 +432,7 @@ impl Expanding for Command {
             command: self.command,
             args: self.args.map_expressions(f).unwrap_or_else(|mut e| {
                 errors.append(&mut e.errors);
-                Value::Table(HashMap::new(), None)
+                Value::Table(IndexMap::new(), None)
             }),
             skipWhen: self.skipWhen.map_expressions(f).unwrap_or_else(|mut e| {
                 errors.append(&mut e.errors);
@@ -484,7 +508,7 @@ impl Command {
             });
         }
         let command = expanded.command.clone();
-        let args = expanded.args()?;
+        let args = expanded.args(&scope.messages)?;
         return Ok(CommandOutput {
             errors: None,
             messages: Some(scope.report_messages()),
@@ -493,10 +517,9 @@ impl Command {
         });
     }
 
-    pub(crate) fn args(&self) -> ResultVec<JsValue> {
-        let to_json = serde_wasm_bindgen::Serializer::json_compatible();
+    pub(crate) fn args(&self, messages: &RefCell<Vec<String>>) -> ResultVec<JsValue> {
         let toml: toml::Value = self.args.clone().into();
-        return match toml.serialize(&to_json) {
+        return match toml_to_js(&toml, messages) {
             Err(e) => Err(err!("object failed to serialize: {e}"))?,
             Ok(x) => Ok(x),
         };
@@ -511,7 +534,7 @@ impl Command {
             command: resolve!(input, command, scope)?,
             args: match input.args {
                 Some(x) => x.into_inner(),
-                Option::None => Value::Table(HashMap::new(), None),
+                Option::None => Value::Table(IndexMap::new(), None),
             },
             skipWhen: resolve!(input, skipWhen, scope)?,
         });
@@ -575,10 +598,10 @@ mod tests {
         assert_eq!(commands[1].command, "b");
         assert_eq!(commands[2].command, "c");
 
-        assert_eq!(commands[0].args, Value::Table(HashMap::new(), None));
+        assert_eq!(commands[0].args, Value::Table(IndexMap::new(), None));
         assert_eq!(
             unwrap_table(&commands[1].args),
-            HashMap::from([
+            IndexMap::from([
                 ("foo".to_string(), Value::Integer(1)),
                 ("bar".to_string(), Value::Integer(2)),
             ])
@@ -670,7 +693,7 @@ mod tests {
         let _data = Command {
             command: "selection-utilities.insertAround".to_string(),
             args: Value::Table(
-                HashMap::from([
+                IndexMap::from([
                     (
                         "after".to_string(),
                         Value::Exp(Expression {
@@ -52,200 +52,7 @@ use toml::Spanned;
 use crate::error::{ResultVec, flatten_errors};
 use crate::expression::Scope;
 use crate::expression::value::{Expanding, Value};
-
-const ALL_KEYS: [&'static str; 191] = [
-    "f0",
-    "f1",
-    "f2",
-    "f3",
-    "f4",
-    "f5",
-    "f6",
-    "f7",
-    "f8",
-    "f9",
-    "f10",
-    "f11",
-    "f12",
-    "0",
-    "1",
-    "2",
-    "3",
-    "4",
-    "5",
-    "6",
-    "7",
-    "8",
-    "9",
-    "a",
-    "b",
-    "c",
-    "d",
-    "e",
-    "f",
-    "g",
-    "h",
-    "i",
-    "j",
-    "k",
-    "l",
-    "m",
-    "n",
-    "o",
-    "p",
-    "q",
-    "r",
-    "s",
-    "t",
-    "u",
-    "v",
-    "w",
-    "x",
-    "y",
-    "z",
-    "`",
-    "-",
-    "=",
-    "[",
-    "]",
-    "\\",
-    ";",
-    ",",
-    ".",
-    "/",
-    "left",
-    "up",
-    "right",
-    "down",
-    "pageup",
-    "pagedown",
-    "end",
-    "home",
-    "tab",
-    "enter",
-    "escape",
-    "space",
-    "backspace",
-    "delete",
-    "pausebreak",
-    "capslock",
-    "insert",
-    "numpad0",
-    "numpad1",
-    "numpad2",
-    "numpad3",
-    "numpad4",
-    "numpad5",
-    "numpad6",
-    "numpad7",
-    "numpad8",
-    "numpad9",
-    "numpad_multiply",
-    "numpad_add",
-    "numpad_separator",
-    "numpad_subtract",
-    "numpad_decimal",
-    "numpad_divide",
-    "[F1]",
-    "[F2]",
-    "[F3]",
-    "[F4]",
-    "[F5]",
-    "[F6]",
-    "[F7]",
-    "[F8]",
-    "[F9]",
-    "[F10]",
-    "[F11]",
-    "[F12]",
-    "[F13]",
-    "[F14]",
-    "[F15]",
-    "[F16]",
-    "[F17]",
-    "[F18]",
-    "[F19]",
-    "[KeyA]",
-    "[KeyB]",
-    "[KeyC]",
-    "[KeyD]",
-    "[KeyE]",
-    "[KeyF]",
-    "[KeyG]",
-    "[KeyH]",
-    "[KeyI]",
-    "[KeyJ]",
-    "[KeyK]",
-    "[KeyL]",
-    "[KeyM]",
-    "[KeyN]",
-    "[KeyO]",
-    "[KeyP]",
-    "[KeyQ]",
-    "[KeyR]",
-    "[KeyS]",
-    "[KeyT]",
-    "[KeyU]",
-    "[KeyV]",
-    "[KeyW]",
-    "[KeyX]",
-    "[KeyY]",
-    "[KeyZ]",
-    "[Digit0]",
-    "[Digit1]",
-    "[Digit2]",
-    "[Digit3]",
-    "[Digit4]",
-    "[Digit5]",
-    "[Digit6]",
-    "[Digit7]",
-    "[Digit8]",
-    "[Digit9]",
-    "[Backquote]",
-    "[Minus]",
-    "[Equal]",
-    "[BracketLeft]",
-    "[BracketRight]",
-    "[Backslash]",
-    "[Semicolon]",
-    "[Quote]",
-    "[Comma]",
-    "[Period]",
-    "[Slash]",
-    "[ArrowLeft]",
-    "[ArrowUp]",
-    "[ArrowRight]",
-    "[ArrowDown]",
-    "[PageUp]",
-    "[PageDown]",
-    "[End]",
-    "[Home]",
-    "[Tab]",
-    "[Enter]",
-    "[Escape]",
-    "[Space]",
-    "[Backspace]",
-    "[Delete]",
-    "[Pause]",
-    "[CapsLock]",
-    "[Insert]",
-    "[Numpad0]",
-    "[Numpad1]",
-    "[Numpad2]",
-    "[Numpad3]",
-    "[Numpad4]",
-    "[Numpad5]",
-    "[Numpad6]",
-    "[Numpad7]",
-    "[Numpad8]",
-    "[Numpad9]",
-    "[NumpadMultiply]",
-    "[NumpadAdd]",
-    "[NumpadComma]",
-    "[NumpadSubtract]",
-    "[NumpadDecimal]",
-    "[NumpadDivide]",
-];
+use crate::key::{all_keys, character_keys};
 
 // this function is access within expressions as `keys([regex])`.
 #[allow(non_snake_case)]
@@ -259,8 +66,8 @@ pub fn expression_fn__keys(
         Ok(x) => x,
     };
     let mut result = rhai::Array::new();
-    for key in ALL_KEYS {
-        if key_regex.find(key).is_some_and(|m| m.len() == key.len()) {
+    for key in all_keys() {
+        if key_regex.find(&key).is_some_and(|m| m.len() == key.len()) {
             result.push(ImmutableString::from(key).into())
         }
     }
@@ -268,13 +75,7 @@ pub fn expression_fn__keys(
 }
 
 pub fn all_characters() -> Vec<String> {
-    let mut result = Vec::new();
-    let character = Regex::new(r"^([a-z0-9]|[`\-=\[\];',\./\\]|tab|space|enter)$").unwrap();
-    for key in ALL_KEYS {
-        if character.is_match(key) {
-            result.push(key.to_string());
-        }
-    }
+    let mut result: Vec<_> = character_keys().map(String::from).collect();
     let mut capitals = Vec::new();
     for key in &result {
         capitals.push(format!("shift+{key}"))
@@ -0,0 +1,266 @@
+#[allow(unused_imports)]
+use log::info;
+
+use indexmap::IndexMap;
+use serde::de::{DeserializeSeed, IntoDeserializer, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use std::ops::Range;
+use toml::Spanned;
+
+use crate::bind::validation::KeyBinding;
+use crate::bind::{BindingDocInput, BindingInput};
+use crate::err;
+use crate::error::{ErrorContext, ParseError, Result, ResultVec};
+use crate::util::Required;
+
+/// @bindingField tree
+/// @description a nested table of key sequences, expanded into bindings
+///
+/// Leader key configurations (as in Spacemacs or Doom Emacs) are easiest to read as a tree:
+/// each table of `[tree]` lists the keys that can follow the keys of its parent tables.
+/// A table with a `command` (or any other field of a [`[[bind]]`](/bindings/bind) entry)
+/// is a binding; its key sequence is the path of keys leading to it.
+///
+/// **Example**
+///
+/// ```toml
+/// [tree.space.f]
+/// doc.name = "files"
+/// f = { command = "workbench.action.quickOpen", doc.name = "find" }
+/// s = { command = "workbench.action.files.save", doc.name = "save" }
+///
+/// [tree.space.w]
+/// doc.name = "windows"
+/// v = { command = "workbench.action.splitEditor", doc.name = "split" }
+/// ```
+///
+/// This defines the bindings `space f f`, `space f s` and `space w v`.
+///
+/// ## Fields
+///
+/// A table of `[tree]` has either of:
+///
+/// - keys: each key (e.g. `f` or `"ctrl+x"`) names the table of the bindings that follow
+///   it. The optional `doc` field documents this group of bindings, as a
+///   [`[[prefix]]`](/bindings/prefix) entry does: its `doc.name` shows up in the visual
+///   documentation and the suggestion palette, in every mode of the bindings that follow.
+/// - binding fields: all fields of `[[bind]]` except `key`, which is given by the table's
+///   position in the tree. Keys are written in the binding's
+///   [`keyNotation`](/bindings/bind).
+#[derive(Clone, Debug)]
+pub struct TreeInput {
+    // documentation of a group of keys
+    doc: Option<Box<BindingDocInput>>,
+    // the binding of a leaf of the tree (boxed, since the tree is parsed recursively, and
+    // a `BindingInput` is large)
+    bind: Option<Box<BindingInput>>,
+    // the keys of a group, and the subtree that follows each of them
+    keys: IndexMap<String, Spanned<TreeInput>>,
+}
+
+// a group of keys of the tree with a `doc` (see `PrefixDoc::from_tree`)
+#[derive(Clone, Debug)]
+pub(crate) struct TreeGroup {
+    pub(crate) key: Spanned<String>,
+    pub(crate) doc: BindingDocInput,
+}
+
+// fields of `BindingInput`; a table of the tree with any of these is a binding
+const BINDING_FIELDS: [&str; 16] = [
+    "key",
+    "keyNotation",
+    "command",
+    "args",
+    "when",
+    "focus",
+    "mode",
+    "priority",
+    "default",
+    "foreach",
+    "prefixes",
+    "finalKey",
+    "repeat",
+    "tags",
+    "before",
+    "after",
+];
+
+// a table is parsed by hand, because whether it is a binding or a group of keys is only
+// known once we see one of its fields
+impl<'de> Deserialize<'de> for TreeInput {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct TreeInputVisitor;
+
+        impl<'de> Visitor<'de> for TreeInputVisitor {
+            type Value = TreeInput;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a table of keys or the fields of a binding")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> std::result::Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut doc = None;
+                let mut keys = IndexMap::new();
+                while let Some(name) = map.next_key::<String>()? {
+                    if name == "doc" {
+                        doc = Some(map.next_value()?);
+                    } else if BINDING_FIELDS.contains(&name.as_str()) {
+                        if let Some(key) = keys.keys().next() {
+                            return Err(serde::de::Error::custom(format_args!(
+                                "expected either keys or the fields of a binding, but found \
+                                 both the key `{key}` and the field `{name}`"
+                            )));
+                        }
+                        // this and the remaining fields are those of the binding
+                        let rest = ResumedMap {
+                            name: Some(name),
+                            map,
+                        };
+                        return Ok(TreeInput {
+                            doc: None,
+                            bind: Some(leaf_binding(rest, doc)?),
+                            keys,
+                        });
+                    } else {
+                        keys.insert(name, map.next_value()?);
+                    }
+                }
+                return Ok(TreeInput {
+                    doc,
+                    bind: None,
+                    keys,
+                });
+            }
+        }
+
+        deserializer.deserialize_map(TreeInputVisitor)
+    }
+}
+
+// the binding of a leaf of the tree; kept out of `visit_map` so that the (large) binding is
+// not part of the stack frame of each level of the tree
+fn leaf_binding<'de, A: MapAccess<'de>>(
+    map: A,
+    doc: Option<Box<BindingDocInput>>,
+) -> std::result::Result<Box<BindingInput>, A::Error> {
+    let mut bind = BindingInput::deserialize(serde::de::value::MapAccessDeserializer::new(map))?;
+    if doc.is_some() {
+        bind.doc = doc.map(|x| *x);
+    }
+    return Ok(Box::new(bind));
+}
+
+// the entries of a map, starting with an entry whose name has already been read
+struct ResumedMap<A> {
+    name: Option<String>,
+    map: A,
+}
+
+impl<'de, A: MapAccess<'de>> MapAccess<'de> for ResumedMap<A> {
+    type Error = A::Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> std::result::Result<Option<K::Value>, A::Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        return match self.name.take() {
+            Some(name) => seed.deserialize(name.into_deserializer()).map(Some),
+            Option::None => self.map.next_key_seed(seed),
+        };
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> std::result::Result<V::Value, A::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        return self.map.next_value_seed(seed);
+    }
+}
+
+impl TreeInput {
+    /// The bindings of the tree, each with the key sequence of its path, and the documented
+    /// groups of keys.
+    pub(crate) fn expand(
+        self,
+        span: Range<usize>,
+    ) -> ResultVec<(Vec<Spanned<BindingInput>>, Vec<TreeGroup>)> {
+        if self.bind.is_some() || self.doc.is_some() {
+            return Err(err!("expected `[tree]` to list keys")).with_range(&span)?;
+        }
+        let mut result = Vec::new();
+        let mut groups = Vec::new();
+        let mut errors = Vec::new();
+        for (key, node) in self.keys {
+            let span = node.span();
+            node.into_inner()
+                .expand_helper(vec![key], span, &mut result, &mut groups, &mut errors);
+        }
+        if errors.len() > 0 {
+            return Err(errors.into());
+        }
+        return Ok((result, groups));
+    }
+
+    fn expand_helper(
+        self,
+        path: Vec<String>,
+        span: Range<usize>,
+        result: &mut Vec<Spanned<BindingInput>>,
+        groups: &mut Vec<TreeGroup>,
+        errors: &mut Vec<ParseError>,
+    ) {
+        let key = match KeyBinding::try_from(path.join(" ")).with_range(&span) {
+            Ok(x) => Spanned::new(span.clone(), Required::Value(x)),
+            Err(mut e) => {
+                errors.append(&mut e.errors);
+                return;
+            }
+        };
+
+        if let Some(mut bind) = self.bind {
+            if let Required::Value(_) = bind.key.as_ref() {
+                let error: Result<()> = Err(err!(
+                    "`key` is given by the position of the binding in `[tree]`"
+                ))
+                .with_range(&bind.key.span());
+                errors.push(error.unwrap_err());
+                return;
+            }
+            bind.key = key;
+            result.push(Spanned::new(span, *bind));
+            return;
+        }
+
+        if self.keys.is_empty() {
+            let error: Result<()> = Err(err!(
+                "expected keys or the fields of a binding under `{}`",
+                path.join(" ")
+            ))
+            .with_range(&span);
+            errors.push(error.unwrap_err());
+            return;
+        }
+
+        // a documented group is named like a `[[prefix]]` entry
+        if let Some(doc) = self.doc {
+            groups.push(TreeGroup {
+                key: Spanned::new(span.clone(), path.join(" ")),
+                doc: *doc,
+            });
+        }
+
+        for (key, node) in self.keys {
+            let mut path = path.clone();
+            path.push(key);
+            let span = node.span();
+            node.into_inner()
+                .expand_helper(path, span, result, groups, errors);
+        }
+    }
+}
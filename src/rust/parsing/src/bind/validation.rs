@@ -9,6 +9,7 @@ use crate::err;
 use crate::error::{ErrorSet, Result, ResultVec, err};
 use crate::expression::Scope;
 use crate::expression::value::{EXPRESSION, Expanding, Expression, TypedValue, Value};
+use crate::key::is_valid_key;
 use crate::util::{Merging, Resolving};
 
 //
@@ -19,87 +20,6 @@ use crate::util::{Merging, Resolving};
 
 lazy_static! {
     static ref MODIFIER_REGEX: Regex = Regex::new(r"(?i)Ctrl|Shift|Alt|Cmd|Win|Meta").unwrap();
-    static ref KEY_REGEXS: Vec<Regex> = vec![
-        Regex::new(r"(?i)f[1-9]").unwrap(),
-        Regex::new(r"(?i)f1[0-9]").unwrap(),
-        Regex::new(r"[a-z]").unwrap(),
-        Regex::new(r"[0-9]").unwrap(),
-        Regex::new(r"`").unwrap(),
-        Regex::new(r"-").unwrap(),
-        Regex::new(r"=").unwrap(),
-        Regex::new(r"\[").unwrap(),
-        Regex::new(r"\]").unwrap(),
-        Regex::new(r"\\").unwrap(),
-        Regex::new(r";").unwrap(),
-        Regex::new(r"'").unwrap(),
-        Regex::new(r",").unwrap(),
-        Regex::new(r"\.").unwrap(),
-        Regex::new(r"\/").unwrap(),
-        Regex::new(r"(?i)left").unwrap(),
-        Regex::new(r"(?i)up").unwrap(),
-        Regex::new(r"(?i)right").unwrap(),
-        Regex::new(r"(?i)down").unwrap(),
-        Regex::new(r"(?i)pageup").unwrap(),
-        Regex::new(r"(?i)pagedown").unwrap(),
-        Regex::new(r"(?i)end").unwrap(),
-        Regex::new(r"(?i)home").unwrap(),
-        Regex::new(r"(?i)tab").unwrap(),
-        Regex::new(r"(?i)enter").unwrap(),
-        Regex::new(r"(?i)escape").unwrap(),
-        Regex::new(r"(?i)space").unwrap(),
-        Regex::new(r"(?i)backspace").unwrap(),
-        Regex::new(r"(?i)delete").unwrap(),
-        Regex::new(r"(?i)pausebreak").unwrap(),
-        Regex::new(r"(?i)capslock").unwrap(),
-        Regex::new(r"(?i)insert").unwrap(),
-        Regex::new(r"(?i)numpad[0-9]").unwrap(),
-        Regex::new(r"(?i)numpad_multiply").unwrap(),
-        Regex::new(r"(?i)numpad_add").unwrap(),
-        Regex::new(r"(?i)numpad_separator").unwrap(),
-        Regex::new(r"(?i)numpad_subtract").unwrap(),
-        Regex::new(r"(?i)numpad_decimal").unwrap(),
-        Regex::new(r"(?i)numpad_divide").unwrap(),
-        // layout independent versions
-        Regex::new(r"(?i)\[f[1-9]\]").unwrap(),
-        Regex::new(r"(?i)\[f1[0-9]\]").unwrap(),
-        Regex::new(r"(?i)\[Key[A-Z]\]").unwrap(),
-        Regex::new(r"(?i)\[Digit[0-9]\]").unwrap(),
-        Regex::new(r"(?i)\[Numpad[0-9]\]").unwrap(),
-        Regex::new(r"\[Backquote\]").unwrap(),
-        Regex::new(r"\[Minus\]").unwrap(),
-        Regex::new(r"\[Equal\]").unwrap(),
-        Regex::new(r"\[BracketLeft\]").unwrap(),
-        Regex::new(r"\[BracketRight\]").unwrap(),
-        Regex::new(r"\[Backslash\]").unwrap(),
-        Regex::new(r"\[Semicolon\]").unwrap(),
-        Regex::new(r"\[Quote\]").unwrap(),
-        Regex::new(r"\[Comma\]").unwrap(),
-        Regex::new(r"\[Period\]").unwrap(),
-        Regex::new(r"\[Slash\]").unwrap(),
-        Regex::new(r"\[ArrowLeft\]").unwrap(),
-        Regex::new(r"\[ArrowUp\]").unwrap(),
-        Regex::new(r"\[ArrowRight\]").unwrap(),
-        Regex::new(r"\[ArrowDown\]").unwrap(),
-        Regex::new(r"\[PageUp\]").unwrap(),
-        Regex::new(r"\[PageDown\]").unwrap(),
-        Regex::new(r"\[End\]").unwrap(),
-        Regex::new(r"\[Home\]").unwrap(),
-        Regex::new(r"\[Tab\]").unwrap(),
-        Regex::new(r"\[Enter\]").unwrap(),
-        Regex::new(r"\[Escape\]").unwrap(),
-        Regex::new(r"\[Space\]").unwrap(),
-        Regex::new(r"\[Backspace\]").unwrap(),
-        Regex::new(r"\[Delete\]").unwrap(),
-        Regex::new(r"\[Pause\]").unwrap(),
-        Regex::new(r"\[CapsLock\]").unwrap(),
-        Regex::new(r"\[Insert\]").unwrap(),
-        Regex::new(r"\[NumpadMultiply\]").unwrap(),
-        Regex::new(r"\[NumpadAdd\]").unwrap(),
-        Regex::new(r"\[NumpadComma\]").unwrap(),
-        Regex::new(r"\[NumpadSubtract\]").unwrap(),
-        Regex::new(r"\[NumpadDecimal\]").unwrap(),
-        Regex::new(r"\[NumpadDivide\]").unwrap(),
-    ];
 }
 
 fn is_exact_match(x: &Regex, val: &str) -> bool {
@@ -110,14 +30,13 @@ fn is_exact_match(x: &Regex, val: &str) -> bool {
     }
 }
 
-fn valid_key_binding_str(str: &str) -> Result<()> {
+pub(crate) fn valid_key_binding_str(str: &str) -> Result<()> {
     for press in Regex::new(r"\s+").unwrap().split(str) {
         let mut first = true;
         for part in press.split('+').rev() {
             if first {
                 first = false;
-                // TODO: don't use is_match (use something to check that the full string matches)
-                if !KEY_REGEXS.iter().any(|r| is_exact_match(&r, part)) {
+                if !is_valid_key(part) {
                     return Err(err!("`{part}` is an invalid key"))?;
                 }
             } else {
@@ -142,7 +61,8 @@ impl TryFrom<String> for KeyBinding {
                 toml::Value::String(value).try_into()?,
             )));
         } else {
-            valid_key_binding_str(&value)?;
+            // NOTE: keys are validated by `Binding::new`, once they are translated from
+            // their notation (see `KeyNotation`)
             return Ok(KeyBinding(TypedValue::Constant(value)));
         }
     }
@@ -172,10 +92,7 @@ impl Expanding for KeyBinding {
             KeyBinding(TypedValue::Variable(value)) => match value.map_expressions(f)? {
                 interp @ Value::Interp(_) => KeyBinding(TypedValue::Variable(interp)),
                 exp @ Value::Exp(_) => KeyBinding(TypedValue::Variable(exp)),
-                Value::String(val) => {
-                    valid_key_binding_str(&val)?;
-                    KeyBinding(TypedValue::Constant(val))
-                }
+                Value::String(val) => KeyBinding(TypedValue::Constant(val)),
                 other @ _ => {
                     let mut result = String::new();
                     let toml: toml::Value = other.into();
@@ -212,6 +129,13 @@ impl Merging for KeyBinding {
 }
 
 impl KeyBinding {
+    // the key sequence, unless it is computed by an expression
+    pub(crate) fn constant(&self) -> Option<&str> {
+        return match self {
+            KeyBinding(TypedValue::Constant(x)) => Some(x),
+            KeyBinding(TypedValue::Variable(_)) => None,
+        };
+    }
     pub fn unwrap(self) -> String {
         match self {
             KeyBinding(TypedValue::Constant(x)) => x,
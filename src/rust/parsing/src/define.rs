@@ -6,16 +6,16 @@ use lazy_static::lazy_static;
 use regex::Regex;
 use rhai::Dynamic;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, hash_map};
+use std::collections::{HashMap, HashSet, hash_map};
 use toml::Spanned;
 
 use crate::bind::BindingInput;
 use crate::bind::command::CommandInput;
 use crate::bind::validation::BindingReference;
 use crate::error::{Context, ErrorContext, ParseError, Result, ResultVec, err};
-use crate::expression::Scope;
 use crate::expression::value::{Expanding, Expression, Value};
-use crate::util::{Merging, Resolving};
+use crate::expression::{Scope, namespace_references};
+use crate::util::{Merging, Required, Resolving};
 use crate::{err, wrn};
 
 // DESIGN NOTE: the rust code follows a pattern across several TOML-defined top level
@@ -54,8 +54,11 @@ pub struct DefineInput {
     /// They can also be changed using [`setValue`](/commands/setValue)
     ///
     /// Expressions can occur within the values, and these will be evaluated at [read-time](/expressions/index#read-time-evaluation).
-    /// No `val.` fields are in scope within these expressions: you cannot refer to
-    /// one `define.val` key within the expression of a second `define.val` key.
+    /// These expressions can refer to other `define.val` keys (e.g. <span
+    /// v-pre>`{{val.x + 1}}`</span>), regardless of the order in which the keys are
+    /// defined: each value is evaluated after the values it refers to. It is an error for
+    /// keys to refer to each other in a cycle (e.g. <span v-pre>`a = "{{val.b}}"`</span>
+    /// and <span v-pre>`b = "{{val.a}}"`</span>).
     ///
     /// ### Example
     ///
@@ -207,7 +210,7 @@ pub struct Define {
     pub bind: HashMap<String, BindingInput>,
     #[serde(skip)]
     pub command: HashMap<String, CommandInput>,
-    pub val: HashMap<String, Value>,
+    pub val: IndexMap<String, Value>,
 }
 
 lazy_static! {
@@ -225,11 +228,12 @@ impl Define {
     ) -> ResultVec<Define> {
         let mut resolved_bind = HashMap::<String, BindingInput>::new();
         let mut resolved_command = HashMap::<String, CommandInput>::new();
-        let mut resolved_var = HashMap::<String, Value>::new();
+        let mut resolved_var = IndexMap::<String, Value>::new();
         let mut errors: Vec<ParseError> = Vec::new();
         let source_define = source.map(|x| &x.define);
 
         // handle `[[define.val]]`
+        let mut pending = IndexMap::<String, Spanned<Value>>::new();
         for def_block in input.val.into_iter().flatten() {
             for (val, value) in def_block.into_iter() {
                 let span = value.span().clone();
@@ -242,25 +246,55 @@ impl Define {
                     errors.push(result.unwrap_err());
                     continue;
                 }
-                match value.resolve("`define.val`", scope) {
-                    Ok::<Value, _>(x) => {
-                        match x.require_constant().with_range(&span) {
-                            Ok(()) => {
-                                resolved_var.insert(val, x);
-                            }
-                            Err(_) => {
-                                // if the value wasn't constant at this point it is
-                                // because we failed to evaluate the expression,
-                                // (in a call to `scope.expand`), so we avoid registering
-                                // the error twice
-                                ()
-                            }
-                        };
-                    }
-                    Err(mut e) => {
-                        errors.append(&mut e.errors);
-                    }
+                pending.insert(val, value);
+            }
+        }
+        // values from the source file are available to the expressions of this file
+        let mut val_map = rhai::Map::new();
+        for (val, value) in source_define.iter().flat_map(|def| def.val.iter()) {
+            val_map.insert(val.into(), value.clone().into());
+        }
+        scope.state.set_or_push("val", val_map);
+        let (order, unresolved, mut cycle_errors) = val_evaluation_order(&pending);
+        errors.append(&mut cycle_errors);
+        let mut evaluated = HashMap::<String, Value>::new();
+        // values that failed to evaluate (or depend on one that did, or on a cycle); their
+        // errors have already been reported, so dependents are skipped silently
+        let mut failed: HashSet<String> = unresolved.into_iter().collect();
+        for (val, dependencies) in order {
+            if dependencies.iter().any(|x| failed.contains(x)) {
+                failed.insert(val);
+                continue;
+            }
+            let x = match scope
+                .parse_asts(&pending[&val])
+                .and_then(|_| scope.expand(&pending[&val]))
+            {
+                Ok(x) => x.into_inner(),
+                Err(mut e) => {
+                    errors.append(&mut e.errors);
+                    failed.insert(val);
+                    continue;
                 }
+            };
+            if let Err(mut e) = x.require_constant() {
+                errors.append(&mut e.errors);
+                failed.insert(val);
+                continue;
+            }
+            if let Some(mut map) = scope
+                .state
+                .get_mut("val")
+                .and_then(|v| v.write_lock::<rhai::Map>())
+            {
+                map.insert(val.as_str().into(), x.clone().into());
+            }
+            evaluated.insert(val, x);
+        }
+        // keep the values in the order they were defined
+        for val in pending.keys() {
+            if let Some(x) = evaluated.remove(val) {
+                resolved_var.insert(val.clone(), x);
             }
         }
         if let Some(def) = &source_define {
@@ -332,6 +366,14 @@ impl Define {
                     warnings.push(err.unwrap_err());
                 }
             }
+            // keys computed by expressions are checked once they are expanded
+            if let Required::Value(key) = def.as_ref().key.as_ref()
+                && let Some(key) = key.constant()
+                && let Err(e) = def.as_ref().translate_key(key, scope)
+            {
+                errors.push(e);
+            }
+
             match span {
                 Err(e) => errors.push(e.into()),
                 Ok(x) => match x.resolve("`id`", scope) {
@@ -455,6 +497,81 @@ impl Define {
     }
 }
 
+// each `define.val` entry and the entries it directly depends on
+type ValDependencies = Vec<(String, Vec<String>)>;
+
+// `define.val` entries can refer to one another, so we evaluate them in dependency order,
+// with ties resolved by the order of definition; each value is returned along with the
+// values it directly depends on. The values of a cycle, and those that depend on them, are
+// returned separately, along with an error for each cycle, so that only they go unevaluated.
+fn val_evaluation_order(
+    vals: &IndexMap<String, Spanned<Value>>,
+) -> (ValDependencies, Vec<String>, Vec<ParseError>) {
+    let mut dependencies = IndexMap::<&str, Vec<String>>::new();
+    for (val, value) in vals {
+        let mut references = Vec::new();
+        let _ = value.as_ref().clone().map_expressions(&mut |expr| {
+            references.append(&mut namespace_references(&expr, "val"));
+            return Ok(Value::Exp(expr));
+        });
+        references.retain(|x| vals.contains_key(x));
+        dependencies.insert(val, references);
+    }
+
+    let mut order = Vec::new();
+    let mut done = HashSet::new();
+    while let Some(val) = dependencies
+        .iter()
+        .find(|(val, deps)| !done.contains(*val) && deps.iter().all(|x| done.contains(x.as_str())))
+        .map(|(val, _)| *val)
+    {
+        done.insert(val);
+        order.push(val);
+    }
+
+    // every remaining value depends on another remaining value: follow these dependencies
+    // until they loop back on themselves to find each cycle
+    let mut errors = Vec::new();
+    let mut visited = done.clone();
+    for start in dependencies.keys() {
+        let mut path: Vec<&str> = Vec::new();
+        let mut current: &str = start;
+        while !visited.contains(current) {
+            path.push(current);
+            visited.insert(current);
+            current = dependencies[current]
+                .iter()
+                .find(|x| !done.contains(x.as_str()))
+                .expect("unresolved dependency");
+        }
+        if let Some(i) = path.iter().position(|x| *x == current) {
+            let cycle = &path[i..];
+            let names = cycle
+                .iter()
+                .chain(std::iter::once(&current))
+                .map(|x| format!("`{x}`"))
+                .collect::<Vec<_>>()
+                .join(" → ");
+            let result: Result<()> = Err(err!(
+                "`define.val` entries refer to each other in a cycle: {names}"
+            ))
+            .with_range(&vals[cycle[0]].span());
+            errors.push(result.unwrap_err());
+        }
+    }
+
+    let unresolved = dependencies
+        .keys()
+        .filter(|x| !done.contains(*x))
+        .map(|x| x.to_string())
+        .collect();
+    let order = order
+        .into_iter()
+        .map(|val| (val.to_string(), dependencies[val].clone()))
+        .collect();
+    return (order, unresolved, errors);
+}
+
 mod tests {
     use test_log::test;
 
@@ -464,7 +581,7 @@ mod tests {
     use crate::resolve;
 
     #[allow(dead_code)]
-    fn unwrap_table(x: &Value) -> HashMap<String, Value> {
+    fn unwrap_table(x: &Value) -> IndexMap<String, Value> {
         match x {
             Value::Table(x, _) => x.clone(),
             _ => panic!("Expected a table!"),
@@ -511,7 +628,7 @@ mod tests {
         let args = foo.args.as_ref().unwrap().clone().into_inner();
         assert_eq!(
             unwrap_table(&args),
-            HashMap::from([
+            IndexMap::from([
                 ("k".into(), Value::Integer(1)),
                 ("h".into(), Value::Integer(2))
             ])
@@ -523,7 +640,7 @@ mod tests {
         let commands = foobar.args.as_ref().unwrap().clone().into_inner();
         assert_eq!(
             unwrap_table(&commands),
-            HashMap::from([(
+            IndexMap::from([(
                 "commands".into(),
                 Value::Array(vec![
                     Value::String("foo".into()),
@@ -8,6 +8,7 @@ use std::collections::HashMap;
 use std::sync::LazyLock;
 
 use crate::bind::{BindSection, Binding, BindingDoc, CombinedBindingDoc};
+use crate::prefix::PrefixDoc;
 
 // we use `docs.rs` to parse the comments of a TOML value as a literate document with tables
 // showing the bindings that fall between each section of the markdown document. this is
@@ -50,7 +51,16 @@ impl FileDocTableRow {
     }
     fn new(x: &Binding) -> Self {
         Self {
-            key: x.key.clone(),
+            key: x.original_key.clone(),
+            mode: x.mode.join(", "),
+            doc: x.doc.clone(),
+            combine_count: 1,
+        }
+    }
+    // a `[[prefix]]` entry is listed like the bindings that start with it
+    fn from_prefix(x: &PrefixDoc) -> Self {
+        Self {
+            key: x.original_key.clone(),
             mode: x.mode.join(", "),
             doc: x.doc.clone(),
             combine_count: 1,
@@ -87,23 +97,25 @@ impl FileDocTableRow {
         self.combine_count += other.combine_count;
     }
 
-    // the actual markdown representation of this keybinding table row
-    fn as_markdown_row(&self, show_mode: bool) -> String {
+    // the actual markdown representation of this keybinding table row; `display` renders
+    // each key press (see `KeyPlatform`)
+    fn as_markdown_row(&self, show_mode: bool, display: &impl Fn(&str) -> String) -> String {
         let newlines = regex::Regex::new(r"[\n\r]+").unwrap();
+        let keys: Vec<_> = self.key.iter().map(|k| display(k)).collect();
         let key = if let Some(combined) = &self.doc.combined
             && !combined.key.is_empty()
             && self.combine_count > 1
         {
-            if self.key.len() > 1 {
-                let mut key = self.key[0..(self.key.len() - 1)].join("</key-bind> <key-bind>");
+            if keys.len() > 1 {
+                let mut key = keys[0..(keys.len() - 1)].join("</key-bind> <key-bind>");
                 key.push_str("</key-bind> <key-bind>");
-                key.push_str(combined.key.as_str());
+                key.push_str(&display(&combined.key));
                 key
             } else {
-                combined.key.clone()
+                display(&combined.key)
             }
         } else {
-            self.key.join("</key-bind> <key-bind>")
+            keys.join("</key-bind> <key-bind>")
         };
 
         let name = if let Some(combined) = &self.doc.combined
@@ -197,13 +209,21 @@ impl FileDocSection {
         };
     }
 
-    // this is called during file parsing, and uses two data sources: (1) the TOML-parsed
-    // `[[bind]]` items---along with their byte offsets---and (2) the literare file
-    // documentation lines (created using the `read` method above). These are sorted based
-    // on their byte offsets to create the final markdown output
+    // rewrite the keys shown for each binding (see `Binding::map_keys`)
+    pub(crate) fn map_keys(&mut self, f: impl Fn(&str) -> String) {
+        for row in self.bindings.values_mut() {
+            row.key = row.key.iter().map(|k| f(k)).collect();
+        }
+    }
+
+    // this is called during file parsing, and uses three data sources: (1) the TOML-parsed
+    // `[[bind]]` items---along with their byte offsets---(2) the `[[prefix]]` items and (3)
+    // the literare file documentation lines (created using the `read` method above). These
+    // are sorted based on their byte offsets to create the final markdown output
     pub(crate) fn assemble(
         bind: &Vec<Binding>,
         bind_span: &Vec<Range<usize>>,
+        prefixes: &[PrefixDoc],
         docs: Vec<FileDocLine>,
     ) -> Vec<FileDocSection> {
         let mut elements: Vec<_> = bind
@@ -211,6 +231,15 @@ impl FileDocSection {
             .zip(bind_span.iter())
             .enumerate()
             .map(|(i, (b, s))| FileDocElement::Bind(FileDocTableRow::new(&b), s.start, i))
+            .chain(prefixes.iter().filter_map(|p| {
+                // prefixes are not bindings, so they don't move the section's `index`
+                let offset = p.span.as_ref()?.start;
+                Some(FileDocElement::Bind(
+                    FileDocTableRow::from_prefix(p),
+                    offset,
+                    bind.len(),
+                ))
+            }))
             .chain(docs.into_iter().map(|d| FileDocElement::Doc(d)))
             .collect();
 
@@ -303,7 +332,11 @@ impl FileDocSection {
         }
     }
 
-    pub(crate) fn write_markdown(docs: &Vec<FileDocSection>, show_mode: bool) -> String {
+    pub(crate) fn write_markdown(
+        docs: &Vec<FileDocSection>,
+        show_mode: bool,
+        display: &impl Fn(&str) -> String,
+    ) -> String {
         let mut result = String::new();
         for section in docs {
             result.push_str(section.doc.as_str());
@@ -320,7 +353,7 @@ impl FileDocSection {
             }
             for key in &section.order {
                 let bind = &section.bindings[key.as_str()];
-                let raw = bind.as_markdown_row(show_mode);
+                let raw = bind.as_markdown_row(show_mode, display);
                 // escape special characters in markdown
                 let re = Regex::new(r"(\\|\[|\]|`|\*|_|\(|\)|\#|!)").unwrap();
                 result.push_str(re.replace_all(raw.as_str(), "\\$1").to_string().as_str());
@@ -1,25 +1,30 @@
 // TODO: here is where we would want to invoke rhai to resolve any outstanding expressions
 
+pub mod profile;
 pub mod value;
 
 #[allow(unused_imports)]
 use log::info;
 
+use instant::Instant;
 use log::error;
 use rhai::{Dynamic, Engine, EvalAltResult, ImmutableString};
-use serde::Serialize;
 use std::cell::RefCell;
 use std::collections::VecDeque;
 use std::collections::{HashMap, HashSet};
+use std::ops::Range;
 use std::rc::Rc;
 use wasm_bindgen::JsValue;
+use wasm_bindgen::prelude::*;
 
 use crate::{
     bind::command::CommandOutput,
-    bind::{BindingDoc, CombinedBindingDoc, ReifiedBinding, foreach::expression_fn__keys},
+    bind::{BindingDoc, CombinedBindingDoc, Focus, ReifiedBinding, foreach::expression_fn__keys},
     err,
-    error::{ErrorContext, RawError, Result, ResultVec},
-    expression::value::{Expanding, Value},
+    error::{CharRange, ErrorContext, RawError, Result, ResultVec},
+    expression::profile::Profile,
+    expression::value::{Expanding, Expression, Value, toml_to_js},
+    key::notation::KeyNotation,
     note,
 };
 
@@ -70,6 +75,13 @@ use crate::{
 ///   3. You cannot use loops
 ///   4. You cannot define named functions
 ///
+/// Integers are 64-bit values and [TOML
+/// datetimes](https://toml.io/en/v1.0.0#offset-date-time) remain datetimes within an
+/// expression: they can be compared with one another and converted to a string using
+/// `to_string`. When passed to a command (e.g. in `args`) datetimes become strings, as do
+/// integers larger in magnitude than 2^53 - 1, which javascript numbers cannot represent
+/// exactly; a warning is shown in the Master Key output pane when this happens.
+///
 /// If you find yourself wanting to write more than a few relatively simple lines, your goal
 /// is probably better accomplished by writing an
 /// [extension](https://code.visualstudio.com/api) and running the extension-defined
@@ -90,15 +102,15 @@ use crate::{
 /// - `keys([regex]):` returns all valid keys (as per `key` in `keybindings.json`) matching
 ///   the regular expression
 /// - `all_modes()`: returns an array of strings of all keybinding modes defined by the
-///   current keybinding set. It does not include the automatically defined mode
-///   `"capture"`. It is rarely advised to define bindings for this mode.
-///   The "capture" is used to capture keys typed during
+///   current keybinding set. It does not include [capture modes](/bindings/mode) (such as
+///   the automatically defined mode `"capture"`). It is rarely advised to define bindings
+///   for these modes. They are used to capture keys typed during
 ///   [`master-key.search`](/commands/search)
 ///   and [`master-key.captureKeys`](/commands/captureKeys). Defining bindings for
 ///   a key will prevent it from being captured by these commands.
 /// - `not_modes([exclusions])`: given an array of strings of excluded modes, returns all
 ///   keybinding modes defined by the current keybinding set that are not among these
-///   exclusions. Like `all_modes` the mode "capture" is not included.
+///   exclusions. Like `all_modes` capture modes are not included.
 ///
 /// ## Run-time Evaluation
 ///
@@ -158,14 +170,54 @@ pub struct Scope {
     pub(crate) engine: rhai::Engine,
     // a list of all keybinding modes (used for validation)
     pub(crate) modes: HashSet<String>,
+    // the modes used to capture keys (see `[[mode]] capture`)
+    pub(crate) capture_modes: HashSet<String>,
     // a list of all kinds (used for validation)
     pub(crate) kinds: HashSet<String>,
     // the default mode (used for validation)
     pub(crate) default_mode: String,
+    // the notation of `bind.key` when a binding does not specify one (see
+    // `header.keyNotation`)
+    pub(crate) key_notation: KeyNotation,
+    // where bindings without modifiers are active when a binding does not specify it (see
+    // `header.focus`)
+    pub(crate) focus: Focus,
     // the current set of variable values to use when evaluating expressions
     pub(crate) state: rhai::Scope<'static>,
     // a list of messages generated by Rhai expressions, shows in the output pane in VSCode
     pub(crate) messages: Rc<RefCell<Vec<String>>>,
+    // when `Some`, every expression evaluated by `expand` is recorded here (see
+    // `KeyFileResult::set_expression_tracing`)
+    pub(crate) trace: Option<Vec<ExpressionTrace>>,
+    // time spent compiling and evaluating each expression
+    pub(crate) profile: Profile,
+}
+
+/// `ExpressionTrace` records the evaluation of a single expression so that the extension
+/// can display why an expression produced the value it did
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone)]
+pub struct ExpressionTrace {
+    // the text of the expression (without the surrounding `{{}}`)
+    pub expression: String,
+    // the location of the expression in the keybinding file (filled in by
+    // `KeyFileResult::expression_trace`)
+    pub range: CharRange,
+    // the values of variables referenced by the expression
+    pub variables: Vec<TracedVariable>,
+    // the resulting value, if evaluation succeeded
+    pub result: Option<String>,
+    // the error message, if evaluation failed
+    pub error: Option<String>,
+    #[wasm_bindgen(skip)]
+    pub span: Range<usize>,
+}
+
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TracedVariable {
+    pub name: String,
+    pub value: String,
 }
 
 // toml_to_dynamic converts a TOML value to a rhai::Dynamic value
@@ -179,15 +231,15 @@ fn toml_to_dynamic(x: toml::Value) -> rhai::Dynamic {
         toml::Value::Integer(x) => Dynamic::from(x),
         toml::Value::Boolean(x) => Dynamic::from(x),
         toml::Value::String(x) => Dynamic::from(x),
-        toml::Value::Datetime(x) => Dynamic::from(x.to_string()),
+        toml::Value::Datetime(x) => Dynamic::from(x),
         toml::Value::Array(xs) => {
             let elements: Vec<Dynamic> = xs.into_iter().map(|x| toml_to_dynamic(x)).collect();
             elements.into()
         }
         toml::Value::Table(x) => {
-            let map: HashMap<String, Dynamic> = x
+            let map: rhai::Map = x
                 .into_iter()
-                .map(|(k, v)| (k, toml_to_dynamic(v)))
+                .map(|(k, v)| (k.into(), toml_to_dynamic(v)))
                 .collect();
             map.into()
         }
@@ -215,6 +267,26 @@ impl Scope {
             .build_type::<CommandOutput>()
             .build_type::<ReifiedBinding>();
 
+        // TOML datetimes remain typed within expressions
+        engine
+            .register_type_with_name::<toml::value::Datetime>("Datetime")
+            .register_fn("to_string", |x: &mut toml::value::Datetime| x.to_string())
+            .register_fn("to_debug", |x: &mut toml::value::Datetime| x.to_string())
+            .register_fn(
+                "==",
+                |x: toml::value::Datetime, y: toml::value::Datetime| x == y,
+            )
+            .register_fn(
+                "!=",
+                |x: toml::value::Datetime, y: toml::value::Datetime| x != y,
+            )
+            .register_fn("<", |x: toml::value::Datetime, y: toml::value::Datetime| {
+                x < y
+            })
+            .register_fn(">", |x: toml::value::Datetime, y: toml::value::Datetime| {
+                x > y
+            });
+
         // messages are displayed in the output are of VSCode
         let messages = Rc::new(RefCell::new(Vec::new()));
         let debug_messages = messages.clone();
@@ -248,8 +320,13 @@ impl Scope {
             messages,
             state: rhai::Scope::new(),
             default_mode: "default".to_string(),
+            key_notation: KeyNotation::default(),
+            focus: Focus::default(),
             modes: HashSet::from(["default".to_string()]),
+            capture_modes: HashSet::from(["capture".to_string()]),
             kinds: HashSet::new(),
+            trace: None,
+            profile: Profile::default(),
         };
 
         // some variables that expressions have access to
@@ -314,12 +391,16 @@ impl Scope {
                     let val: Dynamic = From::<Value>::from(Value::new(v.clone(), None)?);
                     self.state.push_dynamic(k, val);
                 }
-                let dynamic: Dynamic = self
-                    .engine
-                    .eval_ast_with_scope(&mut self.state, ast)
+                let start = Instant::now();
+                let evaluated = self.engine.eval_ast_with_scope(&mut self.state, ast);
+                self.profile.record_evaluate(&expr, start.elapsed());
+                if let Some(trace) = &mut self.trace {
+                    trace.push(trace_entry(&self.state, &expr, &evaluated));
+                }
+                self.state.rewind(rewind_to);
+                let dynamic: Dynamic = evaluated
                     .with_message(format!(" (while evaluating {expr})"))
                     .with_exp_range(&expr.span)?;
-                self.state.rewind(rewind_to);
                 let result_value: std::result::Result<Value, _> = dynamic.clone().try_into();
                 let value = result_value
                     .with_message(format!(" (while evaluating {expr})"))
@@ -335,6 +416,23 @@ impl Scope {
         })?);
     }
 
+    // start (or stop) recording the evaluation of expressions during `expand`
+    pub fn set_tracing(&mut self, enabled: bool) {
+        if enabled {
+            self.trace.get_or_insert_with(Vec::new);
+        } else {
+            self.trace = None;
+        }
+    }
+
+    // access the expressions that have been recorded since the last call to `report_trace`
+    pub fn report_trace(&mut self) -> Vec<ExpressionTrace> {
+        return match &mut self.trace {
+            Some(trace) => std::mem::take(trace),
+            Option::None => Vec::new(),
+        };
+    }
+
     // `parse_asts` (abstract syntax trees) should be run once expandable objects have been
     // defined, so that we can evaluate the expressions using `expand` at some later date
     pub(crate) fn parse_asts(&mut self, x: &(impl Expanding + Clone)) -> ResultVec<()> {
@@ -342,10 +440,10 @@ impl Scope {
             if let Some(e) = expr.error {
                 return Err(e)?;
             }
-            let ast = self
-                .engine
-                .compile_expression(expr.content.clone())
-                .with_exp_range(&expr.span)?;
+            let start = Instant::now();
+            let compiled = self.engine.compile_expression(expr.content.clone());
+            self.profile.record_compile(&expr, start.elapsed());
+            let ast = compiled.with_exp_range(&expr.span)?;
             self.asts.insert(expr.content.clone(), ast);
             return Ok(Value::Exp(expr));
         })?;
@@ -405,8 +503,7 @@ impl Scope {
         if let Some(rhai_value) = self.get_namespace(namespace)?.get(name) {
             let value: Value = rhai_value.clone().try_into()?;
             let toml: toml::Value = value.into();
-            let to_json = serde_wasm_bindgen::Serializer::json_compatible();
-            let js_val = match toml.serialize(&to_json) {
+            let js_val = match toml_to_js(&toml, &self.messages) {
                 Err(e) => Err(err!("While serializing `{name}` {}", e))?,
                 Ok(x) => x,
             };
@@ -416,6 +513,12 @@ impl Scope {
         }
     }
 
+    // the value of `val.[name]`, if it is a string
+    pub(crate) fn defined_string(&self, name: &str) -> Option<String> {
+        let vals = self.get_namespace("val").ok()?;
+        return vals.get(name)?.clone().into_string().ok();
+    }
+
     // lists all values that are under the namespace `val.`
     #[cfg_attr(coverage_nightly, coverage(off))]
     pub fn get_defined_vals(&self) -> Result<Vec<String>> {
@@ -437,6 +540,181 @@ impl Scope {
     // to replay in type script
 }
 
+// a token of an expression, as far as `variable_references` needs to know
+#[derive(Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Punct(char),
+}
+
+// split an expression into identifiers, string literals and punctuation; numbers and
+// comments are dropped and the code interpolated in a backtick string (`${...}`) is
+// tokenized in place of the string
+fn tokenize(content: &str) -> Vec<Token> {
+    let chars: Vec<char> = content.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '/' && next == Some('/') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '/' && next == Some('*') {
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                i += 1;
+            }
+            i += 2;
+        } else if c == '"' || c == '\'' {
+            let mut text = String::new();
+            i += 1;
+            while i < chars.len() && chars[i] != c {
+                if chars[i] == '\\' {
+                    i += 1;
+                }
+                if let Some(x) = chars.get(i) {
+                    text.push(*x);
+                }
+                i += 1;
+            }
+            i += 1;
+            tokens.push(Token::Str(text));
+        } else if c == '`' {
+            i += 1;
+            while i < chars.len() && chars[i] != '`' {
+                if chars[i] == '$' && chars.get(i + 1) == Some(&'{') {
+                    let start = i + 2;
+                    let mut depth = 1;
+                    i = start;
+                    while i < chars.len() {
+                        match chars[i] {
+                            '{' => depth += 1,
+                            '}' => depth -= 1,
+                            _ => (),
+                        }
+                        if depth == 0 {
+                            break;
+                        }
+                        i += 1;
+                    }
+                    let code: String = chars[start..i.min(chars.len())].iter().collect();
+                    tokens.push(Token::Punct('('));
+                    tokens.append(&mut tokenize(&code));
+                    tokens.push(Token::Punct(')'));
+                }
+                i += 1;
+            }
+            i += 1;
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else if c.is_ascii_digit() {
+            while i < chars.len() && (chars[i].is_alphanumeric() || "._".contains(chars[i])) {
+                i += 1;
+            }
+        } else if c == '?' && next == Some('.') {
+            // `val?.x` refers to `x` just as `val.x` does
+            tokens.push(Token::Punct('.'));
+            i += 2;
+        } else {
+            tokens.push(Token::Punct(c));
+            i += 1;
+        }
+    }
+    return tokens;
+}
+
+// the variables an expression refers to, each with the field that follows it, if any (e.g.
+// `x` of `val.x` or `val["x"]`). This is a conservative scan of the expression's text:
+// strings, comments, function calls and the properties or methods of other values are
+// skipped, but a variable may still be listed when it is shadowed (e.g. by a closure's
+// parameter)
+pub(crate) fn variable_references(content: &str) -> Vec<(String, Option<String>)> {
+    let tokens = tokenize(content);
+    let punct = |i: usize, c: char| tokens.get(i) == Some(&Token::Punct(c));
+    let mut result = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        let Token::Ident(name) = token else {
+            continue;
+        };
+        if (i > 0 && punct(i - 1, '.')) || punct(i + 1, '(') {
+            continue;
+        }
+        let field = match (tokens.get(i + 1), tokens.get(i + 2)) {
+            (Some(Token::Punct('.')), Some(Token::Ident(field))) if !punct(i + 3, '(') => {
+                Some(field.clone())
+            }
+            (Some(Token::Punct('[')), Some(Token::Str(field))) if punct(i + 3, ']') => {
+                Some(field.clone())
+            }
+            _ => None,
+        };
+        result.push((name.clone(), field));
+    }
+    return result;
+}
+
+// the fields of `namespace` (e.g. `val`) that an expression refers to, by property
+// (`val.x`) or by constant index (`val["x"]`)
+pub(crate) fn namespace_references(expr: &Expression, namespace: &str) -> Vec<String> {
+    return variable_references(&expr.content)
+        .into_iter()
+        .filter(|(name, _)| name == namespace)
+        .filter_map(|(_, field)| field)
+        .collect();
+}
+
+// summarize an expression's evaluation; must be called before the variables local to
+// the expression (e.g. from `foreach`) are removed from `state`
+fn trace_entry(
+    state: &rhai::Scope,
+    expr: &Expression,
+    evaluated: &std::result::Result<Dynamic, Box<EvalAltResult>>,
+) -> ExpressionTrace {
+    let mut variables = Vec::new();
+    for (name, field) in variable_references(&expr.content) {
+        let Some(value) = state.get(&name) else {
+            continue;
+        };
+        let (name, value) = match (value.read_lock::<rhai::Map>(), field) {
+            (Some(map), Some(field)) => (
+                format!("{name}.{field}"),
+                map.get(field.as_str()).cloned().unwrap_or(Dynamic::UNIT),
+            ),
+            // whole namespaces (e.g. `val`) are too large to be useful here
+            (Some(_), Option::None) => continue,
+            (Option::None, _) => (name.to_string(), value.clone()),
+        };
+        let variable = TracedVariable {
+            name,
+            value: format!("{value:?}"),
+        };
+        if !variables.contains(&variable) {
+            variables.push(variable);
+        }
+    }
+    let (result, error) = match evaluated {
+        Ok(x) => (Some(format!("{x:?}")), None),
+        Err(e) => (None, Some(format!("{e}"))),
+    };
+    return ExpressionTrace {
+        expression: expr.content.clone(),
+        range: CharRange::default(),
+        span: expr.span.clone(),
+        variables,
+        result,
+        error,
+    };
+}
+
 fn define_history_queue_api(engine: &mut Engine, history: HistoryQueue) {
     engine
         .register_type_with_name::<HistoryQueue>("HistoryQueue")
@@ -639,4 +917,16 @@ mod tests {
         let report = err.report(data.as_bytes());
         assert!(!report[0].message.contains("(line"))
     }
+
+    #[test]
+    fn variable_references_skip_strings_and_methods() {
+        let refs = variable_references(
+            r#"val.x + val["y"] + key.len() + "val.z" + foo.val.w + `${val.v}` + f(i) // val.u"#,
+        );
+        let refs: Vec<_> = refs
+            .iter()
+            .map(|(name, field)| format!("{name}.{}", field.as_deref().unwrap_or("")))
+            .collect();
+        assert_eq!(refs, ["val.x", "val.y", "key.", "foo.val", "val.v", "i."]);
+    }
 }
@@ -0,0 +1,191 @@
+// Profiling of expression compilation and evaluation; we track how long each expression
+// takes so that it is possible to find the bindings that make typing feel slow (see
+// `KeyFileResult::expression_profile` and `KeyFileResult::binding_profile`)
+
+use instant::Duration;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use wasm_bindgen::prelude::*;
+
+use crate::error::CharRange;
+use crate::expression::value::Expression;
+
+// accumulated time across repeated calls
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Timing {
+    pub(crate) count: u32,
+    pub(crate) total: Duration,
+    pub(crate) max: Duration,
+}
+
+impl Timing {
+    fn record(&mut self, time: Duration) {
+        self.count += 1;
+        self.total += time;
+        self.max = self.max.max(time);
+    }
+
+    fn add(&mut self, other: &Timing) {
+        self.count += other.count;
+        self.total += other.total;
+        self.max = self.max.max(other.max);
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct ExpressionTimes {
+    compile: Timing,
+    evaluate: Timing,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Profile {
+    // timing of each expression, keyed by its location and content (the same location
+    // can have different content after `foreach` expansion)
+    expressions: HashMap<(Range<usize>, String), ExpressionTimes>,
+    bindings: Vec<BindingEntry>,
+}
+
+// a `[[bind]]` entry whose expressions are timed together
+#[derive(Debug)]
+struct BindingEntry {
+    span: Range<usize>,
+    // the label (the key) used to report the entry
+    key: String,
+    // the spans of the expressions the entry contains
+    expressions: HashSet<Range<usize>>,
+}
+
+fn millis(time: Duration) -> f64 {
+    return time.as_secs_f64() * 1000.0;
+}
+
+impl Profile {
+    fn entry(&mut self, expr: &Expression) -> &mut ExpressionTimes {
+        return self
+            .expressions
+            .entry((expr.span.clone(), expr.content.clone()))
+            .or_default();
+    }
+
+    pub(crate) fn record_compile(&mut self, expr: &Expression, time: Duration) {
+        self.entry(expr).compile.record(time);
+    }
+
+    pub(crate) fn record_evaluate(&mut self, expr: &Expression, time: Duration) {
+        self.entry(expr).evaluate.record(time);
+    }
+
+    pub(crate) fn add_binding(
+        &mut self,
+        span: Range<usize>,
+        label: String,
+        expressions: HashSet<Range<usize>>,
+    ) {
+        if !self.bindings.iter().any(|x| x.span == span) {
+            self.bindings.push(BindingEntry {
+                span,
+                key: label,
+                expressions,
+            });
+        }
+    }
+
+    // all expressions, slowest first
+    pub(crate) fn expressions(&self) -> Vec<ExpressionTiming> {
+        let mut result: Vec<_> = self
+            .expressions
+            .iter()
+            .map(|((span, content), times)| ExpressionTiming {
+                expression: content.clone(),
+                range: None,
+                evaluations: times.evaluate.count,
+                compile_ms: millis(times.compile.total),
+                evaluate_ms: millis(times.evaluate.total),
+                max_evaluate_ms: millis(times.evaluate.max),
+                span: span.clone(),
+            })
+            .collect();
+        result.sort_by(|a, b| b.total_ms().total_cmp(&a.total_ms()));
+        return result;
+    }
+
+    // the time spent on the expressions of each `[[bind]]` entry, slowest first
+    pub(crate) fn bindings(&self) -> Vec<BindingTiming> {
+        let mut result: Vec<_> = self
+            .bindings
+            .iter()
+            .map(|entry| {
+                let mut compile = Timing::default();
+                let mut evaluate = Timing::default();
+                let mut expressions = 0;
+                for ((expr_span, _), times) in &self.expressions {
+                    if entry.expressions.contains(expr_span) {
+                        expressions += 1;
+                        compile.add(&times.compile);
+                        evaluate.add(&times.evaluate);
+                    }
+                }
+                BindingTiming {
+                    key: entry.key.clone(),
+                    range: None,
+                    expressions,
+                    evaluations: evaluate.count,
+                    compile_ms: millis(compile.total),
+                    evaluate_ms: millis(evaluate.total),
+                    span: entry.span.clone(),
+                }
+            })
+            .filter(|x| x.expressions > 0)
+            .collect();
+        result.sort_by(|a, b| {
+            (b.compile_ms + b.evaluate_ms).total_cmp(&(a.compile_ms + a.evaluate_ms))
+        });
+        return result;
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.expressions.clear();
+    }
+}
+
+/// `ExpressionTiming` summarizes the time spent compiling and evaluating a single
+/// expression
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone)]
+pub struct ExpressionTiming {
+    // the text of the expression (without the surrounding `{{}}`)
+    pub expression: String,
+    // the location of the expression in the keybinding file (if known)
+    pub range: Option<CharRange>,
+    pub evaluations: u32,
+    pub compile_ms: f64,
+    pub evaluate_ms: f64,
+    pub max_evaluate_ms: f64,
+    #[wasm_bindgen(skip)]
+    pub span: Range<usize>,
+}
+
+#[wasm_bindgen]
+impl ExpressionTiming {
+    pub fn total_ms(&self) -> f64 {
+        return self.compile_ms + self.evaluate_ms;
+    }
+}
+
+/// `BindingTiming` summarizes the time spent on all expressions within a single `[[bind]]`
+/// entry
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone)]
+pub struct BindingTiming {
+    // the key (or keys) of the first binding generated by the entry
+    pub key: String,
+    // the location of the `[[bind]]` entry in the keybinding file (if known)
+    pub range: Option<CharRange>,
+    pub expressions: u32,
+    pub evaluations: u32,
+    pub compile_ms: f64,
+    pub evaluate_ms: f64,
+    #[wasm_bindgen(skip)]
+    pub span: Range<usize>,
+}
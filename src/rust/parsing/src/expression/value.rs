@@ -9,6 +9,7 @@ use rhai::Dynamic;
 use serde::{Deserialize, Serialize};
 use smallvec::SmallVec;
 use smallvec::smallvec;
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::io::Write;
 use toml::Spanned;
@@ -31,14 +32,15 @@ use crate::util::{LeafValue, Merging, Plural, Required, Resolving};
 
 #[derive(Serialize, Debug, Clone)]
 pub enum Value {
-    Integer(i32),
+    Integer(i64),
     Float(f64),
     String(String),
+    Datetime(toml::value::Datetime),
     Boolean(bool),
     Array(Vec<Value>),
     Table(
-        HashMap<String, Value>,
-        Option<HashMap<String, Range<usize>>>,
+        IndexMap<String, Value>,
+        Option<IndexMap<String, Range<usize>>>,
     ),
     Interp(Vec<Value>),
     Exp(Expression),
@@ -50,6 +52,7 @@ impl PartialEq for Value {
             (Value::Integer(x), Value::Integer(y)) => x == y,
             (Value::Float(x), Value::Float(y)) => x == y,
             (Value::String(x), Value::String(y)) => x == y,
+            (Value::Datetime(x), Value::Datetime(y)) => x == y,
             (Value::Boolean(x), Value::Boolean(y)) => x == y,
             (Value::Array(x), Value::Array(y)) => x == y,
             (Value::Table(x, _), Value::Table(y, _)) => x == y,
@@ -130,13 +133,13 @@ impl<'de> Deserialize<'de> for Value {
 // each value in a `Table`
 #[derive(Serialize, Debug, Clone, PartialEq)]
 pub enum BareValue {
-    Integer(i32),
+    Integer(i64),
     Float(f64),
     String(String),
     Datetime(toml::value::Datetime),
     Boolean(bool),
     Array(Vec<BareValue>),
-    Table(HashMap<String, Spanned<BareValue>>),
+    Table(IndexMap<String, Spanned<BareValue>>),
 }
 
 // Manual implementation of `Deserialize` is required here to capture `Spanned` within
@@ -163,43 +166,25 @@ impl<'de> serde::de::Deserialize<'de> for BareValue {
                 Ok(BareValue::Boolean(value))
             }
 
-            fn visit_i64<E: serde::de::Error>(
-                self,
-                value: i64,
-            ) -> std::result::Result<BareValue, E> {
-                if i32::try_from(value).is_ok() {
-                    Ok(BareValue::Integer(value as i32))
-                } else {
-                    Err(serde::de::Error::custom(
-                        "i64 value was too large (must fit in i32)",
-                    ))
-                }
+            fn visit_i64<E>(self, value: i64) -> std::result::Result<BareValue, E> {
+                Ok(BareValue::Integer(value))
             }
 
             fn visit_u64<E: serde::de::Error>(
                 self,
                 value: u64,
             ) -> std::result::Result<BareValue, E> {
-                if i32::try_from(value).is_ok() {
-                    Ok(BareValue::Integer(value as i32))
+                if let Ok(x) = i64::try_from(value) {
+                    Ok(BareValue::Integer(x))
                 } else {
                     Err(serde::de::Error::custom(
-                        "u64 value was too large (must fit in i32",
+                        "u64 value was too large (must fit in i64)",
                     ))
                 }
             }
 
-            fn visit_u32<E: serde::de::Error>(
-                self,
-                value: u32,
-            ) -> std::result::Result<BareValue, E> {
-                if i32::try_from(value).is_ok() {
-                    Ok(BareValue::Integer(value as i32))
-                } else {
-                    Err(serde::de::Error::custom(
-                        "u32 value was too large (must fit in i32)",
-                    ))
-                }
+            fn visit_u32<E>(self, value: u32) -> std::result::Result<BareValue, E> {
+                Ok(BareValue::Integer(value.into()))
             }
 
             fn visit_i32<E>(self, value: i32) -> std::result::Result<BareValue, E> {
@@ -244,17 +229,17 @@ impl<'de> serde::de::Deserialize<'de> for BareValue {
                     Some(toml_datetime::de::VisitMap::Datetime(datetime)) => {
                         return Ok(BareValue::Datetime(datetime));
                     }
-                    Option::None => return Ok(BareValue::Table(HashMap::new())),
+                    Option::None => return Ok(BareValue::Table(IndexMap::new())),
                     Some(toml_datetime::de::VisitMap::Key(key)) => key,
                 };
-                let mut map = HashMap::new();
+                let mut map = IndexMap::new();
                 map.insert(key.into_owned(), visitor.next_value()?);
                 while let Some(key) = visitor.next_key::<String>()? {
                     match map.entry(key) {
-                        std::collections::hash_map::Entry::Vacant(vacant) => {
+                        indexmap::map::Entry::Vacant(vacant) => {
                             vacant.insert(visitor.next_value()?);
                         }
-                        std::collections::hash_map::Entry::Occupied(occupied) => {
+                        indexmap::map::Entry::Occupied(occupied) => {
                             let msg = format!("duplicate key: `{}`", occupied.key());
                             return Err(serde::de::Error::custom(msg));
                         }
@@ -282,7 +267,7 @@ impl Value {
             BareValue::Boolean(x) => Value::Boolean(x),
             BareValue::Float(x) => Value::Float(x),
             BareValue::Integer(x) => Value::Integer(x),
-            BareValue::Datetime(x) => Value::String(x.to_string()),
+            BareValue::Datetime(x) => Value::Datetime(x),
             BareValue::String(x) => string_to_expression(x, range)?,
             BareValue::Array(toml_values) => {
                 let values = toml_values.into_iter().map(|x| {
@@ -447,15 +432,8 @@ impl BareValue {
         return Ok(match value {
             toml::Value::Boolean(x) => BareValue::Boolean(x),
             toml::Value::Float(x) => BareValue::Float(x),
-            toml::Value::Integer(x) => BareValue::Integer({
-                if i32::try_from(x).is_ok() {
-                    x as i32
-                } else {
-                    Err(err("i64 value was too large (must fit in i32)"))?;
-                    0
-                }
-            }),
-            toml::Value::Datetime(x) => BareValue::String(x.to_string()),
+            toml::Value::Integer(x) => BareValue::Integer(x),
+            toml::Value::Datetime(x) => BareValue::Datetime(x),
             toml::Value::String(x) => BareValue::String(x),
             toml::Value::Array(toml_values) => {
                 let values = flatten_errors(toml_values.into_iter().map(|x| {
@@ -507,6 +485,7 @@ impl From<Value> for BareValue {
             Value::Float(x) => BareValue::Float(x),
             Value::Integer(x) => BareValue::Integer(x),
             Value::String(x) => BareValue::String(x),
+            Value::Datetime(x) => BareValue::Datetime(x),
         };
     }
 }
@@ -528,8 +507,9 @@ impl From<Value> for toml::Value {
             }
             Value::Boolean(x) => toml::Value::Boolean(x),
             Value::Float(x) => toml::Value::Float(x),
-            Value::Integer(x) => toml::Value::Integer(x as i64),
+            Value::Integer(x) => toml::Value::Integer(x),
             Value::String(x) => toml::Value::String(x),
+            Value::Datetime(x) => toml::Value::Datetime(x),
         };
     }
 }
@@ -538,16 +518,17 @@ impl From<Value> for rhai::Dynamic {
     fn from(value: Value) -> Self {
         return match value {
             Value::Float(x) => Dynamic::from(x),
-            Value::Integer(x) => Dynamic::from(x as i64),
+            Value::Integer(x) => Dynamic::from(x),
             Value::Boolean(x) => Dynamic::from(x),
             Value::String(x) => Dynamic::from(x),
+            // datetimes are a custom type within expressions (see `Scope::new`)
+            Value::Datetime(x) => Dynamic::from(x),
             Value::Array(x) => {
                 let elements: Vec<Dynamic> = x.into_iter().map(|x| Dynamic::from(x)).collect();
                 elements.into()
             }
             Value::Table(x, _) => {
-                let map: std::collections::HashMap<String, Dynamic> =
-                    x.into_iter().map(|(k, v)| (k, v.into())).collect();
+                let map: rhai::Map = x.into_iter().map(|(k, v)| (k.into(), v.into())).collect();
                 map.into()
             }
             // the from here results in an opaque custom type
@@ -573,29 +554,31 @@ impl TryFrom<Dynamic> for Value {
                 .collect::<Result<Vec<_>>>()?;
             return Ok(Value::Array(values));
         } else if value.is_map() {
+            // NOTE: rhai maps are ordered by key, so tables computed by an expression will
+            // be in this order
             let pairs = value.as_map_ref().expect("map value");
             let values = pairs
                 .clone()
                 .into_iter()
                 .map(|(k, v)| Ok((k.as_str().to_string(), Value::try_from(v.to_owned())?)))
-                .collect::<Result<HashMap<_, _>>>()?;
+                .collect::<Result<IndexMap<_, _>>>()?;
             return Ok(Value::Table(values, None));
         } else if value.is_bool() {
             return Ok(Value::Boolean(value.as_bool().expect("boolean")));
         } else if value.is_float() {
             return Ok(Value::Float(value.as_float().expect("float")));
         } else if value.is_int() {
-            return Ok(Value::Integer(i32::try_from(
-                value.as_int().expect("integer"),
-            )?));
+            return Ok(Value::Integer(value.as_int().expect("integer")));
         } else if value.is::<i32>() {
-            return Ok(Value::Integer(value.cast::<i32>()));
+            return Ok(Value::Integer(value.cast::<i32>().into()));
         } else if value.is::<usize>() {
             let x: usize = value.cast();
-            if x > i32::MAX as usize {
-                return Err(err!("{x} is to large to be interpreted as an `i32`"))?;
-            }
-            return Ok(Value::Integer(x as i32));
+            return match i64::try_from(x) {
+                Ok(x) => Ok(Value::Integer(x)),
+                Err(_) => Err(err!("{x} is to large to be interpreted as an `i64`"))?,
+            };
+        } else if value.is::<toml::value::Datetime>() {
+            return Ok(Value::Datetime(value.cast()));
         } else if value.is_string() {
             return Ok(Value::String(
                 value
@@ -613,13 +596,75 @@ impl TryFrom<Dynamic> for Value {
     }
 }
 
+//
+// ---------------- Value: Javascript ----------------
+//
+
+// `JsCompatible` serializes a TOML value so that it can be passed to javascript (via
+// `serde_wasm_bindgen`). Two kinds of values lose their type on the way: datetimes are
+// passed as strings, since javascript has no equivalent type for local dates and times,
+// and integers that a javascript number can't represent exactly are passed as strings
+// too, since a `BigInt` would break `JSON.stringify` and numeric argument schemas on the
+// typescript side (`toml_to_js` warns about the latter)
+struct JsCompatible<'a>(&'a toml::Value);
+
+const MAX_SAFE_INTEGER: i64 = (1 << 53) - 1;
+
+fn is_safe_js_integer(x: i64) -> bool {
+    return (-MAX_SAFE_INTEGER..=MAX_SAFE_INTEGER).contains(&x);
+}
+
+impl Serialize for JsCompatible<'_> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        return match self.0 {
+            toml::Value::Integer(x) if is_safe_js_integer(*x) => serializer.serialize_i64(*x),
+            toml::Value::Integer(x) => serializer.collect_str(x),
+            toml::Value::Datetime(x) => serializer.collect_str(x),
+            toml::Value::Array(items) => serializer.collect_seq(items.iter().map(JsCompatible)),
+            toml::Value::Table(kv) => {
+                serializer.collect_map(kv.iter().map(|(k, v)| (k, JsCompatible(v))))
+            }
+            other => other.serialize(serializer),
+        };
+    }
+}
+
+// convert a TOML value to a javascript value (e.g. for command `args`); a warning is
+// added to `messages` when an integer has to be passed as a string
+pub(crate) fn toml_to_js(
+    value: &toml::Value,
+    messages: &RefCell<Vec<String>>,
+) -> std::result::Result<wasm_bindgen::JsValue, serde_wasm_bindgen::Error> {
+    if let Some(x) = unsafe_js_integer(value) {
+        messages.borrow_mut().push(format!(
+            "Warning: `{x}` is too large to be represented exactly by a javascript number; \
+             it is passed to javascript as a string"
+        ));
+    }
+    let to_json = serde_wasm_bindgen::Serializer::json_compatible();
+    return JsCompatible(value).serialize(&to_json);
+}
+
+// the first integer in `value` that `JsCompatible` passes as a string
+fn unsafe_js_integer(value: &toml::Value) -> Option<i64> {
+    return match value {
+        toml::Value::Integer(x) if !is_safe_js_integer(*x) => Some(*x),
+        toml::Value::Array(items) => items.iter().find_map(unsafe_js_integer),
+        toml::Value::Table(kv) => kv.values().find_map(unsafe_js_integer),
+        _ => None,
+    };
+}
+
 //
 // ---------------- Value: Traits ----------------
 //
 
 impl Default for Value {
     fn default() -> Self {
-        return Value::Table(HashMap::new(), None);
+        return Value::Table(IndexMap::new(), None);
     }
 }
 
@@ -747,7 +792,11 @@ impl Expanding for Value {
             Value::Interp(_) => false,
             Value::Array(items) => items.iter().all(|it| it.is_constant()),
             Value::Table(kv, _) => kv.values().all(|it| it.is_constant()),
-            Value::Boolean(_) | Value::Float(_) | Value::Integer(_) | Value::String(_) => true,
+            Value::Boolean(_)
+            | Value::Float(_)
+            | Value::Integer(_)
+            | Value::String(_)
+            | Value::Datetime(_) => true,
         }
     }
     fn map_expressions<F>(self, f: &mut F) -> ResultVec<Self>
@@ -787,7 +836,8 @@ impl Expanding for Value {
             literal @ (Value::Boolean(_)
             | Value::Float(_)
             | Value::Integer(_)
-            | Value::String(_)) => literal,
+            | Value::String(_)
+            | Value::Datetime(_)) => literal,
         });
     }
 }
@@ -1148,12 +1198,105 @@ mod tests {
         let data = r#"
         number = 5_000_000_000
         "#;
-        let err: std::result::Result<Value, _> = toml::from_str(data);
-        assert!(
-            err.unwrap_err()
-                .to_string()
-                .contains("i64 value was too large")
-        );
+        let value: Value = toml::from_str(data).unwrap();
+        let Value::Table(kv, _) = value else {
+            panic!("Expected a table");
+        };
+        assert_eq!(kv["number"], Value::Integer(5_000_000_000));
+    }
+
+    #[test]
+    fn datetime_remains_typed() {
+        let data = r#"
+        joe = 1979-05-27T07:32:00Z
+        "#;
+        let value: Value = toml::from_str(data).unwrap();
+        let Value::Table(kv, _) = value else {
+            panic!("Expected a table");
+        };
+        let expected: toml::value::Datetime = "1979-05-27T07:32:00Z".parse().unwrap();
+        assert_eq!(kv["joe"], Value::Datetime(expected));
+        let toml: toml::Value = kv["joe"].clone().into();
+        assert_eq!(toml, toml::Value::Datetime(expected));
+    }
+
+    #[test]
+    fn rhai_round_trip_is_lossless() {
+        let data = r#"
+        big = 9_007_199_254_740_993
+        small = -9_223_372_036_854_775_808
+        when = 1979-05-27T07:32:00.999999-07:00
+        day = 1979-05-27
+        items = [1, 2.5, "three", 1979-05-27T07:32:00]
+        "#;
+        let value: Value = toml::from_str(data).unwrap();
+        let dynamic: Dynamic = value.clone().into();
+        let round_trip = Value::try_from(dynamic).unwrap();
+        assert_eq!(value, round_trip);
+
+        let toml: toml::Value = round_trip.into();
+        let bare = BareValue::new(toml.clone()).unwrap();
+        let value_again = Value::new(bare, None).unwrap();
+        assert_eq!(value, value_again);
+        assert_eq!(toml, value_again.into());
+    }
+
+    #[test]
+    fn js_conversion_passes_unsafe_integers_as_strings() {
+        let data = r#"
+        safe = 9_007_199_254_740_991
+        big = 9_007_199_254_740_993
+        small = -9_223_372_036_854_775_808
+        when = 1979-05-27
+        items = [1, 9_007_199_254_740_992]
+        "#;
+        let value: toml::Value = toml::from_str(data).unwrap();
+        assert_eq!(unsafe_js_integer(&value), Some(9_007_199_254_740_993));
+
+        let js = toml::Value::try_from(JsCompatible(&value)).unwrap();
+        let expected: toml::Value = toml::from_str(
+            r#"
+            safe = 9_007_199_254_740_991
+            big = "9007199254740993"
+            small = "-9223372036854775808"
+            when = "1979-05-27"
+            items = [1, "9007199254740992"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(js, expected);
+
+        let safe: toml::Value = toml::from_str("a = [1, { b = -9_007_199_254_740_991 }]").unwrap();
+        assert_eq!(unsafe_js_integer(&safe), None);
+    }
+
+    #[test]
+    fn datetime_in_expressions() {
+        let data = r#"
+        a = 1979-05-27
+        b = 1980-01-01
+        "#;
+        let value: Value = toml::from_str(data).unwrap();
+        let mut scope = Scope::new();
+        let val: Dynamic = value.into();
+        scope.state.set_or_push("val", val);
+        let data = r#"
+        earlier = "{{val.a < val.b}}"
+        same = "{{val.a == val.a}}"
+        text = "day: {{val.a}}"
+        copy = "{{val.b}}"
+        big = "{{9_007_199_254_740_993 + 1}}"
+        "#;
+        let value: Value = toml::from_str(data).unwrap();
+        scope.parse_asts(&value).unwrap();
+        let Value::Table(kv, _) = scope.expand(&value).unwrap() else {
+            panic!("Expected a table");
+        };
+        assert_eq!(kv["earlier"], Value::Boolean(true));
+        assert_eq!(kv["same"], Value::Boolean(true));
+        assert_eq!(kv["text"], Value::String("day: 1979-05-27".to_string()));
+        assert_eq!(kv["copy"], Value::Datetime("1980-01-01".parse().unwrap()));
+        assert_eq!(kv["big"], Value::Integer(9_007_199_254_740_994));
     }
 
     #[test]
@@ -162,24 +162,33 @@ use log::{error, info};
 use string_offsets::StringOffsets;
 
 use crate::bind::command::{CommandValue, regularize_commands};
+use crate::bind::tree::TreeInput;
 use crate::bind::{
     BindSection, Binding, BindingCodes, BindingDoc, BindingInput, BindingOutput, BindingOutputArgs,
-    CombinedBindingDoc, KeyId, LegacyBindingInput, ReifiedBinding, UNKNOWN_RANGE,
+    CombinedBindingDoc, Focus, KeyId, LegacyBindingInput, ReifiedBinding, UNKNOWN_RANGE,
 };
 use crate::define::{Define, DefineInput};
 use crate::docs::{FileDocLine, FileDocSection};
 use crate::error::{
     CharRange, Context, ErrorContext, ErrorLevel, ErrorReport, ErrorSet, ParseError, Result,
-    ResultVec, flatten_errors,
+    ResultVec, flatten_errors, range_to_pos,
 };
-use crate::expression::value::{BareValue, Value};
-use crate::expression::{HistoryQueue, MacroStack, Scope};
+use crate::expression::profile::{BindingTiming, ExpressionTiming};
+use crate::expression::value::{BareValue, Expanding, Value};
+use crate::expression::{ExpressionTrace, HistoryQueue, MacroStack, Scope};
+use crate::key::KeyboardLayout;
+use crate::key::display::KeyPlatform;
+use crate::key::notation::KeyNotation;
 use crate::kind::Kind;
-use crate::mode::{Mode, ModeInput, Modes, WhenNoBinding};
+use crate::mode::graph::ModeGraph;
+use crate::mode::{Mode, ModeInput, Modes, PendingReturn, WhenNoBinding};
+use crate::prefix::{PrefixDoc, PrefixDocInput};
+use crate::remap::{Remap, RemapInput};
 use crate::{err, resolve, wrn};
 
 use lazy_static::lazy_static;
 use regex::Regex;
+use rhai::{Dynamic, ImmutableString};
 use semver::{Version, VersionReq};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
@@ -195,6 +204,9 @@ struct KeyFileInput {
     mode: Option<Vec<Spanned<ModeInput>>>,
     bind: Option<Vec<Spanned<BindingInput>>>,
     kind: Option<Vec<Spanned<Kind>>>,
+    remap: Option<Vec<Spanned<RemapInput>>>,
+    tree: Option<Spanned<TreeInput>>,
+    prefix: Option<Vec<Spanned<PrefixDocInput>>>,
     #[serde(flatten)]
     other_fields: HashMap<String, toml::Value>,
 }
@@ -222,12 +234,26 @@ struct KeyFileInput {
 ///
 /// - `name`: The name of this keybinding set; shows up in menus to select keybinding
 ///   presets
-/// - `source`: Append the `kind`, `mode`, `define` and `bind` entries in the current file
-///   to those defined in `source`. The source is the `name` of a Master Key preset. User
-///   defined bindings are not supported as `source` binding files.
+/// - `source`: Append the `kind`, `mode`, `define`, `bind` and `prefix` entries in the
+///   current file to those defined in `source`. The source is the `name` of a Master Key
+///   preset. User defined bindings are not supported as `source` binding files.
 /// - `requiredExtensions`: An array of string identifiers for all extensions used by this
 ///   binding set: identifies can be found using the procedure described below. If you use a
 ///   command from an extension, it is best practice to include that extension here.
+/// - `layout`: The keyboard layout to place the bindings on: one of `"qwerty"` (the
+///   default), `"dvorak"`, `"colemak"`, `"azerty"` or `"qwertz"`. Bindings are written for
+///   the US (QWERTY) layout, and every key of `bind.key` and `bind.prefixes` (including
+///   those of the `source` file) is rewritten to the key at the same physical position on
+///   the given layout: e.g. with `layout = "colemak"`, `key = "k"` becomes `key = "e"`.
+///   Keys the layout labels with a character VSCode cannot bind (e.g. `é` on AZERTY) are
+///   rewritten to their layout independent code (e.g. `[Digit2]`). The documentation
+///   shows the rewritten keys.
+/// - `keyNotation`: The notation `bind.key` is written in: one of `"vscode"` (the
+///   default), `"vim"` or `"emacs"`. A binding can override this with its own
+///   [`keyNotation`](/bindings/bind) field.
+/// - `focus`: Where bindings with a key press that lacks a modifier are active: one of
+///   `"editor"` (the default), `"terminal"`, `"notebook"`, `"list"` or `"any"`. A binding
+///   can override this with its own [`focus`](/bindings/bind) field.
 ///
 /// ## Finding Extension Identifiers
 ///
@@ -244,6 +270,9 @@ struct Header {
     version: Spanned<Version>,
     requiredExtensions: Option<Vec<String>>,
     source: Option<Spanned<String>>,
+    layout: Option<KeyboardLayout>,
+    keyNotation: Option<KeyNotation>,
+    focus: Option<Focus>,
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -256,6 +285,10 @@ pub struct KeyFile {
     pub(crate) mode: Modes,
     pub(crate) bind: Vec<Binding>,
     pub(crate) docs: Vec<FileDocSection>,
+    // documentation of key prefixes (see `[[prefix]]`), including those of the source file
+    pub(crate) prefix: Vec<PrefixDoc>,
+    // the keyboard layout of the bindings (see `header.layout`)
+    pub(crate) layout: KeyboardLayout,
     pub(crate) hash: [u8; 32],
     pub kind: Vec<Kind>,
     // TODO: avoid storing `key_bind` to make serialization smaller
@@ -286,6 +319,9 @@ impl KeyFile {
         }
 
         // [header]
+        let layout = input.header.layout.unwrap_or_default();
+        scope.key_notation = input.header.keyNotation.unwrap_or_default();
+        scope.focus = input.header.focus.unwrap_or_default();
         let version = input.header.version.as_ref();
         if !VersionReq::parse("2.0").unwrap().matches(version) {
             let r: Result<()> = Err(wrn!(
@@ -330,30 +366,13 @@ impl KeyFile {
         };
 
         // [[define]]
-        let mut define_input = input.define.unwrap_or_default();
-        let mut skip_define = false;
-        let _ = scope
-            .parse_asts(&define_input.val)
-            .map_err(|mut es| errors.append(&mut es.errors));
-        match scope.expand(&define_input.val) {
-            Ok(x) => {
-                define_input.val = x;
-            }
+        let define_input = input.define.unwrap_or_default();
+        let mut define = match Define::new(define_input, source, &mut scope, warnings, version) {
             Err(mut es) => {
-                skip_define = true;
                 errors.append(&mut es.errors);
+                Define::default()
             }
-        };
-        let mut define = if !skip_define {
-            match Define::new(define_input, source, &mut scope, warnings, version) {
-                Err(mut es) => {
-                    errors.append(&mut es.errors);
-                    Define::default()
-                }
-                Ok(x) => x,
-            }
-        } else {
-            Define::default()
+            Ok(x) => x,
         };
 
         // [[mode]]
@@ -373,8 +392,38 @@ impl KeyFile {
                     Ok(_) => (),
                 }
             }
+            if let WhenNoBinding::Rules(rules) = &mode.whenNoBinding {
+                for rule in rules {
+                    if let WhenNoBinding::Run(commands) = &rule.action
+                        && let Err(mut es) = scope.parse_asts(commands)
+                    {
+                        errors.append(&mut es.errors);
+                    }
+                }
+            }
+            // NOTE: this is only necessary for modes from a `source` file, since
+            // `Mode::new` has already compiled the expressions of the current file
+            if let Some(text) = &mode.statusText
+                && let Err(mut es) = scope.parse_asts(text)
+            {
+                errors.append(&mut es.errors);
+            }
+            for commands in [&mode.onEnter, &mode.onExit] {
+                if let Err(mut es) = scope.parse_asts(commands) {
+                    errors.append(&mut es.errors);
+                }
+            }
         }
 
+        // [[remap]]
+        let remap = match Remap::new(input.remap, warnings) {
+            Ok(x) => x,
+            Err(mut e) => {
+                errors.append(&mut e.errors);
+                Remap::default()
+            }
+        };
+
         // [[kind]]
         let kind = match Kind::process(&input.kind, source, &mut scope, warnings) {
             Ok(x) => x,
@@ -383,9 +432,36 @@ impl KeyFile {
                 Vec::new()
             }
         };
+        for (_, mode) in &modes.map {
+            if let Some(kind) = &mode.kind
+                && !scope.kinds.contains(kind.as_ref())
+            {
+                errors.push(
+                    Result::<()>::Err(err!("Undefined kind `{}`", kind.as_ref()).into())
+                        .with_range(&kind.span())
+                        .unwrap_err(),
+                );
+            }
+        }
+
+        // [tree]
+        let mut bind_entries = input.bind.unwrap_or_default();
+        let mut tree_groups = Vec::new();
+        if let Some(tree) = input.tree {
+            let span = tree.span();
+            match tree.into_inner().expand(span) {
+                Ok((mut x, groups)) => {
+                    bind_entries.append(&mut x);
+                    tree_groups = groups;
+                }
+                Err(mut e) => errors.append(&mut e.errors),
+            }
+        }
+        // the bindings of `[tree]` are placed where they appear in the file
+        bind_entries.sort_by_key(|x| x.span().start);
 
         // [[bind]]
-        let input_iter = input.bind.into_iter().flatten().map(|x| {
+        let input_iter = bind_entries.into_iter().map(|x| {
             // validate `before/after`
             let span = x.span().clone();
             if !x.as_ref().before.is_none() {
@@ -422,8 +498,32 @@ impl KeyFile {
             .parse_asts(&bind_input)
             .map_err(|mut es| errors.append(&mut es.errors));
 
+        // [[prefix]]
+        let mut prefix = match PrefixDoc::new(input.prefix, scope, warnings) {
+            Ok(x) => x,
+            Err(mut e) => {
+                errors.append(&mut e.errors);
+                Vec::new()
+            }
+        };
+        match PrefixDoc::from_tree(tree_groups, &mut scope) {
+            Ok(mut x) => prefix.append(&mut x),
+            Err(mut e) => errors.append(&mut e.errors),
+        }
+
+        // the expressions of each entry, to attribute their timing to it (see `Profile`)
+        let mut bind_expressions = HashMap::new();
+        for item in &bind_input {
+            let mut spans = HashSet::new();
+            let _ = item.as_ref().clone().map_expressions(&mut |expr| {
+                spans.insert(expr.span.clone());
+                return Ok(Value::Exp(expr));
+            });
+            bind_expressions.insert(item.span(), spans);
+        }
+
         // `foreach` expansion
-        let (mut bind, bind_span): (Vec<_>, Vec<_>) = bind_input
+        let (mut bind, mut bind_span): (Vec<_>, Vec<_>) = bind_input
             .into_iter()
             .flat_map(|x| {
                 let span = x.span().clone();
@@ -466,10 +566,51 @@ impl KeyFile {
                 }
             })
             .unzip();
+        for (binding, span) in bind.iter().zip(bind_span.iter()) {
+            let expressions = bind_expressions.remove(span).unwrap_or_default();
+            scope
+                .profile
+                .add_binding(span.clone(), binding.key.join(" "), expressions);
+        }
 
-        let mut docs = FileDocSection::assemble(&bind, &bind_span, doc_lines);
+        PrefixDoc::tree_modes(&mut prefix, &bind);
+        let mut docs = FileDocSection::assemble(&bind, &bind_span, &prefix, doc_lines);
         FileDocSection::assign_binding_headings(&mut bind, &docs);
 
+        // digit bindings for modes with `count = true`; these are not part of any section
+        // of the documentation, but they show up in the visual documentation
+        let other_bind = source.iter().flat_map(|s| s.bind.iter()).chain(bind.iter());
+        let count_bind = modes.count_bindings(other_bind, scope);
+        for (count_item, span) in count_bind {
+            bind.push(count_item);
+            bind_span.push(span);
+        }
+
+        // rewrite the keys of all bindings, including those of the source file, using
+        // `[[remap]]` and then `header.layout`
+        let mut all_bind: Vec<Binding> = source
+            .iter()
+            .flat_map(|s| s.bind.iter().cloned())
+            .chain(bind.into_iter())
+            .collect();
+        let source_len = all_bind.len() - bind_span.len();
+        let all_spans: Vec<_> = std::iter::repeat_n(None, source_len)
+            .chain(bind_span.iter().cloned().map(Some))
+            .collect();
+        remap.apply(&mut all_bind, &all_spans)?;
+        for bind_item in &mut all_bind {
+            bind_item.map_keys(|k| layout.remap(k));
+        }
+        let rewrite = |k: &str| layout.remap(&remap.sequence(k));
+        for section in &mut docs {
+            section.map_keys(rewrite);
+        }
+        for prefix_item in &mut prefix {
+            prefix_item.map_keys(rewrite);
+        }
+        bind = all_bind.split_off(source_len);
+        let source_bind = all_bind;
+
         // create outputs to store in `keybindings.json`
         // TODO: store spans so we can do avoid serializing `key_bind`?
         let mut key_bind = Vec::new();
@@ -484,12 +625,13 @@ impl KeyFile {
         // add any bindings defined in the source file
         let mut source_offset = 0;
         if let Some(s) = source {
-            source_offset += s.bind.len();
-            for (i, source_bind_item) in s.bind.iter().enumerate() {
+            source_offset += source_bind.len();
+            for (i, source_bind_item) in source_bind.iter().enumerate() {
                 key_bind.append(&mut source_bind_item.outputs(
                     i as i32,
                     &hash,
                     &scope,
+                    &modes,
                     true,
                     Option::None,
                     &mut codes,
@@ -497,14 +639,27 @@ impl KeyFile {
                 )?);
             }
 
-            docs = s.docs.iter().cloned().chain(docs.into_iter()).collect();
+            let source_docs = s.docs.iter().cloned().map(|mut x| {
+                x.map_keys(rewrite);
+                x
+            });
+            docs = source_docs.chain(docs.into_iter()).collect();
+
+            // only this file's `[[prefix]]` entries are checked against its bindings
+            let source_prefix = s.prefix.iter().cloned().map(|mut x| {
+                x.map_keys(rewrite);
+                x.span = None;
+                x
+            });
+            prefix = source_prefix.chain(prefix.into_iter()).collect();
         }
         // add the bindings defined directly in this file
-        for (i, (bind_item, span)) in bind.iter_mut().zip(bind_span.into_iter()).enumerate() {
+        for (i, (bind_item, span)) in bind.iter_mut().zip(bind_span.iter().cloned()).enumerate() {
             key_bind.append(&mut bind_item.outputs(
                 (i + source_offset) as i32,
                 &hash,
                 &scope,
+                &modes,
                 false,
                 Some(span),
                 &mut codes,
@@ -513,11 +668,7 @@ impl KeyFile {
         }
         // now that we've properly expanded this files bindings and any source
         // bindings we can combine them into a single vector
-        let bind = if let Some(s) = source {
-            s.bind.iter().cloned().chain(bind.into_iter()).collect()
-        } else {
-            bind
-        };
+        let bind: Vec<_> = source_bind.into_iter().chain(bind.into_iter()).collect();
 
         modes.insert_implicit_mode_bindings(
             &bind,
@@ -528,6 +679,12 @@ impl KeyFile {
             warnings,
         );
 
+        // check that every mode can be entered and left
+        let spans: Vec<_> = std::iter::repeat_n(None, source_offset)
+            .chain(bind_span.into_iter().map(Some))
+            .collect();
+        ModeGraph::new(&modes, &bind).lint(&modes, &spans, warnings);
+
         // sort all bindings by their priority
         key_bind.sort_by(BindingOutput::cmp_priority);
 
@@ -549,6 +706,10 @@ impl KeyFile {
             }
         }
 
+        // name the prefixes documented by `[[prefix]]`
+        let mut final_key_bind: Vec<_> = final_key_bind.into();
+        PrefixDoc::attach(&prefix, &mut final_key_bind, warnings);
+
         if errors.len() == 0 {
             return Ok(KeyFile {
                 name,
@@ -556,10 +717,12 @@ impl KeyFile {
                 define,
                 bind,
                 docs,
+                prefix,
+                layout,
                 mode: modes,
                 kind,
                 hash,
-                key_bind: final_key_bind.into(),
+                key_bind: final_key_bind,
             });
         } else {
             return Err(errors.into());
@@ -587,6 +750,12 @@ pub struct KeyFileResult {
     pub(crate) file: Option<KeyFile>,
     pub(crate) errors: Option<Vec<ErrorReport>>,
     pub(crate) scope: Scope,
+    // the raw file contents, used to report the location of traced expressions
+    pub(crate) content: Vec<u8>,
+    // the return from a mode with `returnAfter` that has yet to occur
+    pub(crate) pending_return: Option<PendingReturn>,
+    // the platform keys are displayed for (see `set_key_platform`)
+    pub(crate) key_platform: KeyPlatform,
 }
 
 #[wasm_bindgen]
@@ -600,10 +769,33 @@ impl KeyFileResult {
                 level,
             }]),
             scope: Scope::new(),
+            content: Vec::new(),
+            pending_return: None,
+            key_platform: KeyPlatform::default(),
         };
     }
 }
 
+impl KeyFileResult {
+    // the location of `span` in this file; expressions that come from a `source` file may
+    // lie outside of this file, and have an unknown location
+    fn char_range(&self, span: &std::ops::Range<usize>, offsets: &StringOffsets) -> CharRange {
+        return self.known_char_range(span, offsets).unwrap_or_default();
+    }
+
+    fn known_char_range(
+        &self,
+        span: &std::ops::Range<usize>,
+        offsets: &StringOffsets,
+    ) -> Option<CharRange> {
+        if span.end <= self.content.len() {
+            return Some(range_to_pos(span, offsets));
+        } else {
+            return None;
+        }
+    }
+}
+
 // These lines are tested during integration tests with the typescript code
 #[wasm_bindgen]
 #[cfg_attr(coverage_nightly, coverage(off))]
@@ -661,6 +853,19 @@ impl KeyFileResult {
                 args: BindingOutputArgs { command_id, .. },
                 ..
             } => *command_id,
+            // prefixes named by `[[prefix]]`
+            BindingOutput::Prefix { key, args, .. } if !args.name.is_empty() => {
+                return Some(BindingDoc {
+                    name: args.name.clone(),
+                    description: args.description.clone(),
+                    combined: Some(CombinedBindingDoc {
+                        name: args.name.clone(),
+                        key: key.clone(),
+                        description: args.description.clone(),
+                    }),
+                    ..BindingDoc::default()
+                });
+            }
             _ => -1 as i32,
         };
         if command_id >= 0 {
@@ -719,6 +924,45 @@ impl KeyFileResult {
         };
     }
 
+    // record a change of modes (see `mode.ts`), so that we know when to leave a mode
+    // with `returnAfter`
+    pub fn enter_mode(&mut self, from: &str, to: &str) {
+        self.pending_return = match self.mode(to) {
+            Some(Mode {
+                returnAfter: Some(count),
+                returnTo,
+                ..
+            }) => Some(PendingReturn {
+                mode: to.to_string(),
+                to: returnTo.unwrap_or_else(|| match from {
+                    "" => self.default_mode(),
+                    _ => from.to_string(),
+                }),
+                remaining: count,
+            }),
+            _ => None,
+        };
+    }
+
+    // record that `binding` has been run (see `do.ts`); returns the mode to change to when
+    // the binding completes the `returnAfter` count of the current mode. `mode` is the
+    // mode after the binding's commands ran: a binding that changes modes itself is never
+    // overridden by the return
+    pub fn complete_binding(&mut self, binding: &ReifiedBinding, mode: &str) -> Option<String> {
+        if !binding.finalKey {
+            return None;
+        }
+        let pending = self.pending_return.as_mut()?;
+        if pending.mode != binding.mode || pending.mode != mode {
+            return None;
+        }
+        pending.remaining -= 1;
+        if pending.remaining > 0 {
+            return None;
+        }
+        return self.pending_return.take().map(|x| x.to);
+    }
+
     pub fn check_prefix_hash(&self, hash: &str) -> Option<bool> {
         if let Some(KeyFile {
             hash: binding_hash, ..
@@ -759,7 +1003,19 @@ impl KeyFileResult {
                         );
                     }
 
-                    return ReifiedBinding::new(&binding, &mut self.scope);
+                    let mut result = ReifiedBinding::new(&binding, &mut self.scope);
+                    // apply the defaults of the mode this binding is run in
+                    if let Some(mode) = self.mode(&result.mode) {
+                        for tag in mode.tags {
+                            if !binding.tags.contains(&tag) {
+                                result.tags.push(Dynamic::from(ImmutableString::from(tag)));
+                            }
+                        }
+                        if result.doc.kind.is_none() {
+                            result.doc.kind = mode.kind.map(Spanned::into_inner);
+                        }
+                    }
+                    return result;
                 }
             } else {
                 return ReifiedBinding::with_error(
@@ -962,6 +1218,79 @@ impl KeyFileResult {
         return self.scope.get_defined_vals();
     }
 
+    // the platform (a value of node's `process.platform`) that `display_keys` and
+    // `text_docs` render key sequences for
+    pub fn set_key_platform(&mut self, platform: &str) {
+        self.key_platform = KeyPlatform::from_name(platform);
+    }
+
+    // renders a sequence of key presses (e.g. `ctrl+k ctrl+s`) for display on the platform
+    // set by `set_key_platform`; used for the palette, the status bar and documentation
+    pub fn display_keys(&self, keys: &str) -> String {
+        let layout = match &self.file {
+            Some(file) => file.layout,
+            Option::None => KeyboardLayout::default(),
+        };
+        return self.key_platform.display_keys(keys, layout);
+    }
+
+    // when enabled, records each expression evaluated while resolving commands (see
+    // `expression_trace`); used to debug expressions from the extension
+    pub fn set_expression_tracing(&mut self, enabled: bool) {
+        self.scope.set_tracing(enabled);
+    }
+
+    // the expressions evaluated since the last call to `expression_trace`
+    pub fn expression_trace(&mut self) -> Vec<ExpressionTrace> {
+        let offsets = StringOffsets::from_bytes(&self.content);
+        let traces = self.scope.report_trace();
+        return traces
+            .into_iter()
+            .map(|mut entry| {
+                entry.range = self.char_range(&entry.span, &offsets);
+                entry
+            })
+            .collect();
+    }
+
+    // the `n` expressions that have taken the most time to compile and evaluate
+    pub fn expression_profile(&self, n: usize) -> Vec<ExpressionTiming> {
+        let offsets = StringOffsets::from_bytes(&self.content);
+        return self
+            .scope
+            .profile
+            .expressions()
+            .into_iter()
+            .take(n)
+            .map(|mut entry| {
+                entry.range = self.known_char_range(&entry.span, &offsets);
+                entry
+            })
+            .collect();
+    }
+
+    // the `n` `[[bind]]` entries whose expressions have taken the most time to compile and
+    // evaluate
+    pub fn binding_profile(&self, n: usize) -> Vec<BindingTiming> {
+        let offsets = StringOffsets::from_bytes(&self.content);
+        return self
+            .scope
+            .profile
+            .bindings()
+            .into_iter()
+            .take(n)
+            .map(|mut entry| {
+                entry.range = self.known_char_range(&entry.span, &offsets);
+                entry
+            })
+            .collect();
+    }
+
+    // forget all timing collected so far (e.g. to only profile run-time evaluation)
+    pub fn clear_profile(&mut self) {
+        self.scope.profile.clear();
+    }
+
     // list all keybinding kinds (from [[kind]])
     pub fn kinds(&self) -> Vec<Kind> {
         if let Some(KeyFile { kind, .. }) = &self.file {
@@ -971,10 +1300,23 @@ impl KeyFileResult {
         }
     }
 
+    // the transitions between modes, in the DOT format (see `ModeGraph::dot`)
+    pub fn mode_graph(&self) -> Option<String> {
+        return self
+            .file
+            .as_ref()
+            .map(|file| ModeGraph::new(&file.mode, &file.bind).dot());
+    }
+
     // get the actual text documentation as a string of markdown
     pub fn text_docs(&self) -> Option<String> {
         if let Some(KeyFile { docs, mode, .. }) = &self.file {
-            return Some(FileDocSection::write_markdown(&docs, mode.map.len() > 1));
+            let display = |k: &str| self.display_keys(k);
+            return Some(FileDocSection::write_markdown(
+                &docs,
+                mode.map.len() > 1,
+                &display,
+            ));
         } else {
             return None;
         }
@@ -1039,10 +1381,16 @@ where
             scope,
             file: Some(result),
             errors: Some(ErrorSet::from(warnings).report(file_content.as_ref())),
+            content: file_content.as_ref().to_vec(),
+            pending_return: None,
+            key_platform: KeyPlatform::default(),
         },
         Err(err) => KeyFileResult {
             scope,
             file: None,
+            content: file_content.as_ref().to_vec(),
+            pending_return: None,
+            key_platform: KeyPlatform::default(),
             errors: Some(
                 ErrorSet::from(
                     err.errors
@@ -1171,15 +1519,18 @@ pub(crate) mod tests {
     use super::*;
     use crate::bind::foreach::all_characters;
     use crate::bind::prefix::Prefix;
-    use crate::bind::{BindingOutput, BindingOutputArgs, UNKNOWN_RANGE};
+    use crate::bind::{BindingOutput, BindingOutputArgs, TEXT_FOCUS_CONDITION, UNKNOWN_RANGE};
+    use crate::expression::TracedVariable;
     use crate::expression::value::Expression;
     use crate::expression::value::Value;
-    use crate::mode::WhenNoBinding;
+    use crate::mode::{
+        CursorShape, LineNumbers, ModeHighlight, WhenNoBinding, WhenNoBindingHeader,
+    };
+    use indexmap::IndexMap;
     use smallvec::SmallVec;
-    use std::collections::HashMap;
     use test_log::test;
 
-    pub(crate) fn unwrap_table(x: &Value) -> HashMap<String, Value> {
+    pub(crate) fn unwrap_table(x: &Value) -> IndexMap<String, Value> {
         match x {
             Value::Table(x, _) => x.clone(),
             _ => panic!("Expected a table!"),
@@ -1357,7 +1708,7 @@ pub(crate) mod tests {
         assert_eq!(result.bind[0].commands[0].command, "shebang");
         assert_eq!(
             unwrap_table(&result.bind[0].commands[0].args),
-            HashMap::from([
+            IndexMap::from([
                 ("a".into(), Value::Integer(1)),
                 (
                     "b".into(),
@@ -1411,7 +1762,7 @@ pub(crate) mod tests {
         assert_eq!(result.bind[0].commands[0].command, "shebang");
         assert_eq!(
             unwrap_table(&result.bind[0].commands[0].args),
-            HashMap::from([
+            IndexMap::from([
                 ("a".into(), Value::Integer(1)),
                 (
                     "b".into(),
@@ -1549,6 +1900,107 @@ pub(crate) mod tests {
         )
     }
 
+    #[test]
+    fn define_val_dependency_order() {
+        let data = r#"#:master-keybindings
+        [header]
+        version = "2.0.0"
+
+        [[define.val]]
+        greeting = '{{val.hello + " " + val.names["first"]}}'
+        hello = '{{val.word + "o"}}'
+
+        [[define.val]]
+        word = "hell"
+        names.first = "joe"
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        let result = result.file.unwrap();
+        assert_eq!(
+            result.define.val["greeting"],
+            Value::String("hello joe".to_string())
+        );
+        let keys: Vec<_> = result.define.val.keys().cloned().collect();
+        assert_eq!(keys, ["greeting", "hello", "word", "names"]);
+    }
+
+    #[test]
+    fn define_val_cycle_error() {
+        let data = r#"#:master-keybindings
+        [header]
+        version = "2.0.0"
+
+        [[define.val]]
+        unrelated = 1
+        a = '{{val.b.x + 1}}'
+        b = { x = '{{val.c}}' }
+        c = '{{val.a}}'
+        d = '{{val.a}}'
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        let report = result.errors.unwrap();
+        assert_eq!(report.len(), 1);
+        assert!(report[0].message.contains("cycle: `a` → `b` → `c` → `a`"));
+        assert_eq!(report[0].range.start.line, 6);
+    }
+
+    #[test]
+    fn define_val_cycle_skips_only_dependents() {
+        let data = r#"#:master-keybindings
+        [header]
+        version = "2.0.0"
+
+        [[define.val]]
+        a = '{{val.b}}'
+        b = '{{val.a}}'
+        c = '{{val.a + 1}}'
+        d = '{{val.e + 1}}'
+        e = 1
+        f = '{{1 +}}'
+        g = '{{"val.a" + val.e}}'
+
+        [[bind]]
+        key = "x"
+        command = "foo"
+        args.d = '{{val.d}}'
+        args.g = '{{val.g}}'
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        let report = result.errors.unwrap();
+        assert_eq!(report.len(), 2);
+        assert!(report[0].message.contains("cycle: `a` → `b` → `a`"));
+        assert_eq!(report[0].range.start.line, 5);
+        assert_eq!(report[1].range.start.line, 10);
+    }
+
+    #[test]
+    fn define_val_errors_skip_only_dependents() {
+        let data = r#"#:master-keybindings
+        [header]
+        version = "2.0.0"
+
+        [[define.val]]
+        a = '{{[1][5]}}'
+        b = '{{val.a + 1}}'
+        c = '{{[2][7]}}'
+        d = 1
+
+        [[bind]]
+        key = "x"
+        command = "foo"
+        args.value = '{{val.d}}'
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        let report = result.errors.unwrap();
+        assert_eq!(report.len(), 2);
+        assert_eq!(report[0].range.start.line, 5);
+        assert_eq!(report[1].range.start.line, 7);
+    }
+
     #[test]
     fn just_one_default_mode() {
         let data = r#"#:master-keybindings
@@ -1696,80 +2148,1034 @@ pub(crate) mod tests {
 
         let result = parse_keybinding_data(data, None);
         let report = result.errors.unwrap();
-        assert!(report[0].message.contains("`capture` is implicit"));
+        assert!(report[0].message.contains("must set `capture = true`"));
         assert_eq!(report[0].range.start.line, 11);
         assert_eq!(report[0].range.end.line, 11);
     }
 
     #[test]
-    fn eval_mode_expressions() {
-        let data = r#"#:master-keybindings
+    fn user_defined_capture_modes() {
+        let data = r#"
+        #:master-keybindings
+
         [header]
         version = "2.0.0"
 
         [[mode]]
-        name = "a"
+        name = "normal"
         default = true
-        whenNoBinding = 'insertCharacters'
 
         [[mode]]
-        name = "b"
+        name = "insert"
+        whenNoBinding = "insertCharacters"
 
         [[mode]]
-        name = "c"
+        name = "capture"
+        capture = true
+        highlight = "Alert"
+
+        [[mode]]
+        name = "register"
+        capture = true
+        cursorShape = "BlockOutline"
 
         [[bind]]
-        key = "a"
-        command = "foo"
+        key = "i"
+        command = "master-key.enterInsert"
+
+        [[bind]]
+        key = "escape"
         mode = '{{all_modes()}}'
+        command = "master-key.enterNormal"
 
         [[bind]]
-        key = "b"
-        command = "bar"
-        mode = '{{not_modes(["c"])}}'
+        key = "escape"
+        mode = "register"
+        command = "master-key.enterNormal"
+
+        [[bind]]
+        key = "shift+'"
+        command = "master-key.captureKeys"
+        args = { acceptAfter = 1, mode = "register" }
         "#;
 
         let result = parse_keybinding_data(data, None);
-
-        let result = result.file.unwrap();
-        assert!(result.bind[0].mode.iter().any(|x| x == "a"));
-        assert!(result.bind[0].mode.iter().any(|x| x == "b"));
-        assert!(result.bind[0].mode.iter().any(|x| x == "c"));
-        assert!(result.bind[1].mode.iter().any(|x| x == "a"));
-        assert!(result.bind[1].mode.iter().any(|x| x == "b"));
-        assert!(!result.bind[1].mode.iter().any(|x| x == "c"));
+        assert_eq!(result.errors.as_ref().unwrap().len(), 0);
+        let file = result.file.unwrap();
+        let capture = file.mode.get("capture").unwrap();
+        assert!(capture.capture);
+        assert!(matches!(capture.highlight, ModeHighlight::Alert));
+        assert!(matches!(capture.cursorShape, CursorShape::Underline));
+        let register = file.mode.get("register").unwrap();
+        assert!(register.capture);
+        assert_eq!(register.whenNoBinding, WhenNoBinding::InsertCharacters);
+        assert!(matches!(register.cursorShape, CursorShape::BlockOutline));
+        // capture modes are not included in `all_modes()`
+        let mut modes = file.bind[1].mode.clone();
+        modes.sort();
+        assert_eq!(modes, ["insert", "normal"]);
+        assert_eq!(file.bind[2].mode, ["register"]);
     }
 
     #[test]
-    fn validate_mode_expressions() {
-        let data = r#"#:master-keybindings
+    fn mode_cancel_prefix() {
+        let data = r#"
+        #:master-keybindings
+
         [header]
         version = "2.0.0"
 
         [[mode]]
-        name = "a"
+        name = "normal"
         default = true
-        whenNoBinding = 'insertCharacters'
+        cancelPrefix = true
 
         [[mode]]
-        name = "b"
+        name = "insert"
+        whenNoBinding = "insertCharacters"
 
         [[mode]]
-        name = "c"
+        name = "visual"
+        cancelPrefix = "ctrl+g"
 
         [[bind]]
-        key = "b"
-        command = "bar"
-        mode = '{{not_modes(["d"])}}'
+        key = "g g"
+        mode = ["normal", "insert", "visual"]
+        command = "top"
+
+        [[bind]]
+        key = "d w"
+        command = "deleteWord"
         "#;
 
         let result = parse_keybinding_data(data, None);
-        let report = result.errors.unwrap();
-        assert!(report[0].message.contains("mode `d`"));
-        assert_eq!(report[0].range.start.line, 18)
+        let file = result.file.unwrap();
+        let prefix_codes = |mode: &str| -> Vec<i32> {
+            let mut codes: Vec<_> = file
+                .key_bind
+                .iter()
+                .filter_map(|x| match x {
+                    BindingOutput::Prefix { args, .. } if args.mode == mode => Some(args.prefix_id),
+                    _ => None,
+                })
+                .collect();
+            codes.sort();
+            codes
+        };
+        let cancel_codes = |key: &str, mode: &str| -> Vec<i32> {
+            let mut codes: Vec<_> = file
+                .key_bind
+                .iter()
+                .filter_map(|x| match x {
+                    BindingOutput::Ignore {
+                        key: k,
+                        when: Some(w),
+                    } if k == key && w.contains(&format!("'{mode}'")) => {
+                        let code = w.split("master-key.prefixCode == ").nth(1)?;
+                        let code: String = code.chars().take_while(char::is_ascii_digit).collect();
+                        Some(code.parse::<i32>().unwrap())
+                    }
+                    _ => None,
+                })
+                .collect();
+            codes.sort();
+            codes
+        };
+
+        assert_eq!(prefix_codes("normal").len(), 2);
+        assert_eq!(cancel_codes("escape", "normal"), prefix_codes("normal"));
+        assert_eq!(cancel_codes("ctrl+g", "visual"), prefix_codes("visual"));
+        assert_eq!(cancel_codes("escape", "visual").len(), 0);
+        assert_eq!(cancel_codes("escape", "insert").len(), 0);
+        // a bare key only cancels a pending prefix in the focus of bare bindings
+        assert!(file.key_bind.iter().all(|x| match x {
+            BindingOutput::Ignore { key, when: Some(w) } if w.contains("prefixCode") => {
+                w.ends_with(TEXT_FOCUS_CONDITION) == (key == "escape")
+            }
+            _ => true,
+        }));
+        assert_eq!(
+            file.mode.get("normal").unwrap().cancelPrefix,
+            Some("escape".into())
+        );
     }
 
-    fn unwrap_prefixes(prefix: &Prefix) -> &Vec<String> {
+    #[test]
+    fn mode_cancel_prefix_errors() {
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+
+        [[mode]]
+        name = "normal"
+        default = true
+        whenNoBinding = "insertCharacters"
+        cancelPrefix = "g h"
+
+        [[mode]]
+        name = "visual"
+        cancelPrefix = "ctrl+foo"
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        let report = result.errors.unwrap();
+        assert_eq!(report.len(), 2);
+        assert!(report[0].message.contains("single key press"));
+        assert_eq!(report[0].range.start.line, 10);
+        assert!(report[1].message.contains("`foo` is an invalid key"));
+        assert_eq!(report[1].range.start.line, 14);
+    }
+
+    #[test]
+    fn mode_count_bindings() {
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+
+        [[mode]]
+        name = "normal"
+        default = true
+        count = true
+
+        [[mode]]
+        name = "insert"
+        whenNoBinding = "insertCharacters"
+
+        [[bind]]
+        key = "0"
+        when = "!master-key.count"
+        command = "cursorHome"
+
+        [[bind]]
+        key = "numpad5"
+        command = "cursorEnd"
+
+        [[bind]]
+        key = "9"
+        when = "editorHasSelection"
+        command = "cursorPageDown"
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        let file = result.file.unwrap();
+        let counts: Vec<_> = file
+            .key_bind
+            .iter()
+            .filter_map(|x| match x {
+                BindingOutput::Do { key, args, .. } if args.name.starts_with("count ") => {
+                    Some((key.as_str(), args))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(counts.len(), 18);
+        assert!(
+            counts
+                .iter()
+                .all(|(_, args)| args.mode == "normal" && args.implicit)
+        );
+        assert!(counts.iter().any(|(key, _)| *key == "0"));
+        assert!(!counts.iter().any(|(key, _)| *key == "numpad5"));
+        // whatever its `when` clause
+        assert!(!counts.iter().any(|(key, _)| *key == "9"));
+
+        let (_, args) = counts.iter().find(|(key, _)| *key == "7").unwrap();
+        let bind = &file.bind[args.command_id as usize];
+        assert_eq!(bind.commands[0].command, "master-key.updateCount");
+        assert_eq!(
+            bind.commands[0].args,
+            Value::Table(IndexMap::from([("value".into(), Value::Integer(7))]), None)
+        );
+        assert!(!bind.finalKey);
+        assert_eq!(bind.doc.combined.as_ref().unwrap().name, "count 0-9");
+
+        // the explicit `0` binding is kept alongside the count binding
+        let zeros = file
+            .key_bind
+            .iter()
+            .filter(|x| matches!(x, BindingOutput::Do { key, .. } if key == "0"))
+            .count();
+        assert_eq!(zeros, 2);
+    }
+
+    #[test]
+    fn header_keyboard_layout() {
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+        layout = "colemak"
+
+        # # Motions
+
+        [[bind]]
+        key = "k"
+        command = "cursorUp"
+        doc.name = "up"
+
+        [[bind]]
+        key = "ctrl+j"
+        command = "cursorDown"
+
+        [[bind]]
+        key = "g"
+        prefixes.anyOf = ["y"]
+        command = "cursorTop"
+
+        [[bind]]
+        key = "y h"
+        command = "cursorBottom"
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        assert_eq!(result.n_errors(), 0);
+        let file = result.file.as_ref().unwrap();
+        let keys: Vec<_> = file.bind.iter().map(|x| x.key.join(" ")).collect();
+        assert_eq!(keys, ["e", "ctrl+n", "d", "j h"]);
+        assert_eq!(
+            unwrap_prefixes(&file.bind[2].prefixes),
+            &vec!["j".to_string()]
+        );
+        assert!(file.key_bind.iter().any(|x| matches!(x,
+            BindingOutput::Do { key, args, .. } if key == "d" && args.prefix == "j"
+        )));
+        assert!(
+            result
+                .text_docs()
+                .unwrap()
+                .contains("<key-bind>e</key-bind> | up")
+        );
+    }
+
+    #[test]
+    fn key_notation() {
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+        keyNotation = "vim"
+
+        [[define.val]]
+        leader = "<Space>"
+
+        [[bind]]
+        key = "<C-w>h"
+        command = "focusLeft"
+
+        [[bind]]
+        key = "<leader>ff"
+        command = "findFile"
+        doc.name = "find"
+
+        [[bind]]
+        key = "C-x C-s"
+        keyNotation = "emacs"
+        command = "save"
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        assert_eq!(result.n_errors(), 0);
+        let file = result.file.as_ref().unwrap();
+        let keys: Vec<_> = file.bind.iter().map(|x| x.key.join(" ")).collect();
+        assert_eq!(keys, ["ctrl+w h", "space f f", "ctrl+x ctrl+s"]);
+        assert!(
+            result
+                .text_docs()
+                .unwrap()
+                .contains("<key-bind>space</key-bind> <key-bind>f</key-bind>")
+        );
+    }
+
+    #[test]
+    fn key_notation_errors() {
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+        keyNotation = "vim"
+
+        [[bind]]
+        key = "a<X-w>"
+        command = "foo"
+
+        [[bind]]
+        key = "<leader>f"
+        command = "bar"
+
+        [[bind]]
+        key = "C-x C-ff"
+        keyNotation = "emacs"
+        command = "biz"
+
+        [[define.val]]
+        k = "ab<X-w>cd"
+
+        [[bind]]
+        key = "{{val.k}}"
+        command = "baz"
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        let report = result.errors.unwrap();
+        assert!(report[0].message.contains("`X-` is not a Vim modifier"));
+        assert_eq!(report[0].range.start.line, 8);
+        assert_eq!(report[0].range.start.col, 16);
+        assert_eq!(report[0].range.end.col, 21);
+        assert!(report[1].message.contains("`<leader>` is undefined"));
+        assert_eq!(report[1].range.start.line, 12);
+        assert!(report[2].message.contains("expected a single key"));
+        assert_eq!(report[2].range.start.line, 16);
+        assert_eq!(report[2].range.start.col, 19);
+        assert_eq!(report[2].range.end.col, 23);
+        // a key computed by an expression can't point inside the string, even when the
+        // result is as long as the expression
+        let computed = report.iter().find(|x| x.range.start.line == 24).unwrap();
+        assert!(computed.message.contains("`X-` is not a Vim modifier"));
+        assert_eq!(computed.range.start.col, 14);
+        assert_eq!(computed.range.end.col, 25);
+    }
+
+    #[test]
+    fn binding_tree() {
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+
+        [[bind]]
+        key = "ctrl+x"
+        command = "cut"
+
+        [tree.space.f]
+        doc.name = "files"
+        f = { command = "quickOpen", doc.name = "find" }
+        "ctrl+s" = { command = "save", when = "foo" }
+
+        [tree.space.w]
+        v = { command = "split", doc.name = "split" }
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        assert_eq!(result.n_errors(), 0);
+        let file = result.file.as_ref().unwrap();
+        let keys: Vec<_> = file.bind.iter().map(|x| x.key.join(" ")).collect();
+        assert_eq!(keys, ["ctrl+x", "space f f", "space f ctrl+s", "space w v"]);
+        assert_eq!(file.bind[1].doc.name, "find");
+        assert_eq!(
+            file.bind[2].when,
+            Some("(foo) && (editorTextFocus && !suggestionWidgetVisible)".to_string())
+        );
+        let prefix = file.key_bind.iter().find_map(|x| match x {
+            BindingOutput::Prefix { args, .. } if args.key == "space f" => Some(args),
+            _ => None,
+        });
+        assert_eq!(prefix.unwrap().name, "files");
+        assert!(
+            result
+                .text_docs()
+                .unwrap()
+                .contains("<key-bind>space</key-bind> <key-bind>f</key-bind> | files")
+        );
+    }
+
+    #[test]
+    fn binding_tree_errors() {
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+
+        [tree.space]
+        f = { command = "foo", key = "x" }
+        g = {}
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        let report = result.errors.unwrap();
+        assert!(report[0].message.contains("`key` is given by the position"));
+        assert_eq!(report[0].range.start.line, 7);
+        assert_eq!(report[0].range.start.col, 37);
+        assert!(report[1].message.contains("expected keys or the fields"));
+        assert_eq!(report[1].range.start.line, 8);
+
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+
+        [tree.space]
+        h.j = { command = "bar", prefixes.anyOf = ["ctrl+y"] }
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        let report = result.errors.unwrap();
+        assert!(report[0].message.contains("Prefix undefined: ctrl+y"));
+        assert_eq!(report[0].range.start.line, 7);
+
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+
+        [tree.space.f]
+        f = { command = "foo" }
+        command = "bar"
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        let report = result.errors.unwrap();
+        assert!(
+            report[0]
+                .message
+                .contains("both the key `f` and the field `command`")
+        );
+        assert_eq!(report[0].range.start.line, 6);
+    }
+
+    #[test]
+    fn binding_tree_modes() {
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+
+        [[mode]]
+        name = "insert"
+        default = true
+        whenNoBinding = "insertCharacters"
+
+        [[mode]]
+        name = "normal"
+
+        [tree.space.f]
+        doc.name = "files"
+        f = { command = "quickOpen", mode = "normal" }
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        let file = result.file.as_ref().unwrap();
+        let prefixes: Vec<_> = file
+            .key_bind
+            .iter()
+            .filter_map(|x| match x {
+                BindingOutput::Prefix { args, .. } => Some(args),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(prefixes.len(), 2);
+        assert!(prefixes.iter().all(|x| x.mode == "normal"));
+        let prefix = prefixes.iter().find(|x| x.key == "space f");
+        assert_eq!(prefix.unwrap().name, "files");
+        assert!(
+            result.text_docs().unwrap().contains(
+                "<key-bind>space</key-bind> <key-bind>f</key-bind> | files |  | normal |"
+            )
+        );
+    }
+
+    #[test]
+    fn prefix_docs() {
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+
+        ## # Git
+
+        [[prefix]]
+        key = "space g"
+        doc.name = "git"
+        doc.description = "source control"
+
+        [[bind]]
+        key = "space g s"
+        command = "scm"
+        doc.name = "status"
+
+        [[bind]]
+        key = "space f"
+        command = "files"
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        assert_eq!(result.n_errors(), 0);
+        let file = result.file.as_ref().unwrap();
+        let prefix = file.key_bind.iter().enumerate().find_map(|(i, x)| match x {
+            BindingOutput::Prefix { args, .. } if args.key == "space g" => Some((i, args)),
+            _ => None,
+        });
+        let (i, args) = prefix.unwrap();
+        assert_eq!(args.name, "git");
+        assert_eq!(args.description, "source control");
+        let doc = result.docs(i).unwrap();
+        assert_eq!(doc.name, "git");
+        assert_eq!(doc.combined.unwrap().key, "g");
+
+        // the unnamed prefix `space` is left as is
+        let space = file.key_bind.iter().position(|x| match x {
+            BindingOutput::Prefix { args, .. } => args.key == "space",
+            _ => false,
+        });
+        assert!(result.docs(space.unwrap()).is_none());
+
+        let docs = result.text_docs().unwrap();
+        assert!(docs.contains(
+            "| <key-bind>space</key-bind> <key-bind>g</key-bind> | git | source control |"
+        ));
+    }
+
+    #[test]
+    fn prefix_docs_errors() {
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+
+        [[prefix]]
+        key = "space x"
+        doc.name = "unused"
+
+        [[prefix]]
+        key = "space g"
+        mode = "visual"
+        doc.name = "git"
+
+        [[bind]]
+        key = "space g s"
+        command = "scm"
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        let report = result.errors.unwrap();
+        assert!(report[0].message.contains("Undefined mode(s): visual"));
+        assert_eq!(report[0].range.start.line, 12);
+
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+
+        [[prefix]]
+        key = "space x"
+        doc.name = "unused"
+
+        [[bind]]
+        key = "space g s"
+        command = "scm"
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        assert!(result.file.is_some());
+        let report = result.errors.unwrap();
+        assert_eq!(report.len(), 1);
+        assert!(
+            report[0]
+                .message
+                .contains("No binding of mode `default` starts with the prefix `space x`")
+        );
+        assert_eq!(report[0].range.start.line, 6);
+    }
+
+    #[test]
+    fn binding_focus() {
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+        focus = "terminal"
+
+        [[mode]]
+        name = "normal"
+        default = true
+        whenNoBinding = "ignoreCharacters"
+
+        [[mode]]
+        name = "insert"
+        whenNoBinding = "insertCharacters"
+
+        [[bind]]
+        key = "j"
+        command = "down"
+
+        [[bind]]
+        key = "ctrl+k"
+        command = "master-key.prefix"
+
+        [[bind]]
+        key = "ctrl+k s"
+        focus = "list"
+        command = "select"
+
+        [[bind]]
+        key = "s"
+        prefixes.anyOf = ["ctrl+k"]
+        focus = "notebook"
+        when = "foo"
+        command = "save"
+
+        [[bind]]
+        key = "shift+j"
+        focus = "any"
+        when = "bar"
+        command = "join"
+
+        [[bind]]
+        key = "i"
+        command = "master-key.enterInsert"
+
+        [[bind]]
+        key = "escape"
+        mode = "insert"
+        command = "master-key.enterNormal"
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        assert_eq!(result.n_errors(), 0);
+        let file = result.file.unwrap();
+        let when: Vec<_> = file.bind.iter().map(|x| x.when.clone()).collect();
+        assert_eq!(
+            when,
+            [
+                Some("terminalFocus".to_string()),
+                None,
+                Some("(listFocus && !inputFocus)".to_string()),
+                Some("(foo) && (notebookEditorFocused && !inputFocus)".to_string()),
+                Some("bar".to_string()),
+                Some("terminalFocus".to_string()),
+                Some("terminalFocus".to_string()),
+            ]
+        );
+
+        // characters are ignored in the header's focus
+        let ignored = file.key_bind.iter().find_map(|x| match x {
+            BindingOutput::Ignore { when, .. } => when.clone(),
+            _ => None,
+        });
+        assert_eq!(
+            ignored.unwrap(),
+            "(!master-key.mode || master-key.mode == 'normal') && terminalFocus"
+        );
+    }
+
+    #[test]
+    fn platform_key_display() {
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+
+        [[bind]]
+        key = "ctrl+shift+p"
+        command = "palette"
+        doc.name = "palette"
+
+        [[bind]]
+        key = "space [KeyW]"
+        command = "save"
+        doc.name = "save"
+        "#;
+
+        let mut result = parse_keybinding_data(data, None);
+        assert_eq!(result.n_errors(), 0);
+        assert!(
+            result
+                .text_docs()
+                .unwrap()
+                .contains("<key-bind>ctrl+shift+p</key-bind>")
+        );
+
+        result.set_key_platform("darwin");
+        let docs = result.text_docs().unwrap();
+        assert!(docs.contains("<key-bind>⌃⇧P</key-bind> | palette"));
+        assert!(docs.contains("<key-bind>Space</key-bind> <key-bind>W</key-bind> | save"));
+        // `cmd`, `win` and `meta` are one modifier
+        assert_eq!(result.display_keys("win+a"), "⌘A");
+        assert_eq!(result.display_keys("cmd+a"), result.display_keys("meta+a"));
+
+        result.set_key_platform("win32");
+        assert_eq!(result.display_keys("ctrl+shift+p"), "Ctrl+Shift+P");
+    }
+
+    #[test]
+    fn header_keyboard_layout_error() {
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+        layout = "workman"
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        let report = result.errors.unwrap();
+        assert!(report[0].message.contains("unknown variant `workman`"));
+        assert_eq!(report[0].range.start.line, 5);
+    }
+
+    #[test]
+    fn remap_keys() {
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+
+        [[remap]]
+        from = "space"
+        to = ","
+
+        [[remap]]
+        from = ";"
+        to = "shift+;"
+
+        [[remap]]
+        from = "Shift+;"
+        to = ";"
+
+        [[bind]]
+        key = "space f"
+        command = "find"
+
+        [[bind]]
+        key = "g"
+        prefixes.anyOf = ["space"]
+        command = "goto"
+
+        [[bind]]
+        key = ";"
+        command = "repeat"
+
+        [[bind]]
+        key = "shift+;"
+        command = "commandLine"
+
+        [[bind]]
+        key = "ctrl+space"
+        command = "suggest"
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        assert_eq!(result.n_errors(), 0);
+        let file = result.file.unwrap();
+        let keys: Vec<_> = file.bind.iter().map(|x| x.key.join(" ")).collect();
+        assert_eq!(keys, [", f", "g", "shift+;", ";", "ctrl+space"]);
+        assert_eq!(
+            unwrap_prefixes(&file.bind[1].prefixes),
+            &vec![",".to_string()]
+        );
+    }
+
+    #[test]
+    fn remap_errors() {
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+
+        [[remap]]
+        from = "a b"
+        to = "c"
+
+        [[remap]]
+        from = "x"
+        to = "ctrl+foo"
+
+        [[remap]]
+        from = "y"
+        to = "z"
+
+        [[remap]]
+        from = "Y"
+        to = "w"
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        let report = result.errors.unwrap();
+        assert_eq!(report.len(), 4);
+        assert!(
+            report[0]
+                .message
+                .contains("`from` must be a single key press")
+        );
+        assert_eq!(report[0].range.start.line, 7);
+        assert!(report[1].message.contains("`foo` is an invalid key"));
+        assert_eq!(report[1].range.start.line, 12);
+        assert!(report[2].message.contains("`y` is remapped more than once"));
+        assert_eq!(report[2].range.start.line, 18);
+        assert_eq!(report[3].range.start.line, 14);
+    }
+
+    #[test]
+    fn remap_collisions() {
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+
+        [[remap]]
+        from = "a"
+        to = "b"
+
+        [[bind]]
+        key = "a"
+        command = "foo"
+
+        [[bind]]
+        key = "b"
+        command = "bar"
+
+        [[bind]]
+        key = "b"
+        when = "editorHasSelection"
+        command = "biz"
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        let report = result.errors.unwrap();
+        assert_eq!(report.len(), 2);
+        assert!(report[0].message.contains("`b` is bound more than once"));
+        assert_eq!(report[0].range.start.line, 10);
+        assert_eq!(report[1].range.start.line, 14);
+    }
+
+    #[test]
+    fn capture_mode_errors() {
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+
+        [[mode]]
+        name = "normal"
+        default = true
+        whenNoBinding = "insertCharacters"
+
+        [[mode]]
+        name = "register"
+        capture = true
+        whenNoBinding = "ignoreCharacters"
+
+        [[bind]]
+        key = "shift+'"
+        command = "master-key.captureKeys"
+        args = { acceptAfter = 1, mode = "normal" }
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        let report = result.errors.unwrap();
+        assert_eq!(report.len(), 2);
+        assert!(report[0].message.contains("must use `whenNoBinding"));
+        assert_eq!(report[0].range.start.line, 14);
+        assert!(report[1].message.contains("`normal` is not"));
+        assert_eq!(report[1].range.start.line, 16);
+
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+
+        [[mode]]
+        name = "normal"
+        default = true
+        capture = true
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        let report = result.errors.unwrap();
+        assert!(report[0].message.contains("cannot be the default mode"));
+        assert_eq!(report[0].range.start.line, 6);
+    }
+
+    #[test]
+    fn eval_mode_expressions() {
+        let data = r#"#:master-keybindings
+        [header]
+        version = "2.0.0"
+
+        [[mode]]
+        name = "a"
+        default = true
+        whenNoBinding = 'insertCharacters'
+
+        [[mode]]
+        name = "b"
+
+        [[mode]]
+        name = "c"
+
+        [[bind]]
+        key = "a"
+        command = "foo"
+        mode = '{{all_modes()}}'
+
+        [[bind]]
+        key = "b"
+        command = "bar"
+        mode = '{{not_modes(["c"])}}'
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+
+        let result = result.file.unwrap();
+        assert!(result.bind[0].mode.iter().any(|x| x == "a"));
+        assert!(result.bind[0].mode.iter().any(|x| x == "b"));
+        assert!(result.bind[0].mode.iter().any(|x| x == "c"));
+        assert!(result.bind[1].mode.iter().any(|x| x == "a"));
+        assert!(result.bind[1].mode.iter().any(|x| x == "b"));
+        assert!(!result.bind[1].mode.iter().any(|x| x == "c"));
+    }
+
+    #[test]
+    fn validate_mode_expressions() {
+        let data = r#"#:master-keybindings
+        [header]
+        version = "2.0.0"
+
+        [[mode]]
+        name = "a"
+        default = true
+        whenNoBinding = 'insertCharacters'
+
+        [[mode]]
+        name = "b"
+
+        [[mode]]
+        name = "c"
+
+        [[bind]]
+        key = "b"
+        command = "bar"
+        mode = '{{not_modes(["d"])}}'
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        let report = result.errors.unwrap();
+        assert!(report[0].message.contains("mode `d`"));
+        assert_eq!(report[0].range.start.line, 18)
+    }
+
+    fn unwrap_prefixes(prefix: &Prefix) -> &Vec<String> {
         return match prefix {
             Prefix::AnyOf(x) => x,
             x @ _ => panic!("Unexpected, unresolved prefix: {x:?}"),
@@ -2101,21 +3507,104 @@ pub(crate) mod tests {
         command = "bob"
 
         [[bind]]
-        key = "a k"
-        command = "allowed conditional"
-        when = "master-key.count > 0"
+        key = "a k"
+        command = "allowed conditional"
+        when = "master-key.count > 0"
+
+        [[bind]]
+        key = "a k"
+        command = "duplicate"
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        let report = result.errors.unwrap();
+
+        assert!(report[0].message.contains("Duplicate key"));
+        assert_eq!(report[0].range.start.line, 13);
+        assert_eq!(report[1].range.start.line, 4);
+    }
+
+    #[test]
+    fn output_bindings_canonical_keys() {
+        let data = r#"#:master-keybindings
+        [header]
+        version = "2.0.0"
+
+        [[bind]]
+        key = "Shift+Ctrl+A"
+        command = "foo"
+        doc.name = "foo"
+
+        [[bind]]
+        key = "ctrl+shift+a"
+        command = "duplicate"
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        let report = result.errors.as_ref().unwrap();
+        assert!(report[0].message.contains("Duplicate key"));
+        assert_eq!(report[0].range.start.line, 9);
+        assert_eq!(report[1].range.start.line, 4);
+
+        // `cmd`, `win` and `meta` are the same modifier
+        let data = r#"#:master-keybindings
+        [header]
+        version = "2.0.0"
+
+        [[bind]]
+        key = "Cmd+A"
+        command = "foo"
+
+        [[bind]]
+        key = "meta+a"
+        command = "duplicate"
+
+        [[bind]]
+        key = "win+shift+b"
+        command = "bar"
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        let report = result.errors.as_ref().unwrap();
+        assert!(report[0].message.contains("Duplicate key"));
+        assert_eq!(report[0].range.start.line, 8);
+        assert_eq!(report.len(), 2);
+
+        let data = r#"#:master-keybindings
+        [header]
+        version = "2.0.0"
+
+        [[bind]]
+        key = "Shift+Ctrl+A"
+        command = "foo"
+        doc.name = "foo"
+
+        [[bind]]
+        key = "ctrl+k"
+        command = "master-key.prefix"
 
         [[bind]]
-        key = "a k"
-        command = "duplicate"
+        key = "g"
+        prefixes.anyOf = ["Ctrl+K"]
+        command = "bar"
         "#;
 
         let result = parse_keybinding_data(data, None);
-        let report = result.errors.unwrap();
-
-        assert!(report[0].message.contains("Duplicate key"));
-        assert_eq!(report[0].range.start.line, 13);
-        assert_eq!(report[1].range.start.line, 4);
+        assert_eq!(result.n_errors(), 0);
+        let file = result.file.as_ref().unwrap();
+        assert_eq!(
+            unwrap_prefixes(&file.bind[2].prefixes),
+            &vec!["ctrl+k".to_string()]
+        );
+        assert!(file.key_bind.iter().any(|x| matches!(x,
+            BindingOutput::Do { key, .. } if key == "ctrl+shift+a"
+        )));
+        assert!(
+            result
+                .text_docs()
+                .unwrap()
+                .contains("<key-bind>Shift+Ctrl+A</key-bind> | foo")
+        );
     }
 
     #[test]
@@ -2883,7 +4372,403 @@ pub(crate) mod tests {
     }
 
     #[test]
-    fn mode_with_extra_when_binding_key_fails() {
+    fn mode_with_extra_when_binding_key_fails() {
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+
+        [[mode]]
+        name = "insert"
+        default = true
+        whenNoBinding = 'insertCharacters'
+
+        [[mode]]
+        name = "syminsert"
+        highlight = "Highlight"
+        cursorShape = "BlockOutline"
+        whenNoBinding.useMode = "insert"
+        whenNoBinding.x = "foo"
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        let report = result.errors.unwrap();
+
+        assert!(report[0].message.contains("`x`"));
+        assert_eq!(report[0].range.start.line, 15);
+        assert_eq!(report[0].range.end.line, 15);
+    }
+
+    #[test]
+    fn mode_appearance() {
+        let data = r##"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+
+        [[mode]]
+        name = "insert"
+        default = true
+        whenNoBinding = "insertCharacters"
+        color = "terminal.ansiGreen"
+
+        [[mode]]
+        name = "normal"
+        color = "#ff8800"
+        statusText = "N {{key.count}}"
+        lineNumbers = "Relative"
+        "##;
+
+        let mut result = parse_keybinding_data(data, None);
+        let insert = result.mode("insert").unwrap();
+        assert_eq!(insert.color, Some("terminal.ansiGreen".to_string()));
+        assert_eq!(insert.lineNumbers, None);
+        assert_eq!(insert.status_text(&mut result), "insert");
+
+        let normal = result.mode("normal").unwrap();
+        assert_eq!(normal.color, Some("#ff8800".to_string()));
+        assert_eq!(normal.lineNumbers, Some(LineNumbers::Relative));
+        let mut key = rhai::Map::new();
+        key.insert("count".into(), rhai::Dynamic::from(3_i64));
+        result.scope.state.set_or_push("key", key);
+        assert_eq!(normal.status_text(&mut result), "N 3");
+    }
+
+    #[test]
+    fn mode_appearance_errors() {
+        let data = r##"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+
+        [[mode]]
+        name = "insert"
+        default = true
+        whenNoBinding = "insertCharacters"
+        color = "#ff88"
+
+        [[mode]]
+        name = "normal"
+        color = "#ff88000"
+
+        [[mode]]
+        name = "visual"
+        statusText = "{{key.count +}}"
+        "##;
+
+        let result = parse_keybinding_data(data, None);
+        let report = result.errors.unwrap();
+        assert_eq!(report.len(), 2);
+        assert!(report[0].message.contains("not a hex color"));
+        assert_eq!(report[0].range.start.line, 14);
+        assert_eq!(report[1].range.start.line, 18);
+    }
+
+    #[test]
+    fn mode_binding_defaults() {
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+
+        [[kind]]
+        name = "motion"
+        description = "moves the cursor"
+
+        [[kind]]
+        name = "select"
+        description = "changes the selection"
+
+        [[mode]]
+        name = "normal"
+        default = true
+        whenNoBinding = "insertCharacters"
+
+        [[mode]]
+        name = "visual"
+        when = "editorHasSelection"
+        priority = 2
+        tags = ["selection"]
+        doc.kind = "select"
+
+        [[bind]]
+        key = "j"
+        mode = ["normal", "visual"]
+        command = "down"
+        tags = ["motion"]
+
+        [[bind]]
+        key = "k"
+        mode = "visual"
+        priority = 5
+        doc.kind = "motion"
+        command = "up"
+        "#;
+
+        let mut result = parse_keybinding_data(data, None);
+        let file = result.file.as_ref().unwrap();
+        let outputs: Vec<_> = file
+            .key_bind
+            .iter()
+            .filter_map(|x| match x {
+                BindingOutput::Do {
+                    key,
+                    when: Some(w),
+                    args,
+                } => Some((key.clone(), w.clone(), args.clone())),
+                _ => None,
+            })
+            .collect();
+
+        let (_, when, args) = outputs
+            .iter()
+            .find(|(key, _, args)| key == "j" && args.mode == "normal")
+            .unwrap();
+        assert!(!when.contains("editorHasSelection"));
+        assert_eq!(args.priority, 0.0);
+        assert_eq!(args.kind, "");
+
+        let (_, when, args) = outputs
+            .iter()
+            .find(|(key, _, args)| key == "j" && args.mode == "visual")
+            .unwrap();
+        assert!(when.contains("(editorHasSelection)"));
+        assert_eq!(args.priority, 2.0);
+        assert_eq!(args.kind, "select");
+
+        let (_, when, args) = outputs
+            .iter()
+            .find(|(key, _, args)| key == "k" && args.mode == "visual")
+            .unwrap();
+        assert!(when.contains("(editorHasSelection)"));
+        assert_eq!(args.priority, 5.0);
+        assert_eq!(args.kind, "motion");
+
+        let hash = hex::encode(file.hash);
+        result.scope.state.set_or_push("mode", "visual");
+        let binding = result.prepare_binding_to_run(0, &hash);
+        let tags: Vec<String> = binding
+            .tags
+            .iter()
+            .map(|x| x.clone().into_string().unwrap())
+            .collect();
+        assert_eq!(tags, ["motion", "selection"]);
+        assert_eq!(binding.doc.kind, Some("select".to_string()));
+
+        result.scope.state.set_or_push("mode", "normal");
+        let binding = result.prepare_binding_to_run(0, &hash);
+        assert_eq!(binding.tags.len(), 1);
+        assert_eq!(binding.doc.kind, None);
+    }
+
+    #[test]
+    fn mode_binding_defaults_undefined_kind() {
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+
+        [[mode]]
+        name = "normal"
+        default = true
+        whenNoBinding = "insertCharacters"
+        doc.kind = "motion"
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        let report = result.errors.unwrap();
+        assert_eq!(report.len(), 1);
+        assert!(report[0].message.contains("Undefined kind `motion`"));
+        assert_eq!(report[0].range.start.line, 10);
+    }
+
+    #[test]
+    fn mode_graph_warnings() {
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+
+        [[mode]]
+        name = "normal"
+        default = true
+
+        [[mode]]
+        name = "insert"
+        whenNoBinding = "insertCharacters"
+
+        [[mode]]
+        name = "visual"
+
+        [[mode]]
+        name = "orphan"
+
+        [[bind]]
+        key = "i"
+        command = "master-key.enterInsert"
+
+        [[bind]]
+        key = "escape"
+        mode = ["insert", "orphan"]
+        command = "master-key.enterNormal"
+
+        [[bind]]
+        key = "v"
+        command = "master-key.setMode"
+        args.value = "visual"
+
+        [[bind]]
+        key = "x"
+        command = "master-key.setMode"
+        args.value = "vsual"
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        assert!(result.file.is_some());
+        let report = result.errors.unwrap();
+        assert_eq!(report.len(), 3);
+        assert!(report[0].message.contains("undefined mode `vsual`"));
+        assert_eq!(report[0].range.start.line, 34);
+        assert!(
+            report[1]
+                .message
+                .contains("no binding leaves the mode `visual`")
+        );
+        assert_eq!(report[1].range.start.line, 14);
+        assert!(report[2].message.contains("`orphan` can never be entered"));
+        assert_eq!(report[2].range.start.line, 17);
+    }
+
+    #[test]
+    fn mode_graph_dot() {
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+
+        [[mode]]
+        name = "normal"
+        default = true
+
+        [[mode]]
+        name = "insert"
+        whenNoBinding = "insertCharacters"
+
+        [[mode]]
+        name = "pending"
+        returnAfter = 1
+
+        [[mode]]
+        name = "visual"
+        whenNoBinding.useMode = "normal"
+
+        [[bind]]
+        key = "i"
+        command = "master-key.enterInsert"
+
+        [[bind]]
+        key = "escape"
+        mode = "insert"
+        command = "master-key.enterNormal"
+
+        [[bind]]
+        key = "m"
+        command = "master-key.setMode"
+        args.value = '{{"pen" + "ding"}}'
+
+        [[bind]]
+        key = "v"
+        command = "master-key.setMode"
+        args.value = "{{`vis` + `ual`}}"
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        // `visual` is reachable through an expression, so it is not reported
+        assert_eq!(result.errors.as_ref().unwrap().len(), 0);
+        let dot = result.mode_graph().unwrap();
+        assert!(dot.starts_with("digraph modes {\n"));
+        assert!(dot.contains("    \"normal\" [shape=doublecircle];\n"));
+        assert!(dot.contains("    \"insert\" -> \"normal\" [label=\"escape\"];\n"));
+        assert!(dot.contains("    \"normal\" -> \"insert\" [label=\"i\"];\n"));
+        // bindings of `normal` are inherited by `visual`
+        assert!(dot.contains("    \"visual\" -> \"insert\" [label=\"i\"];\n"));
+        assert!(dot.contains("    \"pending\" -> \"(previous)\" [label=\"returnAfter\"];\n"));
+        assert!(dot.contains("    \"normal\" -> \"(computed)\" [label=\"m, v\"];\n"));
+    }
+
+    #[test]
+    fn mode_when_no_binding_rules() {
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+
+        [[mode]]
+        name = "insert"
+        default = true
+        whenNoBinding = "insertCharacters"
+
+        [[mode]]
+        name = "normal"
+        whenNoBinding = [
+            { keys = ["enter"], action = "insertCharacters" },
+            { keys = "{{keys(`[a-z]`)}}", action = "ignoreCharacters" },
+            { keys = "{{keys(`[0-9]`)}}", action.run = [{ command = "digit" }] },
+            { keys = ["enter", "tab"], action = "ignoreCharacters" },
+        ]
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        let result = result.file.unwrap();
+
+        let ignored: HashSet<_> = result
+            .key_bind
+            .iter()
+            .filter_map(|x| match x {
+                BindingOutput::Ignore { key, .. } => Some(key.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(ignored.len(), 26 * 2 + 2);
+        assert!(ignored.contains("shift+q"));
+        assert!(ignored.contains("tab"));
+        assert!(ignored.contains("shift+tab"));
+        assert!(!ignored.contains("enter"));
+        assert!(!ignored.contains("1"));
+        assert!(!ignored.contains(";"));
+
+        let normal = result.mode.get("normal").unwrap();
+        assert!(normal.captures_typing());
+        assert_eq!(
+            normal.typed_action("Q"),
+            WhenNoBindingHeader::IgnoreCharacters
+        );
+        assert_eq!(
+            normal.typed_action("\n"),
+            WhenNoBindingHeader::InsertCharacters
+        );
+        assert_eq!(normal.typed_action("!"), WhenNoBindingHeader::Run);
+        assert_eq!(
+            normal.typed_action(":"),
+            WhenNoBindingHeader::InsertCharacters
+        );
+        assert_eq!(
+            normal.typed_action("é"),
+            WhenNoBindingHeader::InsertCharacters
+        );
+        assert!(!result.mode.get("insert").unwrap().captures_typing());
+    }
+
+    #[test]
+    fn mode_when_no_binding_rule_errors() {
         let data = r#"
         #:master-keybindings
 
@@ -2893,22 +4778,19 @@ pub(crate) mod tests {
         [[mode]]
         name = "insert"
         default = true
-        whenNoBinding = 'insertCharacters'
+        whenNoBinding = "insertCharacters"
 
         [[mode]]
-        name = "syminsert"
-        highlight = "Highlight"
-        cursorShape = "BlockOutline"
-        whenNoBinding.useMode = "insert"
-        whenNoBinding.x = "foo"
+        name = "normal"
+        whenNoBinding = [
+            { keys = "{{keys(`[a-z]`)}}", action.useMode = "insert" },
+        ]
         "#;
 
         let result = parse_keybinding_data(data, None);
         let report = result.errors.unwrap();
-
-        assert!(report[0].message.contains("`x`"));
-        assert_eq!(report[0].range.start.line, 15);
-        assert_eq!(report[0].range.end.line, 15);
+        assert!(report[0].message.contains("`useMode` is not supported"));
+        assert_eq!(report[0].range.start.line, 13);
     }
 
     #[test]
@@ -2994,6 +4876,276 @@ pub(crate) mod tests {
         assert_eq!(normal_fallback.collect::<Vec<_>>().len(), 4);
     }
 
+    #[test]
+    fn mode_extends_inherits_bindings() {
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+
+        [[mode]]
+        name = "normal"
+        default = true
+        cursorShape = "Block"
+        highlight = "Highlight"
+
+        [[mode]]
+        name = "insert"
+        whenNoBinding = "insertCharacters"
+
+        [[mode]]
+        name = "visual"
+        extends = "normal"
+        highlight = "Alert"
+
+        [[mode]]
+        name = "vline"
+        extends = "visual"
+
+        [[bind]]
+        key = "h"
+        command = "left"
+
+        [[bind]]
+        key = "j"
+        command = "down"
+
+        [[bind]]
+        key = "j"
+        mode = "visual"
+        command = "visualDown"
+
+        [[bind]]
+        key = "k"
+        command = "up"
+
+        [[bind]]
+        key = "k"
+        mode = "visual"
+        when = "editorHasSelection"
+        command = "visualUp"
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        let result = result.file.unwrap();
+
+        let vline = result.mode.get("vline").unwrap();
+        assert!(matches!(vline.cursorShape, CursorShape::Block));
+        assert!(matches!(vline.highlight, ModeHighlight::Alert));
+
+        let vline_commands: Vec<_> = result
+            .key_bind
+            .iter()
+            .filter_map(|x| match x {
+                BindingOutput::Do {
+                    key,
+                    when: Some(w),
+                    args: BindingOutputArgs { command_id, .. },
+                    ..
+                } if w.contains("master-key.mode == 'vline'") => Some((
+                    key.clone(),
+                    result.bind[*command_id as usize].commands[0]
+                        .command
+                        .clone(),
+                )),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(vline_commands.len(), 3);
+        assert!(vline_commands.contains(&("h".to_string(), "left".to_string())));
+        assert!(vline_commands.contains(&("j".to_string(), "visualDown".to_string())));
+        // a different `when` clause still shadows the inherited binding
+        assert!(vline_commands.contains(&("k".to_string(), "visualUp".to_string())));
+    }
+
+    #[test]
+    fn mode_enter_exit_commands() {
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+
+        [[mode]]
+        name = "normal"
+        default = true
+        whenNoBinding = "insertCharacters"
+
+        [[mode]]
+        name = "visual"
+        onExit = [{ command = "cancelSelection" }]
+
+        [[mode.onEnter]]
+        command = "selection-utilities.activeAtEnd"
+
+        [[mode.onEnter]]
+        command = "runCommands"
+        args.commands = ["a", { command = "b", args.value = "{{val.x}}" }]
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        let result = result.file.unwrap();
+        let visual = result.mode.get("visual").unwrap();
+        let enter: Vec<_> = visual.onEnter.iter().map(|x| x.command.as_str()).collect();
+        assert_eq!(enter, ["selection-utilities.activeAtEnd", "a", "b"]);
+        let exit: Vec<_> = visual.onExit.iter().map(|x| x.command.as_str()).collect();
+        assert_eq!(exit, ["cancelSelection"]);
+        assert!(result.mode.get("normal").unwrap().onEnter.is_empty());
+    }
+
+    #[test]
+    fn mode_enter_commands_error() {
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+
+        [[mode]]
+        name = "normal"
+        default = true
+        whenNoBinding = "insertCharacters"
+
+        [[mode]]
+        name = "visual"
+
+        [[mode.onEnter]]
+        command = "runCommands"
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        let report = result.errors.unwrap();
+        assert!(report[0].message.contains("must have `args` field"));
+        assert_eq!(report[0].range.start.line, 14);
+    }
+
+    #[test]
+    fn mode_return_after() {
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+
+        [[mode]]
+        name = "insert"
+        default = true
+        whenNoBinding = "insertCharacters"
+
+        [[mode]]
+        name = "normal"
+
+        [[mode]]
+        name = "oneshot"
+        returnAfter = 2
+
+        [[mode]]
+        name = "tonormal"
+        returnTo = "normal"
+        "#;
+
+        let mut result = parse_keybinding_data(data, None);
+        let oneshot = result.mode("oneshot").unwrap();
+        assert_eq!(oneshot.returnAfter, Some(2));
+        assert_eq!(oneshot.returnTo, None);
+        let tonormal = result.mode("tonormal").unwrap();
+        assert_eq!(tonormal.returnAfter, Some(1));
+
+        let mut binding = ReifiedBinding::noop(&result.scope);
+        binding.mode = "oneshot".to_string();
+        result.enter_mode("insert", "oneshot");
+        assert_eq!(result.complete_binding(&binding, &binding.mode), None);
+        assert_eq!(
+            result.complete_binding(&binding, &binding.mode),
+            Some("insert".to_string())
+        );
+        assert_eq!(result.complete_binding(&binding, &binding.mode), None);
+
+        // bindings from other modes, or incomplete bindings do not count
+        result.enter_mode("insert", "tonormal");
+        assert_eq!(result.complete_binding(&binding, "tonormal"), None);
+        binding.mode = "tonormal".to_string();
+        binding.finalKey = false;
+        assert_eq!(result.complete_binding(&binding, &binding.mode), None);
+        binding.finalKey = true;
+        assert_eq!(
+            result.complete_binding(&binding, &binding.mode),
+            Some("normal".to_string())
+        );
+
+        // leaving the mode explicitly cancels the return
+        result.enter_mode("insert", "tonormal");
+        result.enter_mode("tonormal", "insert");
+        assert_eq!(result.complete_binding(&binding, &binding.mode), None);
+
+        // a binding that switches modes itself is not overridden by the return
+        binding.mode = "oneshot".to_string();
+        result.enter_mode("insert", "oneshot");
+        assert_eq!(result.complete_binding(&binding, "oneshot"), None);
+        assert_eq!(result.complete_binding(&binding, "normal"), None);
+    }
+
+    #[test]
+    fn mode_return_after_errors() {
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+
+        [[mode]]
+        name = "insert"
+        default = true
+        whenNoBinding = "insertCharacters"
+
+        [[mode]]
+        name = "other"
+        returnTo = "other"
+
+        [[mode]]
+        name = "oneshot"
+        returnAfter = 0
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        let report = result.errors.unwrap();
+        assert_eq!(report.len(), 2);
+        assert!(report[0].message.contains("different mode"));
+        assert_eq!(report[0].range.start.line, 13);
+        assert!(report[1].message.contains("at least 1"));
+        assert_eq!(report[1].range.start.line, 17);
+    }
+
+    #[test]
+    fn mode_extends_cycle_error() {
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+
+        [[mode]]
+        name = "normal"
+        default = true
+        whenNoBinding = "insertCharacters"
+
+        [[mode]]
+        name = "a"
+        extends = "b"
+
+        [[mode]]
+        name = "b"
+        extends = "a"
+        "#;
+
+        let result = parse_keybinding_data(data, None);
+        let report = result.errors.unwrap();
+        assert_eq!(report.len(), 1);
+        assert!(report[0].message.contains("cycle: `a` → `b` → `a`"));
+        assert_eq!(report[0].range.start.line, 13);
+    }
+
     #[test]
     fn indexing_binding_resolution() {
         let data = r#"
@@ -3222,6 +5374,161 @@ pub(crate) mod tests {
         assert!(report[0].message.contains("string: "))
     }
 
+    #[test]
+    fn tables_preserve_order() {
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+
+        [[define.val]]
+        zebra = 1
+        apple = 2
+        mango = { z = 1, a = 2, m = 3 }
+
+        [[bind]]
+        key = "a"
+        command = "foo"
+        args = { zz = 1, aa = '{{val.mango}}', mm = { y = 1, b = 2 } }
+        "#;
+
+        let mut result = parse_keybinding_data(data, None);
+        let file = result.file.as_ref().unwrap();
+        let vals: Vec<_> = file.define.val.keys().collect();
+        assert_eq!(vals, ["zebra", "apple", "mango"]);
+
+        let command = result.scope.expand(&file.bind[0].commands[0]).unwrap();
+        let args: toml::Value = command.args.into();
+        let args = args.as_table().unwrap();
+        assert_eq!(args.keys().collect::<Vec<_>>(), ["zz", "aa", "mm"]);
+        let inner = args["mm"].as_table().unwrap();
+        assert_eq!(inner.keys().collect::<Vec<_>>(), ["y", "b"]);
+        // values that pass through expressions are ordered by key
+        let mango = args["aa"].as_table().unwrap();
+        assert_eq!(mango.keys().collect::<Vec<_>>(), ["a", "m", "z"]);
+    }
+
+    #[test]
+    fn expression_trace_records_evaluation() {
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+
+        [[define.val]]
+        step = 2
+
+        [[bind]]
+        key = "a"
+        command = "foo"
+        args.value = '{{val.step * 3}}'
+        args.bad = '{{val.step + "x" - 1}}'
+        args.text = '{{"val.other: " + val.step.to_string()}}'
+        "#;
+
+        let mut result = parse_keybinding_data(data, None);
+        let command = result.file.as_ref().unwrap().bind[0].commands[0].clone();
+        result.scope.expand(&command).unwrap_err();
+        assert!(result.expression_trace().is_empty());
+
+        result.set_expression_tracing(true);
+        result.scope.expand(&command).unwrap_err();
+        let mut trace = result.expression_trace();
+        trace.sort_by(|a, b| a.expression.cmp(&b.expression));
+        // names inside strings are not variables
+        let text = trace.remove(0);
+        assert_eq!(text.result.as_ref().unwrap(), r#""val.other: 2""#);
+        assert_eq!(
+            text.variables,
+            vec![TracedVariable {
+                name: "val.step".to_string(),
+                value: "2".to_string()
+            }]
+        );
+
+        assert_eq!(trace.len(), 2);
+        assert_eq!(trace[0].expression, "val.step * 3");
+        assert_eq!(trace[0].result.as_ref().unwrap(), "6");
+        assert_eq!(trace[0].error, None);
+        assert_eq!(
+            trace[0].variables,
+            vec![TracedVariable {
+                name: "val.step".to_string(),
+                value: "2".to_string()
+            }]
+        );
+        assert_eq!(trace[0].range.start.line, 12);
+        assert_eq!(trace[1].expression, r#"val.step + "x" - 1"#);
+        assert!(trace[1].result.is_none());
+        assert!(trace[1].error.as_ref().unwrap().contains("-"));
+        assert!(result.expression_trace().is_empty());
+    }
+
+    #[test]
+    fn expression_profile_reports_timing() {
+        let data = r#"
+        #:master-keybindings
+
+        [header]
+        version = "2.0.0"
+
+        [[bind]]
+        key = "a"
+        command = "foo"
+        args.value = '{{1 + 2}}'
+
+        [[bind]]
+        foreach.i = ['{{keys(`[0-9]`)}}']
+        key = "b {{i}}"
+        command = "bar"
+        args.x = '{{2 * 3}}'
+        args.y = '{{i}}'
+
+        [tree.space]
+        c = { command = "baz", args.z = '{{3 * 4}}' }
+        d = { command = "biz", args.z = '{{4 * 5}}' }
+        "#;
+
+        let mut result = parse_keybinding_data(data, None);
+        let expressions = result.expression_profile(10);
+        let value = expressions
+            .iter()
+            .find(|x| x.expression == "1 + 2")
+            .unwrap();
+        assert_eq!(value.range.as_ref().unwrap().start.line, 9);
+        assert_eq!(value.evaluations, 0);
+        let keys = expressions.iter().find(|x| x.expression == "i").unwrap();
+        assert_eq!(keys.evaluations, 10);
+        assert_eq!(result.expression_profile(1).len(), 1);
+
+        let bindings = result.binding_profile(10);
+        assert_eq!(bindings.len(), 4);
+        let binding = bindings.iter().find(|x| x.key == "a").unwrap();
+        assert_eq!(binding.range.as_ref().unwrap().start.line, 6);
+        assert_eq!(binding.expressions, 1);
+        let binding = bindings.iter().find(|x| x.key == "b 0").unwrap();
+        assert_eq!(binding.range.as_ref().unwrap().start.line, 11);
+        assert_eq!(binding.expressions, 4);
+        // entries of `[tree]` share a table, but not their expressions
+        for key in ["space c", "space d"] {
+            let binding = bindings.iter().find(|x| x.key == key).unwrap();
+            assert_eq!(binding.expressions, 1);
+        }
+
+        result.clear_profile();
+        assert!(result.expression_profile(10).is_empty());
+        let command = result.file.as_ref().unwrap().bind[0].commands[0].clone();
+        result.scope.expand(&command).unwrap();
+        result.scope.expand(&command).unwrap();
+        let expressions = result.expression_profile(10);
+        assert_eq!(expressions.len(), 1);
+        assert_eq!(expressions[0].evaluations, 2);
+        assert_eq!(expressions[0].compile_ms, 0.0);
+        assert_eq!(result.binding_profile(10)[0].key, "a");
+    }
+
     #[test]
     fn tags_resolve_from_default() {
         let data = r#"
@@ -3400,7 +5707,7 @@ pub(crate) mod tests {
             ])
         );
         assert_eq!(
-            result.binding_section(51).unwrap().names,
+            result.binding_section(52).unwrap().names,
             Vec::from([
                 "Test Documentation".to_string(),
                 "Second Section".to_string()
@@ -3434,7 +5741,7 @@ pub(crate) mod tests {
             &"l"
         );
         assert_eq!(
-            &result.file.as_ref().unwrap().bind[51]
+            &result.file.as_ref().unwrap().bind[52]
                 .key
                 .first()
                 .as_ref()
@@ -4030,10 +6337,10 @@ pub(crate) mod tests {
         let result = result.file.unwrap();
         assert_eq!(result.bind.len(), 319);
 
-        assert!(FileDocSection::write_markdown(&result.docs, true).len() > 0);
+        assert!(FileDocSection::write_markdown(&result.docs, true, &|k| k.to_string()).len() > 0);
         // info!(
         //     "docs: {}",
-        //     FileDocSection::write_markdown(&result.docs, true)
+        //     FileDocSection::write_markdown(&result.docs, true, &|k| k.to_string())
         // )
     }
 
@@ -4048,10 +6355,10 @@ pub(crate) mod tests {
         let result = result.file.unwrap();
         assert_eq!(result.bind.len(), 135);
 
-        assert!(FileDocSection::write_markdown(&result.docs, true).len() > 0);
+        assert!(FileDocSection::write_markdown(&result.docs, true, &|k| k.to_string()).len() > 0);
         // info!(
         //     "docs: {}",
-        //     FileDocSection::write_markdown(&result.docs, true)
+        //     FileDocSection::write_markdown(&result.docs, true, &|k| k.to_string())
         // )
     }
 }
@@ -0,0 +1,564 @@
+#[allow(unused_imports)]
+use log::info;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+pub mod display;
+pub mod notation;
+
+//
+// ---------------- Key Vocabulary ----------------
+//
+
+// Every key that can be bound is listed here, once, under two names: the name VSCode uses
+// in `keybindings.json` (e.g. `a`) and the layout independent code of the physical key
+// (e.g. `KeyA`), which is written in brackets in a binding (e.g. `[KeyA]`). The codes are
+// those of
+// [`KeyboardEvent.code`](https://developer.mozilla.org/en-US/docs/Web/API/KeyboardEvent/code).
+// The list follows VSCode's keyboard mapper: some keys only have a name (e.g.
+// `numpad_separator`) and others only a code (e.g. `NumpadEnter`, which VSCode names
+// `enter`). Validation, `keys()` (see `foreach.rs`) and `all_characters` all read from this
+// list.
+
+// a key that can be bound
+struct Key {
+    // the name of the key in `keybindings.json`; empty if VSCode has no name for it
+    name: &'static str,
+    // the layout independent code of the key; empty if it has no code of its own
+    code: &'static str,
+    // true if the key types a character (on a US keyboard)
+    character: bool,
+}
+
+const fn key(name: &'static str, code: &'static str) -> Key {
+    return Key {
+        name,
+        code,
+        character: false,
+    };
+}
+
+const fn char_key(name: &'static str, code: &'static str) -> Key {
+    return Key {
+        name,
+        code,
+        character: true,
+    };
+}
+
+// in the order of the physical keys' codes
+const KEYS: [Key; 133] = [
+    char_key("a", "KeyA"),
+    char_key("b", "KeyB"),
+    char_key("c", "KeyC"),
+    char_key("d", "KeyD"),
+    char_key("e", "KeyE"),
+    char_key("f", "KeyF"),
+    char_key("g", "KeyG"),
+    char_key("h", "KeyH"),
+    char_key("i", "KeyI"),
+    char_key("j", "KeyJ"),
+    char_key("k", "KeyK"),
+    char_key("l", "KeyL"),
+    char_key("m", "KeyM"),
+    char_key("n", "KeyN"),
+    char_key("o", "KeyO"),
+    char_key("p", "KeyP"),
+    char_key("q", "KeyQ"),
+    char_key("r", "KeyR"),
+    char_key("s", "KeyS"),
+    char_key("t", "KeyT"),
+    char_key("u", "KeyU"),
+    char_key("v", "KeyV"),
+    char_key("w", "KeyW"),
+    char_key("x", "KeyX"),
+    char_key("y", "KeyY"),
+    char_key("z", "KeyZ"),
+    char_key("0", "Digit0"),
+    char_key("1", "Digit1"),
+    char_key("2", "Digit2"),
+    char_key("3", "Digit3"),
+    char_key("4", "Digit4"),
+    char_key("5", "Digit5"),
+    char_key("6", "Digit6"),
+    char_key("7", "Digit7"),
+    char_key("8", "Digit8"),
+    char_key("9", "Digit9"),
+    char_key("enter", "Enter"),
+    key("escape", "Escape"),
+    key("backspace", "Backspace"),
+    char_key("tab", "Tab"),
+    char_key("space", "Space"),
+    char_key("-", "Minus"),
+    char_key("=", "Equal"),
+    char_key("[", "BracketLeft"),
+    char_key("]", "BracketRight"),
+    char_key("\\", "Backslash"),
+    char_key(";", "Semicolon"),
+    char_key("'", "Quote"),
+    char_key("`", "Backquote"),
+    char_key(",", "Comma"),
+    char_key(".", "Period"),
+    char_key("/", "Slash"),
+    key("capslock", "CapsLock"),
+    key("f1", "F1"),
+    key("f2", "F2"),
+    key("f3", "F3"),
+    key("f4", "F4"),
+    key("f5", "F5"),
+    key("f6", "F6"),
+    key("f7", "F7"),
+    key("f8", "F8"),
+    key("f9", "F9"),
+    key("f10", "F10"),
+    key("f11", "F11"),
+    key("f12", "F12"),
+    key("", "PrintScreen"),
+    key("scrolllock", "ScrollLock"),
+    key("pausebreak", "Pause"),
+    key("insert", "Insert"),
+    key("home", "Home"),
+    key("pageup", "PageUp"),
+    key("delete", "Delete"),
+    key("end", "End"),
+    key("pagedown", "PageDown"),
+    key("right", "ArrowRight"),
+    key("left", "ArrowLeft"),
+    key("down", "ArrowDown"),
+    key("up", "ArrowUp"),
+    key("numlock", "NumLock"),
+    key("numpad_divide", "NumpadDivide"),
+    key("numpad_multiply", "NumpadMultiply"),
+    key("numpad_subtract", "NumpadSubtract"),
+    key("numpad_add", "NumpadAdd"),
+    key("", "NumpadEnter"),
+    key("numpad0", "Numpad0"),
+    key("numpad1", "Numpad1"),
+    key("numpad2", "Numpad2"),
+    key("numpad3", "Numpad3"),
+    key("numpad4", "Numpad4"),
+    key("numpad5", "Numpad5"),
+    key("numpad6", "Numpad6"),
+    key("numpad7", "Numpad7"),
+    key("numpad8", "Numpad8"),
+    key("numpad9", "Numpad9"),
+    key("numpad_decimal", "NumpadDecimal"),
+    key("numpad_separator", ""),
+    key("", "NumpadEqual"),
+    key("oem_102", "IntlBackslash"),
+    key("contextmenu", "ContextMenu"),
+    key("f13", "F13"),
+    key("f14", "F14"),
+    key("f15", "F15"),
+    key("f16", "F16"),
+    key("f17", "F17"),
+    key("f18", "F18"),
+    key("f19", "F19"),
+    key("f20", "F20"),
+    key("f21", "F21"),
+    key("f22", "F22"),
+    key("f23", "F23"),
+    key("f24", "F24"),
+    key("abnt_c1", "IntlRo"),
+    key("abnt_c2", "NumpadComma"),
+    key("", "IntlYen"),
+    key("oem_8", ""),
+    key("audiovolumemute", "AudioVolumeMute"),
+    key("audiovolumeup", "AudioVolumeUp"),
+    key("audiovolumedown", "AudioVolumeDown"),
+    key("mediatracknext", "MediaTrackNext"),
+    key("mediatrackprevious", "MediaTrackPrevious"),
+    key("mediastop", "MediaStop"),
+    key("mediaplaypause", "MediaPlayPause"),
+    key("launchmediaplayer", "MediaSelect"),
+    key("launchmail", "LaunchMail"),
+    key("", "LaunchApp1"),
+    key("launchapp2", "LaunchApp2"),
+    key("browsersearch", "BrowserSearch"),
+    key("browserhome", "BrowserHome"),
+    key("browserback", "BrowserBack"),
+    key("browserforward", "BrowserForward"),
+    key("", "BrowserStop"),
+    key("", "BrowserRefresh"),
+    key("", "BrowserFavorites"),
+];
+
+lazy_static! {
+    static ref BRACKETED_CODE: Regex = Regex::new(r"\[([A-Za-z0-9]+)\]").unwrap();
+}
+
+// the key with the given name (case insensitive)
+fn find_name(name: &str) -> Option<&'static Key> {
+    return KEYS
+        .iter()
+        .find(|k| !k.name.is_empty() && k.name.eq_ignore_ascii_case(name));
+}
+
+// the key with the given layout independent code (case insensitive)
+fn find_code(code: &str) -> Option<&'static Key> {
+    return KEYS
+        .iter()
+        .find(|k| !k.code.is_empty() && k.code.eq_ignore_ascii_case(code));
+}
+
+// the layout independent code for a key name (e.g. `a` => `KeyA`)
+fn code_for_name(name: &str) -> Option<&'static str> {
+    return find_name(name).map(|k| k.code).filter(|x| !x.is_empty());
+}
+
+// the key name for a layout independent code (e.g. `KeyA` => `a`)
+fn name_for_code(code: &str) -> Option<&'static str> {
+    return find_code(code).map(|k| k.name).filter(|x| !x.is_empty());
+}
+
+/// True if `key` (without modifiers) is a key name (e.g. `a`) or a bracketed layout
+/// independent code (e.g. `[KeyA]`). Both are case insensitive.
+pub(crate) fn is_valid_key(key: &str) -> bool {
+    if let Some(code) = key.strip_prefix('[').and_then(|x| x.strip_suffix(']')) {
+        return find_code(code).is_some();
+    }
+    return find_name(key).is_some();
+}
+
+/// All valid keys: the key names followed by the bracketed layout independent codes
+pub(crate) fn all_keys() -> impl Iterator<Item = String> {
+    let names = KEYS
+        .iter()
+        .filter(|k| !k.name.is_empty())
+        .map(|k| k.name.to_string());
+    let codes = KEYS
+        .iter()
+        .filter(|k| !k.code.is_empty())
+        .map(|k| format!("[{}]", k.code));
+    return names.chain(codes);
+}
+
+/// The names of the keys that type a character (e.g. `a`, `;` or `space`)
+pub(crate) fn character_keys() -> impl Iterator<Item = &'static str> {
+    return KEYS.iter().filter(|k| k.character).map(|k| k.name);
+}
+
+// the unshifted key of each character typed with `shift` on a US keyboard
+const SHIFTED: [(char, char); 21] = [
+    ('~', '`'),
+    ('!', '1'),
+    ('@', '2'),
+    ('#', '3'),
+    ('$', '4'),
+    ('%', '5'),
+    ('^', '6'),
+    ('&', '7'),
+    ('*', '8'),
+    ('(', '9'),
+    (')', '0'),
+    ('_', '-'),
+    ('+', '='),
+    ('{', '['),
+    ('}', ']'),
+    ('|', '\\'),
+    (':', ';'),
+    ('"', '\''),
+    ('<', ','),
+    ('>', '.'),
+    ('?', '/'),
+];
+
+/// The key press that types `c` with `shift` on a US keyboard (e.g. `A` is typed by
+/// `shift+a` and `:` by `shift+;`), if any
+pub(crate) fn shifted_press(c: char) -> Option<String> {
+    if c.is_ascii_uppercase() {
+        return Some(format!("shift+{}", c.to_ascii_lowercase()));
+    }
+    let (_, base) = SHIFTED.iter().find(|(x, _)| *x == c)?;
+    return Some(format!("shift+{base}"));
+}
+
+// modifiers, in the order VSCode lists them
+const MODIFIERS: [&str; 6] = ["ctrl", "shift", "alt", "meta", "cmd", "win"];
+
+// VSCode reads `cmd` and `win` as other names for `meta`
+fn canonical_modifier(modifier: &str) -> String {
+    let modifier = modifier.to_lowercase();
+    return match modifier.as_str() {
+        "cmd" | "win" => "meta".to_string(),
+        _ => modifier,
+    };
+}
+
+/// The canonical spelling of a key press: its modifiers in lowercase and in a fixed order,
+/// followed by the key as listed above, e.g. `Shift+Ctrl+A` becomes `ctrl+shift+a` and
+/// `[keya]` becomes `[KeyA]`; `cmd` and `win` become `meta`. Two spellings of the same key
+/// press have the same canonical form.
+pub(crate) fn canonical_press(press: &str) -> String {
+    let mut parts: Vec<_> = press.split('+').collect();
+    let key = parts.pop().unwrap_or_default();
+    let key = match key.strip_prefix('[').and_then(|x| x.strip_suffix(']')) {
+        Some(code) => match find_code(code) {
+            Some(k) => format!("[{}]", k.code),
+            Option::None => key.to_string(),
+        },
+        Option::None => key.to_lowercase(),
+    };
+    let mut modifiers: Vec<_> = parts.iter().map(|x| canonical_modifier(x)).collect();
+    modifiers.sort_by_key(|x| MODIFIERS.iter().position(|m| m == x));
+    modifiers.dedup();
+    modifiers.push(key);
+    return modifiers.join("+");
+}
+
+/// The canonical spelling of each key press in a sequence (e.g. `Space F`)
+pub(crate) fn canonical_sequence(keys: &str) -> String {
+    return keys
+        .split_whitespace()
+        .map(canonical_press)
+        .collect::<Vec<_>>()
+        .join(" ");
+}
+
+/// Replaces each key name in a sequence of key presses (e.g. `ctrl+w a`) with its layout
+/// independent code (e.g. `ctrl+[KeyW] [KeyA]`); modifiers and codes are left as is.
+#[wasm_bindgen]
+pub fn layout_independent_key(key: &str) -> String {
+    return key
+        .split(' ')
+        .map(|press| {
+            let mut parts: Vec<_> = press.split('+').map(String::from).collect();
+            if let Some(last) = parts.last_mut()
+                && let Some(code) = code_for_name(last)
+            {
+                *last = format!("[{code}]");
+            }
+            parts.join("+")
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+}
+
+/// Replaces each bracketed layout independent code in `key` (e.g. `ctrl+[KeyW]`) with its
+/// key name, for display: `[w]`, or `w` when `no_brackets` is true. Any other text is left
+/// as is.
+#[wasm_bindgen]
+pub fn simplify_layout_independent_key(key: &str, no_brackets: bool) -> String {
+    return BRACKETED_CODE
+        .replace_all(key, |captures: &regex::Captures| {
+            let code = &captures[1];
+            return match (name_for_code(code), no_brackets) {
+                (Some(name), true) => name.to_string(),
+                (Some(name), false) => format!("[{name}]"),
+                (Option::None, _) => captures[0].to_string(),
+            };
+        })
+        .into_owned();
+}
+
+//
+// ---------------- Keyboard Layouts ----------------
+//
+
+// The keys of the US layout that produce a character, by row; the rows of each layout list
+// the characters produced by the same physical keys
+const US_ROWS: [&str; 4] = [
+    "`1234567890-=",
+    "qwertyuiop[]\\",
+    "asdfghjkl;'",
+    "zxcvbnm,./",
+];
+const DVORAK_ROWS: [&str; 4] = [
+    "`1234567890[]",
+    "',.pyfgcrl/=\\",
+    "aoeuidhtns-",
+    ";qjkxbmwvz",
+];
+const COLEMAK_ROWS: [&str; 4] = [
+    "`1234567890-=",
+    "qwfpgjluy;[]\\",
+    "arstdhneio'",
+    "zxcvbkm,./",
+];
+const AZERTY_ROWS: [&str; 4] = [
+    "²&é\"'(-è_çà)=",
+    "azertyuiop^$*",
+    "qsdfghjklmù",
+    "wxcvbn,;:!",
+];
+const QWERTZ_ROWS: [&str; 4] = [
+    "^1234567890ß´",
+    "qwertzuiopü+#",
+    "asdfghjklöä",
+    "yxcvbnm,.-",
+];
+
+// the keyboard layout a file's bindings are placed on (see `header.layout`)
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyboardLayout {
+    #[default]
+    Qwerty,
+    Dvorak,
+    Colemak,
+    Azerty,
+    Qwertz,
+}
+
+impl KeyboardLayout {
+    fn rows(&self) -> [&'static str; 4] {
+        return match self {
+            KeyboardLayout::Qwerty => US_ROWS,
+            KeyboardLayout::Dvorak => DVORAK_ROWS,
+            KeyboardLayout::Colemak => COLEMAK_ROWS,
+            KeyboardLayout::Azerty => AZERTY_ROWS,
+            KeyboardLayout::Qwertz => QWERTZ_ROWS,
+        };
+    }
+
+    /// The character this layout labels the US key `key` with (e.g. `é` for `2` on
+    /// AZERTY); `None` for keys that do not produce a character
+    pub(crate) fn label(&self, key: &str) -> Option<String> {
+        let key = key.to_lowercase();
+        for (us, row) in US_ROWS.iter().zip(self.rows()) {
+            if let Some(i) = us.chars().position(|c| c.to_string() == key) {
+                return Some(row.chars().nth(i)?.to_string());
+            }
+        }
+        return None;
+    }
+
+    // the key at the same physical position as the US key `key`
+    fn remap_key(&self, key: &str) -> Option<String> {
+        let target = self.label(key)?;
+        if code_for_name(&target).is_some() {
+            return Some(target);
+        } else {
+            return Some(format!("[{}]", code_for_name(key)?));
+        }
+    }
+
+    /// Rewrites a sequence of key presses (e.g. `ctrl+k j`), written for the US layout, so
+    /// that each key is at the same physical position on this layout
+    pub(crate) fn remap(&self, key: &str) -> String {
+        if *self == KeyboardLayout::Qwerty {
+            return key.to_string();
+        }
+        return key
+            .split(' ')
+            .map(|press| {
+                let mut parts: Vec<_> = press.split('+').map(String::from).collect();
+                if let Some(last) = parts.last_mut()
+                    && let Some(remapped) = self.remap_key(last)
+                {
+                    *last = remapped;
+                }
+                parts.join("+")
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+    }
+}
+
+/// All keys that can be bound, both as key names and as layout independent codes
+#[wasm_bindgen]
+#[cfg_attr(coverage_nightly, coverage(off))]
+pub fn valid_keys() -> Vec<String> {
+    return all_keys().collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_vocabulary() {
+        assert!(is_valid_key("a"));
+        assert!(is_valid_key("PageUp"));
+        assert!(is_valid_key("f13"));
+        assert!(is_valid_key("[F19]"));
+        assert!(is_valid_key("[keya]"));
+        assert!(is_valid_key("'"));
+        assert!(!is_valid_key("f0"));
+        assert!(!is_valid_key("[KeyAA]"));
+        assert!(!is_valid_key("foo"));
+
+        assert!(is_valid_key("f24"));
+        assert!(is_valid_key("[IntlBackslash]"));
+        assert!(is_valid_key("[NumpadEnter]"));
+        assert!(is_valid_key("audiovolumeup"));
+        assert!(is_valid_key("numpad_separator"));
+        assert!(!is_valid_key("f25"));
+        assert_eq!(code_for_name("abnt_c2"), Some("NumpadComma"));
+        assert_eq!(code_for_name("numpad_separator"), None);
+
+        // every accepted key round trips
+        for key in all_keys() {
+            assert!(is_valid_key(&key), "{key}");
+            assert_eq!(canonical_press(&key), key);
+            assert_eq!(canonical_press(&key.to_uppercase()), key);
+            let independent = layout_independent_key(&key);
+            let simple = simplify_layout_independent_key(&independent, true);
+            assert_eq!(layout_independent_key(&simple), independent);
+        }
+        for k in KEYS
+            .iter()
+            .filter(|k| !k.name.is_empty() && !k.code.is_empty())
+        {
+            let independent = layout_independent_key(k.name);
+            assert_eq!(independent, format!("[{}]", k.code));
+            assert_eq!(simplify_layout_independent_key(&independent, true), k.name);
+        }
+
+        // each name and code is listed once
+        let keys: Vec<_> = all_keys().map(|k| k.to_lowercase()).collect();
+        let unique: std::collections::HashSet<_> = keys.iter().collect();
+        assert_eq!(keys.len(), unique.len());
+        assert_eq!(character_keys().count(), 26 + 10 + 11 + 3);
+    }
+
+    #[test]
+    fn layout_independent_conversion() {
+        assert_eq!(
+            layout_independent_key("ctrl+shift+w escape [KeyA]"),
+            "ctrl+shift+[KeyW] [Escape] [KeyA]"
+        );
+        assert_eq!(
+            simplify_layout_independent_key("^[KEYW], [SPACE] [Unknown]", false),
+            "^[w], [space] [Unknown]"
+        );
+    }
+
+    #[test]
+    fn canonical_key_presses() {
+        assert_eq!(canonical_press("Shift+Ctrl+A"), "ctrl+shift+a");
+        assert_eq!(
+            canonical_press("alt+cmd+ctrl+[keya]"),
+            "ctrl+alt+meta+[KeyA]"
+        );
+        assert_eq!(canonical_press("Win+shift+b"), "shift+meta+b");
+        assert_eq!(canonical_press("cmd+meta+b"), "meta+b");
+        assert_eq!(canonical_press("shift+shift+Escape"), "shift+escape");
+        assert_eq!(canonical_sequence("Space  Shift+;"), "space shift+;");
+    }
+
+    #[test]
+    fn keyboard_layouts() {
+        assert_eq!(KeyboardLayout::Colemak.remap("ctrl+k j"), "ctrl+e n");
+        assert_eq!(
+            KeyboardLayout::Dvorak.remap("shift+; q escape"),
+            "shift+s ' escape"
+        );
+        assert_eq!(KeyboardLayout::Azerty.remap("a 2 ;"), "q [Digit2] m");
+        assert_eq!(KeyboardLayout::Qwertz.remap("z y [KeyA]"), "y z [KeyA]");
+        assert_eq!(KeyboardLayout::Qwerty.remap("K"), "K");
+        for layout in [
+            KeyboardLayout::Dvorak,
+            KeyboardLayout::Colemak,
+            KeyboardLayout::Azerty,
+            KeyboardLayout::Qwertz,
+        ] {
+            for (us, row) in US_ROWS.iter().zip(layout.rows()) {
+                assert_eq!(us.chars().count(), row.chars().count());
+            }
+        }
+    }
+}
@@ -0,0 +1,187 @@
+#[allow(unused_imports)]
+use log::info;
+
+use crate::key::{KeyboardLayout, code_for_name, find_code, find_name, name_for_code};
+
+//
+// ---------------- Key Display ----------------
+//
+
+// Key sequences are stored in VSCode's notation (e.g. `ctrl+shift+p`); when they are shown
+// to the user (in the documentation, the palette and the status bar) they are rendered the
+// way the user's platform labels its keys: `⌃⇧P` on macOS and `Ctrl+Shift+P` on Windows
+// and Linux. Layout independent codes (e.g. `[KeyW]`) are shown as the key at that
+// position of the file's layout (see `header.layout`).
+
+/// The platform key sequences are displayed for
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyPlatform {
+    // keys are shown as written in `keybindings.json`
+    #[default]
+    Plain,
+    Mac,
+    Windows,
+    Linux,
+}
+
+// modifiers, in the order each platform lists them, and their labels on macOS, Windows and
+// Linux
+const MODIFIER_LABELS: [(&str, &str, &str, &str); 6] = [
+    ("ctrl", "⌃", "Ctrl", "Ctrl"),
+    ("alt", "⌥", "Alt", "Alt"),
+    ("shift", "⇧", "Shift", "Shift"),
+    ("meta", "⌘", "Win", "Super"),
+    ("cmd", "⌘", "Win", "Super"),
+    ("win", "⌘", "Win", "Super"),
+];
+
+// labels of named keys on macOS and on other platforms; keys not listed here are labeled
+// by their layout independent code (e.g. `F5` or `NumpadAdd`), or their name if they have
+// none
+const KEY_LABELS: [(&str, &str, &str); 15] = [
+    ("enter", "↩", "Enter"),
+    ("tab", "⇥", "Tab"),
+    ("escape", "⎋", "Esc"),
+    ("backspace", "⌫", "Backspace"),
+    ("delete", "⌦", "Delete"),
+    ("space", "Space", "Space"),
+    ("up", "↑", "Up"),
+    ("down", "↓", "Down"),
+    ("left", "←", "Left"),
+    ("right", "→", "Right"),
+    ("pageup", "⇞", "PageUp"),
+    ("pagedown", "⇟", "PageDown"),
+    ("home", "↖", "Home"),
+    ("end", "↘", "End"),
+    ("capslock", "⇪", "CapsLock"),
+];
+
+impl KeyPlatform {
+    /// The platform named by `name`: a value of node's `process.platform` (e.g. `darwin`)
+    /// or one of `mac`, `windows` and `linux`; other names display keys as written.
+    pub(crate) fn from_name(name: &str) -> Self {
+        return match name.to_lowercase().as_str() {
+            "darwin" | "mac" | "macos" => KeyPlatform::Mac,
+            "win32" | "windows" => KeyPlatform::Windows,
+            "linux" => KeyPlatform::Linux,
+            _ => KeyPlatform::Plain,
+        };
+    }
+
+    // the label of a key (without modifiers); `None` if it is not a key
+    fn key_label(&self, key: &str, layout: KeyboardLayout) -> Option<String> {
+        // show layout independent codes as the key at that position
+        let key = match key.strip_prefix('[').and_then(|x| x.strip_suffix(']')) {
+            Some(code) => match name_for_code(code) {
+                Some(name) => layout.label(name).unwrap_or(name.to_string()),
+                // keys without a name are labeled by their code
+                Option::None => return find_code(code).map(|k| k.code.to_string()),
+            },
+            Option::None => key.to_lowercase(),
+        };
+        if let Some((_, mac, other)) = KEY_LABELS.iter().find(|(x, _, _)| *x == key) {
+            return Some(match self {
+                KeyPlatform::Mac => mac.to_string(),
+                _ => other.to_string(),
+            });
+        }
+        if key.chars().count() == 1 {
+            return Some(key.to_uppercase());
+        }
+        if let Some(code) = code_for_name(&key) {
+            return Some(code.to_string());
+        }
+        // keys without a code are labeled by their name
+        return find_name(&key).map(|k| k.name.to_string());
+    }
+
+    /// Renders a single key press (e.g. `ctrl+shift+p`) for this platform; text that is not
+    /// a key press (e.g. the `0-9` of a combined binding) is returned unchanged
+    pub(crate) fn display_press(&self, press: &str, layout: KeyboardLayout) -> String {
+        if *self == KeyPlatform::Plain {
+            return press.to_string();
+        }
+        let mut parts: Vec<_> = press.split('+').collect();
+        let key = parts.pop().unwrap_or_default();
+        let Some(key) = self.key_label(key, layout) else {
+            return press.to_string();
+        };
+        let mut modifiers = Vec::new();
+        for (name, mac, windows, linux) in MODIFIER_LABELS {
+            if parts.iter().any(|x| x.eq_ignore_ascii_case(name)) {
+                let label = match self {
+                    KeyPlatform::Mac => mac,
+                    KeyPlatform::Windows => windows,
+                    _ => linux,
+                };
+                if !modifiers.contains(&label) {
+                    modifiers.push(label);
+                }
+            }
+        }
+        if parts.len() > modifiers.len() {
+            // some modifier is not a known modifier
+            return press.to_string();
+        }
+        if *self == KeyPlatform::Mac {
+            return format!("{}{key}", modifiers.concat());
+        }
+        modifiers.push(&key);
+        return modifiers.join("+");
+    }
+
+    /// Renders a sequence of key presses (e.g. `ctrl+k ctrl+s`) for this platform
+    pub(crate) fn display_keys(&self, keys: &str, layout: KeyboardLayout) -> String {
+        return keys
+            .split(' ')
+            .map(|press| self.display_press(press, layout))
+            .collect::<Vec<_>>()
+            .join(" ");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn platform_display() {
+        let qwerty = KeyboardLayout::Qwerty;
+        let mac = KeyPlatform::from_name("darwin");
+        assert_eq!(mac.display_keys("ctrl+shift+p", qwerty), "⌃⇧P");
+        assert_eq!(mac.display_keys("shift+cmd+escape", qwerty), "⇧⌘⎋");
+        assert_eq!(mac.display_keys("space f", qwerty), "Space F");
+        assert_eq!(mac.display_keys("alt+[KeyW]", qwerty), "⌥W");
+
+        let windows = KeyPlatform::from_name("win32");
+        assert_eq!(windows.display_keys("shift+ctrl+p", qwerty), "Ctrl+Shift+P");
+        assert_eq!(windows.display_keys("meta+f5", qwerty), "Win+F5");
+        let linux = KeyPlatform::from_name("linux");
+        assert_eq!(
+            linux.display_keys("meta+numpad_add", qwerty),
+            "Super+NumpadAdd"
+        );
+
+        // codes are shown as the key at the same position of the layout
+        let colemak = KeyboardLayout::Colemak;
+        assert_eq!(windows.display_keys("ctrl+[KeyK]", colemak), "Ctrl+E");
+        let azerty = KeyboardLayout::Azerty;
+        assert_eq!(mac.display_keys("[Digit2]", azerty), "É");
+
+        // keys with only a code or only a name are labeled by it
+        assert_eq!(
+            mac.display_keys("shift+[NumpadEnter]", qwerty),
+            "⇧NumpadEnter"
+        );
+        assert_eq!(
+            windows.display_keys("ctrl+numpad_separator", qwerty),
+            "Ctrl+numpad_separator"
+        );
+
+        // other text and unknown platforms are left as is
+        assert_eq!(windows.display_keys("0-9", qwerty), "0-9");
+        assert_eq!(windows.display_keys("hyper+x", qwerty), "hyper+x");
+        let plain = KeyPlatform::from_name("web");
+        assert_eq!(plain.display_keys("ctrl+[KeyW]", qwerty), "ctrl+[KeyW]");
+    }
+}
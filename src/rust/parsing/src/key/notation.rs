@@ -0,0 +1,410 @@
+#[allow(unused_imports)]
+use log::info;
+
+use serde::Deserialize;
+use std::ops::Range;
+use toml::Spanned;
+
+use crate::bind::validation::valid_key_binding_str;
+use crate::err;
+use crate::error::{ErrorContext, Result, ResultVec};
+use crate::expression::Scope;
+use crate::expression::value::{Expanding, Expression, Value};
+use crate::key::{is_valid_key, shifted_press};
+use crate::util::Merging;
+
+//
+// ---------------- Key Notation ----------------
+//
+
+// Vim and Emacs users can write key sequences in the notation of their editor (e.g. `<C-w>
+// h` or `C-x C-f`); these are translated to VSCode's notation (`ctrl+w h`, `ctrl+x ctrl+f`)
+// before the keys are validated. Characters are assumed to be typed on a US keyboard
+// (e.g. `:` is `shift+;`).
+
+/// The notation a key sequence is written in (see `header.keyNotation`)
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyNotation {
+    #[default]
+    VSCode,
+    Vim,
+    Emacs,
+}
+
+// names of keys written in brackets (e.g. `<CR>`) that are not VSCode key names; compared
+// case insensitively
+const VIM_NAMES: [(&str, &str); 10] = [
+    ("cr", "enter"),
+    ("return", "enter"),
+    ("esc", "escape"),
+    ("bs", "backspace"),
+    ("del", "delete"),
+    ("ins", "insert"),
+    ("lt", "shift+,"),
+    ("bar", "shift+\\"),
+    ("bslash", "\\"),
+    ("nl", "enter"),
+];
+
+// names of keys written in brackets (e.g. `<return>`) that are not VSCode key names
+const EMACS_BRACKETED_NAMES: [(&str, &str); 3] = [
+    ("return", "enter"),
+    ("prior", "pageup"),
+    ("next", "pagedown"),
+];
+
+// names of keys written without brackets (e.g. `RET`)
+const EMACS_NAMES: [(&str, &str); 6] = [
+    ("RET", "enter"),
+    ("SPC", "space"),
+    ("TAB", "tab"),
+    ("ESC", "escape"),
+    ("DEL", "backspace"),
+    ("LFD", "enter"),
+];
+
+// the location of `range` (an index into a key string) in the file, when the `offset` of
+// the key string is known
+fn locate(offset: Option<usize>, range: Range<usize>) -> Option<Range<usize>> {
+    return offset.map(|o| (o + range.start)..(o + range.end));
+}
+
+// the key press that types `c`
+fn char_press(c: char) -> Option<String> {
+    if let Some(press) = shifted_press(c) {
+        return Some(press);
+    }
+    if is_valid_key(&c.to_string()) {
+        return Some(c.to_string());
+    }
+    return None;
+}
+
+// the key press of a named key (e.g. `esc` or `f1`)
+fn named_press(name: &str, names: &[(&str, &str)]) -> Option<String> {
+    if let Some((_, key)) = names.iter().find(|(x, _)| x.eq_ignore_ascii_case(name)) {
+        return Some(key.to_string());
+    }
+    if name.chars().count() > 1 && is_valid_key(name) {
+        return Some(name.to_lowercase());
+    }
+    return None;
+}
+
+// join modifiers and a key press; the key press may have its own modifiers (e.g. `shift+;`
+// for `:`)
+fn with_modifiers(modifiers: &[&str], press: String) -> String {
+    if modifiers.is_empty() {
+        return press;
+    }
+    return format!("{}+{press}", modifiers.join("+"));
+}
+
+impl KeyNotation {
+    /// Translates the key sequence of a binding (or `[[prefix]]`) to VSCode's notation and
+    /// validates the result; `<leader>` is read from `val.leader`. Errors point at the span
+    /// of `key`, or inside of it when `literal` (i.e. `key` is the text of the file, rather
+    /// than the result of an expression) and the key is written without escapes.
+    pub(crate) fn translate_key(
+        &self,
+        key: &Spanned<String>,
+        literal: bool,
+        scope: &Scope,
+    ) -> Result<String> {
+        let span = key.span();
+        let text = key.as_ref();
+        let offset = (literal && span.len() == text.len() + 2).then(|| span.start + 1);
+        let leader = scope.defined_string("leader");
+        let key = self
+            .translate(text, leader.as_deref(), offset)
+            .with_range(&span)?;
+        valid_key_binding_str(&key).with_range(&span)?;
+        return Ok(key);
+    }
+
+    /// Translates `keys`, written in this notation, to a sequence of key presses in
+    /// VSCode's notation (e.g. `<C-w>h` becomes `ctrl+w h`). `leader` is the value of
+    /// `<leader>` (see `define.val`) and `offset` the location of `keys` in the file, when
+    /// known, so that errors point to the offending part of `keys`.
+    pub(crate) fn translate(
+        &self,
+        keys: &str,
+        leader: Option<&str>,
+        offset: Option<usize>,
+    ) -> Result<String> {
+        let presses = match self {
+            KeyNotation::VSCode => return Ok(keys.to_string()),
+            KeyNotation::Vim => self.vim_presses(keys, leader, offset)?,
+            KeyNotation::Emacs => self.emacs_presses(keys, leader, offset)?,
+        };
+        if presses.is_empty() {
+            return Err(err!("expected at least one key"))?;
+        }
+        return Ok(presses.join(" "));
+    }
+
+    // the key presses of `<leader>`, which is written in the same notation as the key
+    fn leader_presses(
+        &self,
+        modifiers: &[&str],
+        leader: Option<&str>,
+        range: Range<usize>,
+        offset: Option<usize>,
+    ) -> Result<Vec<String>> {
+        if !modifiers.is_empty() {
+            return Err(err!("modifiers cannot be applied to `<leader>`"))
+                .with_range(&locate(offset, range));
+        }
+        let Some(leader) = leader else {
+            return Err(err!(
+                "`<leader>` is undefined; define it using `[[define.val]]` (e.g. `leader = \
+                 \"<Space>\"`)"
+            ))
+            .with_range(&locate(offset, range));
+        };
+        let presses = self
+            .translate(leader, None, None)
+            .with_message(" (in the value of `val.leader`)")
+            .with_range(&locate(offset, range))?;
+        return Ok(presses.split(' ').map(String::from).collect());
+    }
+
+    // Vim notation: each character is a key press unless it is part of a bracketed key
+    // (e.g. `<C-w>` or `<Esc>`); whitespace separates key presses but is otherwise ignored
+    fn vim_presses(
+        &self,
+        keys: &str,
+        leader: Option<&str>,
+        offset: Option<usize>,
+    ) -> Result<Vec<String>> {
+        let mut presses = Vec::new();
+        let mut chars = keys.char_indices();
+        while let Some((start, c)) = chars.next() {
+            if c.is_whitespace() {
+                continue;
+            }
+            if c == '<'
+                && let Some(len) = keys[start..].find('>')
+                && len > 1
+                && !keys[(start + 1)..(start + len)].contains(char::is_whitespace)
+            {
+                let end = start + len + 1;
+                let mut name = &keys[(start + 1)..(end - 1)];
+                let mut modifiers = Vec::new();
+                while name.len() > 2 && name.as_bytes()[1] == b'-' {
+                    modifiers.push(match &name[..1] {
+                        "C" | "c" => "ctrl",
+                        "S" | "s" => "shift",
+                        "A" | "a" | "M" | "m" => "alt",
+                        "D" | "d" => "cmd",
+                        other => {
+                            return Err(err!("`{other}-` is not a Vim modifier"))
+                                .with_range(&locate(offset, start..end));
+                        }
+                    });
+                    name = &name[2..];
+                }
+                if name.eq_ignore_ascii_case("leader") {
+                    let range = start..end;
+                    presses.append(&mut self.leader_presses(&modifiers, leader, range, offset)?);
+                } else {
+                    let mut name_chars = name.chars();
+                    let press = match (name_chars.next(), name_chars.next()) {
+                        // as in Vim, the letter of `<C-a>` is case insensitive
+                        (Some(c), Option::None) if modifiers.contains(&"ctrl") => {
+                            char_press(c.to_ascii_lowercase())
+                        }
+                        (Some(c), Option::None) => char_press(c),
+                        _ => named_press(name, &VIM_NAMES),
+                    };
+                    let Some(press) = press else {
+                        return Err(err!("`<{name}>` is not a key"))
+                            .with_range(&locate(offset, start..end));
+                    };
+                    presses.push(with_modifiers(&modifiers, press));
+                }
+                for _ in keys[(start + 1)..end].chars() {
+                    chars.next();
+                }
+            } else {
+                let Some(press) = char_press(c) else {
+                    let end = start + c.len_utf8();
+                    return Err(err!("`{c}` is not a key")).with_range(&locate(offset, start..end));
+                };
+                presses.push(press);
+            }
+        }
+        return Ok(presses);
+    }
+
+    // Emacs notation: key presses are separated by whitespace, each is a list of modifiers
+    // (e.g. `C-M-`) followed by a character, a named key (e.g. `RET`) or a bracketed key
+    // (e.g. `<f1>`); an unmodified word of several characters is one key press per
+    // character
+    fn emacs_presses(
+        &self,
+        keys: &str,
+        leader: Option<&str>,
+        offset: Option<usize>,
+    ) -> Result<Vec<String>> {
+        let mut presses = Vec::new();
+        for word in keys.split_whitespace() {
+            let start = word.as_ptr() as usize - keys.as_ptr() as usize;
+            let range = start..(start + word.len());
+            let mut rest = word;
+            let mut modifiers = Vec::new();
+            while rest.len() > 2 && rest.as_bytes()[1] == b'-' {
+                modifiers.push(match &rest[..1] {
+                    "C" => "ctrl",
+                    "M" => "alt",
+                    "S" => "shift",
+                    "s" => "meta",
+                    other => {
+                        return Err(err!("`{other}-` is not an Emacs modifier"))
+                            .with_range(&locate(offset, range));
+                    }
+                });
+                rest = &rest[2..];
+            }
+
+            if let Some(name) = rest.strip_prefix('<').and_then(|x| x.strip_suffix('>'))
+                && !name.is_empty()
+            {
+                if name.eq_ignore_ascii_case("leader") {
+                    presses.append(&mut self.leader_presses(&modifiers, leader, range, offset)?);
+                    continue;
+                }
+                let Some(press) = named_press(name, &EMACS_BRACKETED_NAMES) else {
+                    return Err(err!("`<{name}>` is not a key")).with_range(&locate(offset, range));
+                };
+                presses.push(with_modifiers(&modifiers, press));
+                continue;
+            }
+            if let Some((_, press)) = EMACS_NAMES.iter().find(|(x, _)| *x == rest) {
+                presses.push(with_modifiers(&modifiers, press.to_string()));
+                continue;
+            }
+
+            let mut rest_chars = rest.chars();
+            if let (Some(c), Option::None) = (rest_chars.next(), rest_chars.next()) {
+                let Some(press) = char_press(c) else {
+                    return Err(err!("`{c}` is not a key")).with_range(&locate(offset, range));
+                };
+                presses.push(with_modifiers(&modifiers, press));
+            } else if !modifiers.is_empty() {
+                return Err(err!(
+                    "expected a single key after the modifiers, found `{rest}`; separate key \
+                     presses with spaces"
+                ))
+                .with_range(&locate(offset, range));
+            } else {
+                for (i, c) in rest.char_indices() {
+                    let Some(press) = char_press(c) else {
+                        let c_range = (start + i)..(start + i + c.len_utf8());
+                        return Err(err!("`{c}` is not a key"))
+                            .with_range(&locate(offset, c_range));
+                    };
+                    presses.push(press);
+                }
+            }
+        }
+        return Ok(presses);
+    }
+}
+
+// see `util.rs`
+impl Merging for KeyNotation {
+    fn merge(self, new: Self) -> Self {
+        return new;
+    }
+    fn coalesce(self, new: Self) -> Self {
+        return new;
+    }
+}
+
+// see `value.rs`
+impl Expanding for KeyNotation {
+    fn is_constant(&self) -> bool {
+        return true;
+    }
+    fn map_expressions<F>(self, _f: &mut F) -> ResultVec<Self>
+    where
+        F: FnMut(Expression) -> Result<Value>,
+    {
+        return Ok(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vim(keys: &str) -> String {
+        return KeyNotation::Vim
+            .translate(keys, Some("<Space>"), None)
+            .unwrap();
+    }
+
+    fn emacs(keys: &str) -> String {
+        return KeyNotation::Emacs
+            .translate(keys, Some("C-c"), None)
+            .unwrap();
+    }
+
+    #[test]
+    fn vim_notation() {
+        assert_eq!(vim("<C-w> h"), "ctrl+w h");
+        assert_eq!(vim("<C-w>h"), "ctrl+w h");
+        assert_eq!(vim("gg"), "g g");
+        assert_eq!(vim("<C-A>"), "ctrl+a");
+        assert_eq!(vim("<M-S-x>"), "alt+shift+x");
+        assert_eq!(vim("G"), "shift+g");
+        assert_eq!(vim(":"), "shift+;");
+        assert_eq!(vim("<CR>"), "enter");
+        assert_eq!(vim("<Esc>"), "escape");
+        assert_eq!(vim("<F5>"), "f5");
+        assert_eq!(vim("<lt>"), "shift+,");
+        assert_eq!(vim("<leader>f"), "space f");
+        assert_eq!(vim("<"), "shift+,");
+    }
+
+    #[test]
+    fn emacs_notation() {
+        assert_eq!(emacs("C-x C-f"), "ctrl+x ctrl+f");
+        assert_eq!(emacs("M-x"), "alt+x");
+        assert_eq!(emacs("C-M-%"), "ctrl+alt+shift+5");
+        assert_eq!(emacs("s-a"), "meta+a");
+        assert_eq!(emacs("RET"), "enter");
+        assert_eq!(emacs("C-<f1>"), "ctrl+f1");
+        assert_eq!(emacs("<prior>"), "pageup");
+        assert_eq!(emacs("ab"), "a b");
+        assert_eq!(emacs("<leader> f"), "ctrl+c f");
+    }
+
+    #[test]
+    fn notation_errors() {
+        let offset = Some(10);
+        let err = KeyNotation::Vim
+            .translate("a <X-w>", None, offset)
+            .unwrap_err();
+        assert!(err.to_string().contains("`X-` is not a Vim modifier"));
+        assert!(matches!(err.contexts[0], crate::error::Context::Range(ref r) if *r == (12..17)));
+
+        let err = KeyNotation::Vim
+            .translate("<leader>", None, None)
+            .unwrap_err();
+        assert!(err.to_string().contains("`<leader>` is undefined"));
+
+        let err = KeyNotation::Emacs
+            .translate("C-x C-ff", None, offset)
+            .unwrap_err();
+        assert!(err.to_string().contains("expected a single key"));
+        assert!(matches!(err.contexts[0], crate::error::Context::Range(ref r) if *r == (14..18)));
+
+        assert_eq!(
+            KeyNotation::VSCode.translate("<C-w>", None, None).unwrap(),
+            "<C-w>"
+        );
+    }
+}
@@ -91,7 +91,9 @@ impl Kind {
 
                 known_kinds.insert(kind_input.name.clone());
             }
-            scope.kinds.extend(input.iter().map(|x| x.as_ref().name.clone()));
+            scope
+                .kinds
+                .extend(input.iter().map(|x| x.as_ref().name.clone()));
             result = input.iter().map(|x| x.as_ref().clone()).collect();
         } else {
             result = Vec::new();
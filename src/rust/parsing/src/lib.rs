@@ -3,6 +3,7 @@
 // assorted utilities
 mod error;
 mod expression;
+mod key;
 mod util;
 
 // define file sections
@@ -11,6 +12,8 @@ mod define;
 mod docs;
 mod kind;
 mod mode;
+mod prefix;
+mod remap;
 
 // top level parsing
 pub mod file;
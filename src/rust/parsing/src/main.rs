@@ -1,22 +1,79 @@
 use std::env;
 use std::fs;
 
-use parsing::file::parse_keybinding_data;
+use parsing::file::{KeyFileResult, parse_keybinding_data};
 
 // NOTE: this isn't a user facing executable, so we are lazy about error handling
-fn process_preset(path: &str) -> String {
+fn process_preset(path: &str) -> (KeyFileResult, String) {
     let data = std::fs::read(path).expect("file to exist");
     let result = parse_keybinding_data(&data, None);
-    return result.text_docs().expect("documentation");
+    let docs = result.text_docs().expect("documentation");
+    return (result, docs);
+}
+
+// `line` and `col` are zero-indexed
+fn location(path: &str, pos: Option<(usize, usize)>) -> String {
+    return match pos {
+        Some((line, col)) => format!("{path}:{}:{}", line + 1, col + 1),
+        None => format!("{path}:?"),
+    };
+}
+
+// list the slowest expressions (and bindings) found while parsing the preset
+fn print_profile(path: &str, result: &KeyFileResult, n: usize) {
+    println!("Slowest expressions:");
+    for timing in result.expression_profile(n) {
+        println!(
+            "  {}  {:.3}ms (compile {:.3}ms, {} evaluations {:.3}ms)  {{{{{}}}}}",
+            location(
+                path,
+                timing.range.as_ref().map(|r| (r.start.line, r.start.col))
+            ),
+            timing.total_ms(),
+            timing.compile_ms,
+            timing.evaluations,
+            timing.evaluate_ms,
+            timing.expression,
+        );
+    }
+    println!("Slowest bindings:");
+    for timing in result.binding_profile(n) {
+        println!(
+            "  {}  {:.3}ms (compile {:.3}ms, {} evaluations {:.3}ms)  key = {:?}",
+            location(
+                path,
+                timing.range.as_ref().map(|r| (r.start.line, r.start.col))
+            ),
+            timing.compile_ms + timing.evaluate_ms,
+            timing.compile_ms,
+            timing.evaluations,
+            timing.evaluate_ms,
+            timing.key,
+        );
+    }
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
     if args.len() < 3 {
-        eprintln!("Usage: parsing <input> <output>");
+        eprintln!("Usage: parsing <input> <output> [--profile [count]] [--mode-graph <dot>]");
         return;
     }
 
-    let output = process_preset(&args[1]);
+    let (result, output) = process_preset(&args[1]);
     fs::write(&args[2], output).expect("file write to work");
+
+    if let Some(i) = args.iter().position(|x| x == "--mode-graph") {
+        let path = args.get(i + 1).expect("`--mode-graph` to have a file name");
+        let graph = result.mode_graph().expect("mode graph");
+        fs::write(path, graph).expect("file write to work");
+    }
+
+    if let Some(i) = args.iter().position(|x| x == "--profile") {
+        let n = match args.get(i + 1) {
+            Some(n) => n.parse().expect("`--profile` count to be a number"),
+            None => 10,
+        };
+        print_profile(&args[1], &result, n);
+    }
 }
@@ -1,20 +1,32 @@
 #[allow(unused_imports)]
 use log::info;
 
+use indexmap::IndexMap;
+use lazy_static::lazy_static;
+use log::error;
+use regex::Regex;
 use rhai::{EvalAltResult, ImmutableString};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
+use std::ops::Range;
 use toml::Spanned;
 use wasm_bindgen::prelude::*;
 
-use crate::bind::command::{Command, CommandInput};
+pub mod graph;
+
+use crate::bind::command::{Command, CommandInput, regularize_commands};
 use crate::bind::foreach::all_characters;
+use crate::bind::prefix::Prefix;
+use crate::bind::validation::valid_key_binding_str;
 use crate::bind::{
-    Binding, BindingCodes, BindingOutput, ReifiedBinding, TEXT_FOCUS_CONDITION, UNKNOWN_RANGE,
+    Binding, BindingCodes, BindingDoc, BindingOutput, CombinedBindingDoc, KEY_WITH_MODIFIER,
+    ReifiedBinding, UNKNOWN_RANGE,
 };
-use crate::error::{Context, ErrorContext, ParseError, Result, ResultVec, err};
+use crate::error::{Context, ErrorContext, ParseError, Result, ResultVec, err, flatten_errors};
 use crate::expression::Scope;
+use crate::expression::value::{TypedValue, Value};
 use crate::file::KeyFileResult;
+use crate::key::{canonical_press, shifted_press};
 use crate::resolve;
 use crate::util::{LeafValue, Resolving};
 use crate::{err, wrn};
@@ -62,6 +74,14 @@ use crate::{err, wrn};
 /// whenNoBinding = "insertCharacters"
 /// ```
 ///
+/// Master Key checks how the modes of a file connect to one another, by following the
+/// `master-key.setMode`, `master-key.enterInsert` and `master-key.enterNormal` commands of
+/// each binding (including bindings inherited through `extends` or `useMode`), along with
+/// `onEnter`, `whenNoBinding` and `returnAfter`. It warns about modes that no binding
+/// leaves, modes that cannot be reached from the default mode and commands that switch to
+/// an undefined mode. A mode computed by an [expression](/expressions/index) could be any
+/// mode, so such files are not checked for unreachable modes.
+///
 /// ## Fields
 ///
 /// The only required field for a mode is its name (marked with "❗") but there are a number
@@ -82,6 +102,32 @@ pub struct ModeInput {
     default: Option<bool>,
     /// @forBindingField mode
     ///
+    /// - `capture`: whether this mode is used to capture keys typed by the user, as done by
+    ///   [`master-key.captureKeys`](/commands/captureKeys) and
+    ///   [`master-key.search`](/commands/search). These commands accept a `mode` argument
+    ///   naming the capture mode to use, so that different uses of captured keys can have a
+    ///   distinct appearance (e.g. `cursorShape`) and bindings (e.g. to cancel capturing
+    ///   keys with `escape`). A capture mode cannot be the default mode and always lets the
+    ///   user type (i.e. `whenNoBinding = "insertCharacters"`). The mode named `capture`
+    ///   is used when no `mode` argument is given: it is defined implicitly, but can be
+    ///   defined explicitly (with `capture = true`) to change its appearance. Capture
+    ///   modes are not included in [`all_modes()`](/expressions/#read-time-evaluation).
+    ///
+    ///   ```toml
+    ///   [[mode]]
+    ///   name = "register"
+    ///   capture = true
+    ///   cursorShape = "BlockOutline"
+    ///   highlight = "Alert"
+    ///
+    ///   [[bind]]
+    ///   key = "shift+'"
+    ///   command = "master-key.captureKeys"
+    ///   args = { acceptAfter = 1, mode = "register" }
+    ///   ```
+    capture: Option<bool>,
+    /// @forBindingField mode
+    ///
     /// - `highlight`: Whether and how to highlight the name of this mode in the bottom left
     ///   corner of VSCode. Possible values are:
     ///     - `NoHighlight` does not add coloring
@@ -100,6 +146,25 @@ pub struct ModeInput {
     cursorShape: Option<CursorShape>,
     /// @forBindingField mode
     ///
+    /// - `color`: the color of the mode's name in the bottom left corner of VSCode. Either
+    ///   a hex color (e.g. `"#ff8800"`) or a [theme color
+    ///   id](https://code.visualstudio.com/api/references/theme-color) (e.g.
+    ///   `"terminal.ansiGreen"`).
+    color: Option<Spanned<String>>,
+    /// @forBindingField mode
+    ///
+    /// - ⚡ `statusText`: the text displayed in the bottom left corner of VSCode for this
+    ///   mode (defaults to `name`). This can include [expressions](/expressions/index),
+    ///   evaluated whenever the status is updated, such as <code v-pre>"NORMAL
+    ///   {{key.count}}"</code> or <code v-pre>"{{key.prefix}}"</code>.
+    statusText: Option<Spanned<Value>>,
+    /// @forBindingField mode
+    ///
+    /// - `lineNumbers`: How line numbers are displayed when in this mode. One of `On`,
+    ///   `Off`, `Relative` or `Interval`. Defaults to the `editor.lineNumbers` setting.
+    lineNumbers: Option<LineNumbers>,
+    /// @forBindingField mode
+    ///
     /// - `whenNoBinding`: How to respond to keys when there is no binding for them in this
     /// mode. The options are:
     ///   - `"ignoreCharacters"`: The mode will introduce implicit bindings that cause any
@@ -116,8 +181,122 @@ pub struct ModeInput {
     ///     Then `commands` are run, as per the fields allowed when
     ///     [running multiple commands](/bindings/bind#running-multiple-commands) in
     ///     `[[bind]]`.
+    ///   - `[{keys = <keys>, action = <action>}, ...]`: a list of rules that determine the
+    ///     behavior for specific keys. `keys` is a key or list of keys (e.g. <code
+    ///     v-pre>"{{keys(`[a-z]`)}}"</code>) and `action` is one of
+    ///     `"ignoreCharacters"`, `"insertCharacters"` or `{"run": [<commands>]}`. A key
+    ///     also covers its shifted variant (e.g. `a` covers `shift+a`). The first rule
+    ///     listing a key determines its behavior; keys not listed by any rule are inserted
+    ///     as usual. Only keys that type a character are affected (e.g. not `f1`).
+    ///
+    ///     ```toml
+    ///     [[mode]]
+    ///     name = "normal"
+    ///     whenNoBinding = [
+    ///         { keys = "{{keys(`[a-z]`)}}", action = "ignoreCharacters" },
+    ///         { keys = ["enter", "tab"], action = "insertCharacters" },
+    ///         { keys = "{{keys(`[0-9]`)}}", action.run = [{ command = "foo" }] },
+    ///     ]
+    ///     ```
     #[serde(default)]
     whenNoBinding: Option<Spanned<WhenNoBindingInput>>,
+    /// @forBindingField mode
+    ///
+    /// - `extends`: the name of another mode whose bindings are inherited by this mode.
+    ///   Inheritance is transitive: if the parent mode extends a third mode, this mode
+    ///   inherits the bindings of both. A binding defined in this mode (or in a closer
+    ///   parent) shadows every inherited binding with the same `key`, whatever their
+    ///   `when` clauses.
+    ///   `highlight` and `cursorShape` are also inherited, unless they are set in this
+    ///   mode. Modes cannot extend one another in a cycle.
+    extends: Option<Spanned<String>>,
+    /// @forBindingField mode
+    ///
+    /// - `onEnter`: commands to run whenever this mode is entered, regardless of which
+    ///   command changed the mode. Follows the same format as described under [running
+    ///   multiple commands](/bindings/bind#running-multiple-commands).
+    onEnter: Option<Vec<Spanned<CommandInput>>>,
+    /// @forBindingField mode
+    ///
+    /// - `onExit`: commands to run whenever this mode is exited, in the same format
+    ///   as `onEnter`. These run before the next mode becomes active, and before its
+    ///   `onEnter` commands. Neither `onExit` nor `onEnter` commands can change the mode
+    ///   (e.g. with [`master-key.setMode`](/commands/setMode)).
+    onExit: Option<Vec<Spanned<CommandInput>>>,
+    /// @forBindingField mode
+    ///
+    /// - `returnAfter`: when set, this is a one-shot mode: once this many bindings have
+    ///   completed (i.e. run their `finalKey`) in this mode, the mode automatically
+    ///   reverts. For example, `returnAfter = 1` can be used to run a single normal mode
+    ///   command from insert mode, ala vim's `ctrl-o`. Must be at least 1.
+    /// - `returnTo`: the mode to revert to when `returnAfter` is reached. Defaults to the
+    ///   mode that was active before entering this mode. Setting `returnTo` without
+    ///   `returnAfter` implies `returnAfter = 1`.
+    returnAfter: Option<Spanned<i32>>,
+    returnTo: Option<Spanned<String>>,
+    /// @forBindingField mode
+    ///
+    /// - `when`: a [when clause](https://code.visualstudio.com/api/references/when-clause-contexts)
+    ///   that every binding in this mode requires, in addition to its own `when` clause
+    ///   (e.g. `"editorHasSelection"` for a visual mode).
+    /// - `priority`: the `priority` of bindings in this mode that do not set their own
+    ///   `priority`.
+    /// - `tags`: tags added to the `tags` of every binding run in this mode.
+    /// - `doc.kind`: the `doc.kind` of bindings in this mode that do not set their own
+    ///   `doc.kind`.
+    ///
+    /// These defaults apply to all bindings active in the mode, including those inherited
+    /// through `extends` or `whenNoBinding.useMode`. A binding listed under several modes
+    /// takes the defaults of each mode separately: e.g. its `when` clause in mode `a`
+    /// includes the `when` of `a` but not the `when` of mode `b`.
+    ///
+    /// ```toml
+    /// [[mode]]
+    /// name = "visual"
+    /// when = "editorHasSelection"
+    /// tags = ["selection"]
+    /// doc.kind = "select"
+    /// ```
+    when: Option<Spanned<String>>,
+    priority: Option<f64>,
+    tags: Option<Vec<String>>,
+    doc: Option<ModeDocInput>,
+    /// @forBindingField mode
+    ///
+    /// - `cancelPrefix`: when `true`, this mode has implicit bindings that cancel a
+    ///   pending key sequence: after pressing a prefix (e.g. `g` of `g g`), pressing
+    ///   `escape` clears the prefix and any count (see
+    ///   [`master-key.updateCount`](/commands/updateCount)) without running a command.
+    ///   Set this to a key (e.g. `"ctrl+g"`) to cancel with that key instead of `escape`.
+    ///   A key without modifiers only cancels in the
+    ///   [`header.focus`](/bindings/header). An explicit binding for the key after a given prefix (e.g. `key = "g escape"`)
+    ///   takes precedence over the implicit binding.
+    cancelPrefix: Option<Spanned<CancelPrefixInput>>,
+    /// @forBindingField mode
+    ///
+    /// - `count`: when `true`, this mode has implicit bindings for the digits `0-9` (and
+    ///   `numpad0-9`) that call [`master-key.updateCount`](/commands/updateCount), so that
+    ///   a number typed before a binding is available as `key.count` in its
+    ///   [expressions](/expressions/index) and as `master-key.count` in `when` clauses.
+    ///   This takes the place of a `foreach` over the digits, as in the example below. A
+    ///   digit that is bound explicitly in the mode, whatever its `when` clause, replaces
+    ///   its implicit count binding. To use a digit both as a command and as part of a
+    ///   count (e.g. `0` in vim) add `when = "!master-key.count"` to its binding: a binding
+    ///   whose `when` clause refers to `master-key.count` is kept alongside the count
+    ///   binding, and takes precedence over it.
+    ///
+    /// ```toml
+    /// [[mode]]
+    /// name = "normal"
+    /// default = true
+    /// count = true
+    ///
+    /// [[bind]]
+    /// key = "j"
+    /// command = "cursorMove"
+    /// args = { to = "down", value = "{{key.count}}" }
+    /// ```
+    count: Option<bool>,
 
     #[serde(flatten)]
     other_fields: HashMap<String, toml::Value>,
@@ -128,17 +307,45 @@ impl Default for ModeInput {
         return ModeInput {
             name: "default".to_string(),
             default: Some(true),
+            capture: None,
             highlight: None,
             cursorShape: None,
+            color: None,
+            statusText: None,
+            lineNumbers: None,
             whenNoBinding: Some(Spanned::new(
                 UNKNOWN_RANGE,
                 WhenNoBindingInput::InsertCharacters,
             )),
+            extends: None,
+            onEnter: None,
+            onExit: None,
+            returnAfter: None,
+            returnTo: None,
+            when: None,
+            priority: None,
+            tags: None,
+            doc: None,
+            cancelPrefix: None,
+            count: None,
             other_fields: HashMap::new(),
         };
     }
 }
 
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ModeDocInput {
+    kind: Option<Spanned<String>>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum CancelPrefixInput {
+    Enabled(bool),
+    Key(String),
+}
+
 #[derive(Clone, Debug, Default)]
 pub enum WhenNoBindingInput {
     #[default]
@@ -146,6 +353,15 @@ pub enum WhenNoBindingInput {
     InsertCharacters,
     UseMode(String),
     Run(Vec<CommandInput>),
+    Rules(Vec<WhenNoBindingRuleInput>),
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WhenNoBindingRuleInput {
+    // a key, or list of keys (usually computed using an expression)
+    keys: Value,
+    action: WhenNoBindingInput,
 }
 
 // we use a custom serializer here to improve the error messages; use the default untagged
@@ -166,7 +382,7 @@ impl<'de> serde::de::Deserialize<'de> for WhenNoBindingInput {
             // This is our main custom error message!
             fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
                 formatter.write_str(
-                    "a string ('ignoreCharacters' or 'insertCharacters') or a single-key object ('useMode: <string>' or 'run: [<commands>]')",
+                    "a string ('ignoreCharacters' or 'insertCharacters'), a single-key object ('useMode: <string>' or 'run: [<commands>]') or an array of rules ('{keys, action}')",
                 )
             }
 
@@ -185,6 +401,18 @@ impl<'de> serde::de::Deserialize<'de> for WhenNoBindingInput {
                 }
             }
 
+            // Handles the list of rules
+            fn visit_seq<A>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error>
+            where
+                A: serde::de::SeqAccess<'de>,
+            {
+                let mut rules = Vec::new();
+                while let Some(rule) = seq.next_element::<WhenNoBindingRuleInput>()? {
+                    rules.push(rule);
+                }
+                Ok(WhenNoBindingInput::Rules(rules))
+            }
+
             // Handles the newtype variants: UseMode(String) and Run(...)
             fn visit_map<A>(self, mut map: A) -> std::result::Result<Self::Value, A::Error>
             where
@@ -226,7 +454,7 @@ impl<'de> serde::de::Deserialize<'de> for WhenNoBindingInput {
             }
         }
 
-        // Tell Serde we can deserialize from *either* a string or a map.
+        // Tell Serde we can deserialize from a string, a map or a sequence.
         // `deserialize_any` will call the appropriate visitor method.
         deserializer.deserialize_any(WhenNoBindingInputVisitor)
     }
@@ -257,15 +485,51 @@ pub enum CursorShape {
 }
 impl LeafValue for CursorShape {}
 
+#[wasm_bindgen]
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum LineNumbers {
+    On,
+    Off,
+    Relative,
+    Interval,
+}
+impl LeafValue for LineNumbers {}
+
+lazy_static! {
+    // a hex color or a theme color id
+    static ref COLOR: Regex =
+        Regex::new(r"^(#([0-9a-fA-F]{3,4}|[0-9a-fA-F]{6}|[0-9a-fA-F]{8})|[A-Za-z][\w-]*(\.[\w-]+)*)$")
+            .unwrap();
+}
+
 #[derive(Clone, Debug, Serialize)]
 #[allow(non_snake_case)]
 #[wasm_bindgen(getter_with_clone)]
 pub struct Mode {
     pub name: String,
     pub default: bool,
+    pub capture: bool,
     pub highlight: ModeHighlight,
     pub cursorShape: CursorShape,
+    pub color: Option<String>,
+    pub(crate) statusText: Option<Spanned<Value>>,
+    pub lineNumbers: Option<LineNumbers>,
     pub(crate) whenNoBinding: WhenNoBinding,
+    pub extends: Option<String>,
+    pub(crate) onEnter: Vec<Command>,
+    pub(crate) onExit: Vec<Command>,
+    pub returnAfter: Option<i32>,
+    pub returnTo: Option<String>,
+    // defaults for the bindings of this mode (see `Binding::outputs`)
+    pub when: Option<String>,
+    pub priority: Option<f64>,
+    pub tags: Vec<String>,
+    // validated once `[[kind]]` is known (see `KeyFile::new`)
+    pub(crate) kind: Option<Spanned<String>>,
+    // the key that cancels a pending prefix, if any
+    pub cancelPrefix: Option<String>,
+    // whether the digits update `master-key.count` (see `Modes::count_bindings`)
+    pub count: bool,
 }
 
 // this is only run in the typescript code, so we ignore coverage
@@ -274,12 +538,40 @@ pub struct Mode {
 impl Mode {
     #[allow(non_snake_case)]
     pub fn whenNoBinding(&self) -> WhenNoBindingHeader {
+        return (&self.whenNoBinding).into();
+    }
+
+    // whether typed characters must be intercepted to run commands (see `capture.ts`)
+    pub fn captures_typing(&self) -> bool {
         return match &self.whenNoBinding {
-            WhenNoBinding::IgnoreCharacters => WhenNoBindingHeader::IgnoreCharacters,
-            WhenNoBinding::InsertCharacters => WhenNoBindingHeader::InsertCharacters,
-            WhenNoBinding::UseMode(_) => WhenNoBindingHeader::UseMode,
-            WhenNoBinding::Run(_) => WhenNoBindingHeader::Run,
+            WhenNoBinding::Run(_) => true,
+            WhenNoBinding::Rules(rules) => rules
+                .iter()
+                .any(|rule| matches!(rule.action, WhenNoBinding::Run(_))),
+            _ => false,
+        };
+    }
+
+    // the behavior when typing `typed` (e.g. "A"), used to apply `whenNoBinding` rules;
+    // text that doesn't come from a single key is inserted
+    pub fn typed_action(&self, typed: &str) -> WhenNoBindingHeader {
+        return match typed_key(typed) {
+            Some(key) => self.whenNoBinding.for_key(&key).into(),
+            Option::None => WhenNoBindingHeader::InsertCharacters,
+        };
+    }
+
+    // the commands to run for `typed` (e.g. "A") according to the `whenNoBinding` rules
+    pub fn run_commands_for(&self, typed: &str, bindings: &mut KeyFileResult) -> ReifiedBinding {
+        let action = match typed_key(typed) {
+            Some(key) => self.whenNoBinding.for_key(&key),
+            Option::None => &self.whenNoBinding,
         };
+        if let WhenNoBinding::Run(commands) = action {
+            return ReifiedBinding::from_commands(commands.clone(), &bindings.scope);
+        } else {
+            return ReifiedBinding::noop(&bindings.scope);
+        }
     }
 
     pub fn run_commands(&self, bindings: &mut KeyFileResult) -> ReifiedBinding {
@@ -292,6 +584,48 @@ impl Mode {
             return ReifiedBinding::noop(&bindings.scope);
         }
     }
+
+    // the text to show in the status bar for this mode (see `mode-status.ts`)
+    pub fn status_text(&self, bindings: &mut KeyFileResult) -> String {
+        let template = match &self.statusText {
+            Some(x) => x,
+            Option::None => return self.name.clone(),
+        };
+        return match bindings.scope.expand(template.as_ref()) {
+            Ok(Value::String(text)) => text,
+            Ok(value) => {
+                let toml: toml::Value = value.into();
+                toml.to_string()
+            }
+            Err(e) => {
+                error!(
+                    "Failed to evaluate `statusText` of mode `{}`: {e}",
+                    self.name
+                );
+                self.name.clone()
+            }
+        };
+    }
+
+    pub fn enter_commands(&self, bindings: &mut KeyFileResult) -> ReifiedBinding {
+        return ReifiedBinding::from_commands(self.onEnter.clone(), &bindings.scope);
+    }
+
+    pub fn exit_commands(&self, bindings: &mut KeyFileResult) -> ReifiedBinding {
+        return ReifiedBinding::from_commands(self.onExit.clone(), &bindings.scope);
+    }
+}
+
+// the state of a mode with `returnAfter`, tracked at runtime (see
+// `KeyFileResult::enter_mode` and `KeyFileResult::complete_binding`)
+#[derive(Clone, Debug)]
+pub(crate) struct PendingReturn {
+    // the one-shot mode
+    pub(crate) mode: String,
+    // the mode to return to
+    pub(crate) to: String,
+    // the number of bindings left to complete before returning
+    pub(crate) remaining: i32,
 }
 
 #[derive(Clone, Debug, Serialize, Default, PartialEq)]
@@ -301,14 +635,83 @@ pub enum WhenNoBinding {
     InsertCharacters,
     UseMode(String),
     Run(Vec<Command>),
+    Rules(Vec<WhenNoBindingRule>),
+}
+
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct WhenNoBindingRule {
+    pub(crate) keys: Vec<String>,
+    // never `UseMode` or `Rules`
+    pub(crate) action: WhenNoBinding,
+}
+
+impl WhenNoBindingRule {
+    // a key is covered by a rule when it, or its unshifted variant, is listed
+    fn matches(&self, key: &str) -> bool {
+        return self
+            .keys
+            .iter()
+            .any(|k| k == key || key.strip_prefix("shift+") == Some(k.as_str()));
+    }
+}
+
+impl WhenNoBinding {
+    // the behavior for a given key (e.g. `shift+a`): this is only different from `self`
+    // for `Rules`
+    pub(crate) fn for_key(&self, key: &str) -> &WhenNoBinding {
+        return match self {
+            WhenNoBinding::Rules(rules) => match rules.iter().find(|r| r.matches(key)) {
+                Some(rule) => &rule.action,
+                Option::None => &WhenNoBinding::InsertCharacters,
+            },
+            x => x,
+        };
+    }
 }
 
 #[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum WhenNoBindingHeader {
     IgnoreCharacters,
     InsertCharacters,
     UseMode,
     Run,
+    Rules,
+}
+
+impl From<&WhenNoBinding> for WhenNoBindingHeader {
+    fn from(value: &WhenNoBinding) -> Self {
+        return match value {
+            WhenNoBinding::IgnoreCharacters => WhenNoBindingHeader::IgnoreCharacters,
+            WhenNoBinding::InsertCharacters => WhenNoBindingHeader::InsertCharacters,
+            WhenNoBinding::UseMode(_) => WhenNoBindingHeader::UseMode,
+            WhenNoBinding::Run(_) => WhenNoBindingHeader::Run,
+            WhenNoBinding::Rules(_) => WhenNoBindingHeader::Rules,
+        };
+    }
+}
+
+// the key (as listed by `all_characters`) that generates the typed text; e.g. `A` is
+// generated by `shift+a`
+pub(crate) fn typed_key(typed: &str) -> Option<String> {
+    let mut chars = typed.chars();
+    let c = match (chars.next(), chars.next()) {
+        (Some(c), Option::None) => c,
+        _ => {
+            return if typed == "\r\n" {
+                Some("enter".to_string())
+            } else {
+                None
+            };
+        }
+    };
+    return match c {
+        ' ' => Some("space".to_string()),
+        '\t' => Some("tab".to_string()),
+        '\n' | '\r' => Some("enter".to_string()),
+        c if c.is_ascii_graphic() => shifted_press(c).or_else(|| Some(c.to_string())),
+        _ => None,
+    };
 }
 
 impl LeafValue for WhenNoBinding {}
@@ -320,10 +723,41 @@ impl Resolving<WhenNoBinding> for WhenNoBindingInput {
             WhenNoBindingInput::InsertCharacters => WhenNoBinding::InsertCharacters,
             WhenNoBindingInput::UseMode(mode) => WhenNoBinding::UseMode(mode.resolve(name, scope)?),
             WhenNoBindingInput::Run(commands) => WhenNoBinding::Run(commands.resolve(name, scope)?),
+            WhenNoBindingInput::Rules(rules) => {
+                WhenNoBinding::Rules(flatten_errors(rules.into_iter().map(|rule| {
+                    return rule.resolve(name, scope);
+                }))?)
+            }
         });
     }
 }
 
+impl Resolving<WhenNoBindingRule> for WhenNoBindingRuleInput {
+    fn resolve(self, name: &'static str, scope: &mut Scope) -> ResultVec<WhenNoBindingRule> {
+        let action = match self.action {
+            WhenNoBindingInput::UseMode(_) => {
+                return Err(err(
+                    "`useMode` is not supported as a `whenNoBinding` rule action",
+                ))?;
+            }
+            WhenNoBindingInput::Rules(_) => {
+                return Err(err("`whenNoBinding` rules cannot be nested"))?;
+            }
+            x => x.resolve(name, scope)?,
+        };
+        scope.parse_asts(&self.keys)?;
+        let keys = match scope.expand(&self.keys)? {
+            Value::String(key) => vec![key],
+            Value::Array(items) => flatten_errors(items.into_iter().map(|item| match item {
+                Value::String(key) => Ok(key),
+                _ => Err(err("expected `keys` to be a list of strings"))?,
+            }))?,
+            _ => Err(err("expected `keys` to be a string or a list of strings"))?,
+        };
+        return Ok(WhenNoBindingRule { keys, action });
+    }
+}
+
 #[wasm_bindgen]
 impl Mode {
     pub(crate) fn new(
@@ -339,6 +773,11 @@ impl Mode {
                 }
             }
         }
+        if let Some(ref parent) = input.extends
+            && !scope.modes.contains(parent.as_ref())
+        {
+            Err(err!("mode `{}` is not defined", parent.as_ref())).with_range(&parent.span())?;
+        }
 
         // warning about unknown fields
         for (key, _) in &input.other_fields {
@@ -349,31 +788,155 @@ impl Mode {
             warnings.push(err.unwrap_err());
         }
 
+        if let Some(ref count) = input.returnAfter
+            && *count.as_ref() < 1
+        {
+            Err(err("`returnAfter` must be at least 1")).with_range(&count.span())?;
+        }
+        if let Some(ref mode) = input.returnTo {
+            if !scope.modes.contains(mode.as_ref()) {
+                Err(err!("mode `{}` is not defined", mode.as_ref())).with_range(&mode.span())?;
+            }
+            if mode.as_ref() == &input.name {
+                Err(err("`returnTo` must name a different mode")).with_range(&mode.span())?;
+            }
+        }
+        let return_after = match (&input.returnAfter, &input.returnTo) {
+            (Some(count), _) => Some(*count.as_ref()),
+            (Option::None, Some(_)) => Some(1),
+            (Option::None, Option::None) => None,
+        };
+
+        if let Some(ref color) = input.color
+            && !COLOR.is_match(color.as_ref())
+        {
+            Err(err!(
+                "`{}` is not a hex color (e.g. `#ff8800`) or theme color id",
+                color.as_ref()
+            ))
+            .with_range(&color.span())?;
+        }
+        if let Some(ref text) = input.statusText {
+            match text.as_ref() {
+                Value::String(_) | Value::Exp(_) | Value::Interp(_) => (),
+                _ => Err(err("expected `statusText` to be a string")).with_range(&text.span())?,
+            }
+            scope.parse_asts(text)?;
+        }
+
+        let on_enter = Mode::hook_commands(input.onEnter, scope, warnings)?;
+        let on_exit = Mode::hook_commands(input.onExit, scope, warnings)?;
+
+        // capture modes must let the user type, since typing is what they capture
+        let capture = input.capture.unwrap_or_default();
+        if capture {
+            if input.default.unwrap_or_default() {
+                Err(err("a capture mode cannot be the default mode"))?;
+            }
+            if let Some(ref when) = input.whenNoBinding
+                && !matches!(when.as_ref(), WhenNoBindingInput::InsertCharacters)
+            {
+                Err(err(
+                    "a capture mode must use `whenNoBinding = \"insertCharacters\"`",
+                ))
+                .with_range(&when.span())?;
+            }
+        }
+        let when_no_binding = match capture {
+            true => WhenNoBinding::InsertCharacters,
+            false => resolve!(input, whenNoBinding, scope)?,
+        };
+        let cancel_prefix = match &input.cancelPrefix {
+            Some(cancel) => match cancel.as_ref() {
+                CancelPrefixInput::Enabled(true) => Some("escape".to_string()),
+                CancelPrefixInput::Enabled(false) => None,
+                CancelPrefixInput::Key(key) => {
+                    if key.trim().contains(char::is_whitespace) {
+                        Err(err("`cancelPrefix` must be a single key press"))
+                            .with_range(&cancel.span())?;
+                    }
+                    valid_key_binding_str(key.trim()).with_range(&cancel.span())?;
+                    Some(canonical_press(key.trim()))
+                }
+            },
+            Option::None => None,
+        };
+        let cursor_shape = match (capture, &input.cursorShape) {
+            (true, Option::None) => CursorShape::Underline,
+            _ => resolve!(input, cursorShape, scope)?,
+        };
+
         return Ok(Mode {
             name: resolve!(input, name, scope)?,
             default: resolve!(input, default, scope)?,
+            capture,
             highlight: resolve!(input, highlight, scope)?,
-            cursorShape: resolve!(input, cursorShape, scope)?,
-            whenNoBinding: resolve!(input, whenNoBinding, scope)?,
+            cursorShape: cursor_shape,
+            color: input.color.map(Spanned::into_inner),
+            statusText: input.statusText,
+            lineNumbers: input.lineNumbers,
+            whenNoBinding: when_no_binding,
+            extends: input.extends.map(Spanned::into_inner),
+            onEnter: on_enter,
+            onExit: on_exit,
+            returnAfter: return_after,
+            returnTo: input.returnTo.map(Spanned::into_inner),
+            when: input.when.map(Spanned::into_inner),
+            priority: input.priority,
+            tags: input.tags.unwrap_or_default(),
+            kind: input.doc.and_then(|x| x.kind),
+            cancelPrefix: cancel_prefix,
+            count: input.count.unwrap_or_default(),
         });
     }
 
+    // regularize the commands listed under `onEnter` or `onExit`
+    fn hook_commands(
+        input: Option<Vec<Spanned<CommandInput>>>,
+        scope: &mut Scope,
+        warnings: &mut Vec<ParseError>,
+    ) -> ResultVec<Vec<Command>> {
+        let mut result = Vec::new();
+        let mut errors = Vec::new();
+        for command in input.into_iter().flatten() {
+            let span = command.span().clone();
+            let mut command_warnings = Vec::new();
+            command.as_ref().check_other_fields(&mut command_warnings);
+            match regularize_commands(command.as_ref(), scope, &mut command_warnings)
+                .with_range(&span)
+            {
+                Ok(mut commands) => result.append(&mut commands),
+                Err(mut e) => errors.append(&mut e.errors),
+            }
+            command_warnings
+                .iter_mut()
+                .for_each(|w| w.contexts.push(Context::Range(span.clone())));
+            warnings.append(&mut command_warnings);
+        }
+        if errors.len() > 0 {
+            return Err(errors.into());
+        }
+        return Ok(result);
+    }
+
     // this generates a sequence of commands used to ignore types characters in a mode
-    // using `whenNoBinding = "ignoreCharacters"`.
-    fn create_ignore_characters(name: &str, scope: &Scope, result: &mut Vec<BindingOutput>) {
-        for k in all_characters() {
+    // using `whenNoBinding = "ignoreCharacters"` (or the `keys` of a rule with that action)
+    fn create_ignore_characters(
+        name: &str,
+        scope: &Scope,
+        keys: impl Iterator<Item = String>,
+        result: &mut Vec<BindingOutput>,
+    ) {
+        for k in keys {
             let when: String;
             if name != &scope.default_mode {
-                when = format!("master-key.mode == '{}' && {TEXT_FOCUS_CONDITION}", name)
+                when = format!("master-key.mode == '{}'", name)
             } else {
-                when = format!(
-                    "(!master-key.mode || master-key.mode == '{}') && {TEXT_FOCUS_CONDITION}",
-                    name
-                )
+                when = format!("!master-key.mode || master-key.mode == '{}'", name)
             }
             result.push(BindingOutput::Ignore {
                 key: k,
-                when: Some(when),
+                when: scope.focus.restrict(Some(when)),
             });
         }
     }
@@ -384,11 +947,14 @@ impl Mode {
 pub struct Modes {
     pub(crate) map: HashMap<String, Mode>,
     pub default: String,
+    // the location of each mode defined in the current file (used to report warnings)
+    #[serde(skip)]
+    pub(crate) spans: HashMap<String, Range<usize>>,
 }
 
 impl Modes {
     pub(crate) fn new(
-        input: Option<Vec<Spanned<ModeInput>>>,
+        mut input: Option<Vec<Spanned<ModeInput>>>,
         source: Option<&crate::file::KeyFile>,
         scope: &mut Scope,
         warnings: &mut Vec<ParseError>,
@@ -397,15 +963,19 @@ impl Modes {
         // 1. there is one default mode,
         // 2. all modes are unique
         // 3. at least one mode allows the user to type keys
-        // 4. no mode is == "capture": this is a reserved mode used internally
+        // 4. a mode named "capture" is a capture mode: it is used by default to capture keys
         let mut all_mode_names = HashSet::new();
+        let mut capture_mode_names = HashSet::from(["capture".to_string()]);
         let mut default_mode = None;
         let mut first_mode_span = UNKNOWN_RANGE;
 
         if let Some(source_file) = source {
             default_mode = Some(source_file.mode.default.clone());
-            for mode in source_file.mode.map.keys() {
-                all_mode_names.insert(mode.clone());
+            for (name, mode) in &source_file.mode.map {
+                all_mode_names.insert(name.clone());
+                if mode.capture {
+                    capture_mode_names.insert(name.clone());
+                }
             }
         }
         let mut errors = Vec::new();
@@ -429,16 +999,19 @@ impl Modes {
                 }
                 default_mode = Some(mode_name.clone());
             }
-            if mode_name == "capture" {
+            if mode.as_ref().capture.unwrap_or_default() {
+                capture_mode_names.insert(mode_name.clone());
+            } else if mode_name == "capture" {
                 let result: Result<()> = Err(err!(
-                    "The mode `capture` is implicitly defined, and should never \
-                                be defined by the user."
+                    "The mode `capture` is used to capture keys by default, and must \
+                     set `capture = true`"
                 ))
                 .with_range(&mode.span());
                 errors.push(result.unwrap_err());
             }
             all_mode_names.insert(mode_name);
         }
+        Modes::inherit_appearance(&mut input, source, &mut errors);
 
         let old_modes = scope.modes.clone();
         let old_default_mode = scope.default_mode.clone();
@@ -447,9 +1020,11 @@ impl Modes {
 
         // create `Mode` objects
         let mut modes = HashMap::new();
+        let mut spans = HashMap::new();
         for mode in input.into_iter().flatten() {
             let span = mode.span().clone();
             let mode_name = mode.as_ref().name.clone();
+            spans.insert(mode_name.clone(), span.clone());
             let mut mode_warnings = Vec::new();
             match Mode::new(mode.into_inner(), scope, &mut mode_warnings).with_range(&span) {
                 Ok(x) => {
@@ -498,7 +1073,7 @@ impl Modes {
         // validate that at least one mode allows the user to type keys
         if !modes
             .iter()
-            .any(|(_, m)| m.whenNoBinding == WhenNoBinding::InsertCharacters)
+            .any(|(_, m)| !m.capture && m.whenNoBinding == WhenNoBinding::InsertCharacters)
         {
             match Err(err(
                 "`whenNoBinding='insertCharacters'` must be set for at least one mode; \
@@ -518,14 +1093,22 @@ impl Modes {
         }
 
         // define expression related functions that need to know the set of available modes
-        let all_modes_fn_data = scope.modes.clone();
+        // (capture modes are only entered by commands that capture keys, so they are
+        // excluded)
+        let user_modes: HashSet<String> = scope
+            .modes
+            .iter()
+            .filter(|x| !capture_mode_names.contains(*x))
+            .cloned()
+            .collect();
+        let all_modes_fn_data = user_modes.clone();
         scope.engine.register_fn("all_modes", move || {
             all_modes_fn_data
                 .iter()
                 .map(|x| rhai::Dynamic::from(ImmutableString::from(x)))
                 .collect::<rhai::Array>()
         });
-        let not_modes_fn_data = scope.modes.clone();
+        let not_modes_fn_data = user_modes;
         scope.engine.register_fn(
             "not_modes",
             move |x: rhai::Array| -> std::result::Result<rhai::Array, Box<EvalAltResult>> {
@@ -553,18 +1136,31 @@ impl Modes {
             },
         );
 
-        // add the implicit `capture` mode, it should not be known to functions above
-        // (hence it being inserted after these functions)
-        modes.insert(
-            "capture".to_string(),
-            Mode {
-                name: "capture".to_string(),
-                default: false,
-                highlight: ModeHighlight::NoHighlight,
-                cursorShape: CursorShape::Underline,
-                whenNoBinding: WhenNoBinding::InsertCharacters,
-            },
-        );
+        // add the implicit `capture` mode, unless it is defined by the user
+        scope.modes.insert("capture".to_string());
+        scope.capture_modes = capture_mode_names;
+        modes.entry("capture".to_string()).or_insert_with(|| Mode {
+            name: "capture".to_string(),
+            default: false,
+            capture: true,
+            highlight: ModeHighlight::NoHighlight,
+            cursorShape: CursorShape::Underline,
+            color: None,
+            statusText: None,
+            lineNumbers: None,
+            whenNoBinding: WhenNoBinding::InsertCharacters,
+            extends: None,
+            onEnter: Vec::new(),
+            onExit: Vec::new(),
+            returnAfter: None,
+            returnTo: None,
+            when: None,
+            priority: None,
+            tags: Vec::new(),
+            kind: None,
+            cancelPrefix: None,
+            count: false,
+        });
 
         if errors.len() > 0 {
             return Err(errors.into());
@@ -573,6 +1169,7 @@ impl Modes {
         return Ok(Modes {
             map: modes,
             default: default_mode.unwrap(),
+            spans,
         });
     }
 
@@ -580,6 +1177,84 @@ impl Modes {
         return self.map.get(x);
     }
 
+    // checks for cycles in `extends` and copies `highlight` and `cursorShape` from the
+    // nearest parent that defines them (when the mode does not)
+    fn inherit_appearance(
+        input: &mut Option<Vec<Spanned<ModeInput>>>,
+        source: Option<&crate::file::KeyFile>,
+        errors: &mut Vec<ParseError>,
+    ) {
+        let mut parents = HashMap::new();
+        let mut appearance = HashMap::new();
+        for (name, mode) in source.iter().flat_map(|x| x.mode.map.iter()) {
+            if let Some(parent) = &mode.extends {
+                parents.insert(name.clone(), parent.clone());
+            }
+            let shape = Some(mode.cursorShape.clone());
+            appearance.insert(name.clone(), (Some(mode.highlight.clone()), shape));
+        }
+        for mode in input.iter().flatten() {
+            let mode = mode.as_ref();
+            if let Some(parent) = &mode.extends {
+                parents.insert(mode.name.clone(), parent.as_ref().clone());
+            }
+            let shape = mode.cursorShape.clone();
+            appearance.insert(mode.name.clone(), (mode.highlight.clone(), shape));
+        }
+
+        let mut in_cycle = HashSet::new();
+        for mode in input.iter_mut().flatten() {
+            let span = match &mode.as_ref().extends {
+                Some(parent) => parent.span().clone(),
+                Option::None => continue,
+            };
+            let name = mode.as_ref().name.clone();
+            let mut chain = vec![name.clone()];
+            let mut highlight = mode.as_ref().highlight.clone();
+            let mut cursor_shape = mode.as_ref().cursorShape.clone();
+            while let Some(parent) = parents.get(chain.last().unwrap()) {
+                if chain.contains(parent) {
+                    if parent == &name && !in_cycle.contains(&name) {
+                        chain.push(name.clone());
+                        let names = chain
+                            .iter()
+                            .map(|x| format!("`{x}`"))
+                            .collect::<Vec<_>>()
+                            .join(" → ");
+                        let result: Result<()> =
+                            Err(err!("modes extend one another in a cycle: {names}"))
+                                .with_range(&span);
+                        errors.push(result.unwrap_err());
+                        in_cycle.extend(chain);
+                    }
+                    break;
+                }
+                if let Some((parent_highlight, parent_shape)) = appearance.get(parent) {
+                    highlight = highlight.or_else(|| parent_highlight.clone());
+                    cursor_shape = cursor_shape.or_else(|| parent_shape.clone());
+                }
+                chain.push(parent.clone());
+            }
+            let mode = mode.get_mut();
+            mode.highlight = highlight;
+            mode.cursorShape = cursor_shape;
+        }
+    }
+
+    // the modes that `mode` extends: its parent, grandparent and so on (nearest first)
+    pub(crate) fn ancestors(&self, mode: &str) -> Vec<&str> {
+        let mut result = Vec::new();
+        let mut current = self.map.get(mode);
+        while let Some(parent) = current.and_then(|x| x.extends.as_deref()) {
+            if parent == mode || result.contains(&parent) {
+                break;
+            }
+            result.push(parent);
+            current = self.map.get(parent);
+        }
+        return result;
+    }
+
     // helps to generated the `ignore` bindings when a mode doesn't allow the user to insert
     // characters
     fn ignore_character_bindings_helper(
@@ -593,7 +1268,23 @@ impl Modes {
             Some(Mode {
                 whenNoBinding: WhenNoBinding::IgnoreCharacters,
                 ..
-            }) => Mode::create_ignore_characters(parent_mode, scope, result),
+            }) => Mode::create_ignore_characters(
+                parent_mode,
+                scope,
+                all_characters().into_iter(),
+                result,
+            ),
+            Some(Mode {
+                whenNoBinding: when @ WhenNoBinding::Rules(_),
+                ..
+            }) => Mode::create_ignore_characters(
+                parent_mode,
+                scope,
+                all_characters()
+                    .into_iter()
+                    .filter(|k| when.for_key(k) == &WhenNoBinding::IgnoreCharacters),
+                result,
+            ),
             Some(Mode {
                 whenNoBinding: WhenNoBinding::InsertCharacters,
                 ..
@@ -612,6 +1303,115 @@ impl Modes {
         };
     }
 
+    // bindings that update the count, for modes with `count = true`; `bindings` lists all
+    // other bindings of the file, any of which replaces the count binding with the same
+    // key, mode and when clause. Each binding is paired with the span of its mode.
+    pub(crate) fn count_bindings<'a>(
+        &self,
+        bindings: impl Iterator<Item = &'a Binding>,
+        scope: &Scope,
+    ) -> Vec<(Binding, Range<usize>)> {
+        let when = scope.focus.restrict(None);
+        // an explicit binding of a digit replaces the count binding, unless its `when`
+        // clause depends on the count (e.g. `!master-key.count`)
+        let defined: HashSet<(&str, &str)> = bindings
+            .filter(|bind| {
+                let counted = bind
+                    .when
+                    .as_ref()
+                    .is_some_and(|w| w.contains("master-key.count"));
+                bind.key.len() == 1 && !counted
+            })
+            .flat_map(|bind| {
+                let key = bind.key[0].as_str();
+                bind.mode.iter().map(move |mode| (mode.as_str(), key))
+            })
+            .collect();
+        let mut modes: Vec<_> = self.map.values().filter(|x| x.count).collect();
+        modes.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut result = Vec::new();
+        for mode in modes {
+            let span = self.spans.get(&mode.name).cloned().unwrap_or(UNKNOWN_RANGE);
+            for digit in 0..10 {
+                for key in [format!("{digit}"), format!("numpad{digit}")] {
+                    if defined.contains(&(mode.name.as_str(), key.as_str())) {
+                        continue;
+                    }
+                    let args = IndexMap::from([("value".to_string(), Value::Integer(digit))]);
+                    let bind = Binding {
+                        section: None,
+                        key: vec![key.clone()],
+                        original_key: vec![key],
+                        commands: vec![Command {
+                            command: "master-key.updateCount".to_string(),
+                            args: Value::Table(args, None),
+                            skipWhen: TypedValue::default(),
+                        }],
+                        when: when.clone(),
+                        mode: vec![mode.name.clone()],
+                        priority: None,
+                        prefixes: Prefix::default(),
+                        finalKey: false,
+                        repeat: TypedValue::default(),
+                        tags: Vec::new(),
+                        doc: BindingDoc {
+                            name: format!("count {digit}"),
+                            description: format!(
+                                "Add digit {digit} to the count argument of a command"
+                            ),
+                            combined: Some(CombinedBindingDoc {
+                                name: "count 0-9".to_string(),
+                                key: "0-9".to_string(),
+                                description: "Add digit 0-9 to the count argument of a command"
+                                    .to_string(),
+                            }),
+                            ..BindingDoc::default()
+                        },
+                        implicit: true,
+                    };
+                    result.push((bind, span.clone()));
+                }
+            }
+        }
+        return result;
+    }
+
+    // bindings that clear a pending prefix, for modes with `cancelPrefix`; these are
+    // generated for every prefix of the mode, so they must be created after all other
+    // bindings
+    pub(crate) fn cancel_prefix_bindings(
+        &self,
+        codes: &BindingCodes,
+        scope: &Scope,
+    ) -> Vec<BindingOutput> {
+        let mut result = Vec::new();
+        let mut modes: Vec<_> = self.map.values().collect();
+        modes.sort_by(|a, b| a.name.cmp(&b.name));
+        for mode in modes {
+            let Some(key) = &mode.cancelPrefix else {
+                continue;
+            };
+            let mode_when = if mode.name != scope.default_mode {
+                format!("master-key.mode == '{}'", mode.name)
+            } else {
+                format!("(!master-key.mode || master-key.mode == '{}')", mode.name)
+            };
+            for code in codes.prefix_codes(&mode.name) {
+                let mut when = Some(format!("{mode_when} && master-key.prefixCode == {code}"));
+                // like any other bare key (see `Binding::new`)
+                if !KEY_WITH_MODIFIER.is_match(key) {
+                    when = scope.focus.restrict(when);
+                }
+                result.push(BindingOutput::Ignore {
+                    key: key.clone(),
+                    when,
+                });
+            }
+        }
+        return result;
+    }
+
     pub(crate) fn ignore_character_bindings(&self, scope: &Scope) -> Vec<BindingOutput> {
         let mut result = Vec::new();
         for mode in self.map.keys() {
@@ -620,7 +1420,7 @@ impl Modes {
         return result;
     }
 
-    // implements `whenNoBindings.useMode = "otherMode"`
+    // implements `whenNoBindings.useMode = "otherMode"` and `extends = "otherMode"`
     pub(crate) fn insert_implicit_mode_bindings(
         &self,
         bindings: &Vec<Binding>,
@@ -648,6 +1448,25 @@ impl Modes {
             }
         }
 
+        // and implicit keybindings for any parent mode (ala `extends`); a binding with the
+        // same key in the mode or a closer parent shadows the parent binding, regardless
+        // of `when`: two bindings of a key with different `when` clauses would both be
+        // active whenever both clauses hold, with no well-defined precedence
+        let ancestors: HashMap<&str, Vec<&str>> = self
+            .map
+            .keys()
+            .map(|mode| (mode.as_str(), self.ancestors(mode)))
+            .filter(|(_, parents)| !parents.is_empty())
+            .collect();
+        let defined: HashSet<(&str, String)> = bindings
+            .iter()
+            .flat_map(|bind| {
+                bind.mode
+                    .iter()
+                    .map(move |mode| (mode.as_str(), bind.key.join(" ")))
+            })
+            .collect();
+
         for (id, bind) in bindings.iter().enumerate() {
             let mut implicit_modes = Vec::new();
             for mode in &bind.mode {
@@ -656,12 +1475,23 @@ impl Modes {
                         implicit_modes.push(String::from(*from));
                     }
                 }
+                let key = bind.key.join(" ");
+                for (child, parents) in &ancestors {
+                    if let Some(depth) = parents.iter().position(|x| x == mode) {
+                        let shadowed = std::iter::once(child)
+                            .chain(parents[0..depth].iter())
+                            .any(|x| defined.contains(&(*x, key.clone())));
+                        if !shadowed && !implicit_modes.iter().any(|x| x == child) {
+                            implicit_modes.push(child.to_string());
+                        }
+                    }
+                }
             }
             let mut implicit_bind = bind.clone();
             implicit_bind.mode = implicit_modes;
             implicit_bind.implicit = true;
             let mut output = match implicit_bind
-                .outputs(id as i32, hash, &scope, false, None, codes, warnings)
+                .outputs(id as i32, hash, &scope, self, false, None, codes, warnings)
             {
                 Ok(x) => x,
                 // silently ignore errors; these will be reported for the explicit bindings
@@ -669,6 +1499,9 @@ impl Modes {
             };
             key_bind.append(&mut output);
         }
+
+        // and keybindings to cancel a pending prefix, for modes that request it
+        key_bind.append(&mut self.cancel_prefix_bindings(codes, scope));
     }
 }
 
@@ -680,12 +1513,28 @@ impl Default for Modes {
                 Mode {
                     name: "default".to_string(),
                     default: true,
+                    capture: false,
                     highlight: ModeHighlight::default(),
                     cursorShape: CursorShape::default(),
+                    color: None,
+                    statusText: None,
+                    lineNumbers: None,
                     whenNoBinding: WhenNoBinding::InsertCharacters,
+                    extends: None,
+                    onEnter: Vec::new(),
+                    onExit: Vec::new(),
+                    returnAfter: None,
+                    returnTo: None,
+                    when: None,
+                    priority: None,
+                    tags: Vec::new(),
+                    kind: None,
+                    cancelPrefix: None,
+                    count: false,
                 },
             )]),
             default: "default".to_string(),
+            spans: HashMap::new(),
         };
     }
 }
@@ -0,0 +1,289 @@
+#[allow(unused_imports)]
+use log::info;
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::ops::Range;
+
+use crate::bind::Binding;
+use crate::bind::command::Command;
+use crate::error::{ErrorContext, ParseError, Result};
+use crate::expression::value::Value;
+use crate::mode::{Modes, WhenNoBinding};
+use crate::wrn;
+
+//
+// ---------------- Mode Transitions ----------------
+//
+
+// The mode graph records every change of mode the commands of a file can cause. It is
+// used to warn about modes a user could get stuck in (or never reach) and to visualize the
+// modes of a file (as a DOT graph) in its documentation.
+
+// the mode a transition switches to
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum ModeTarget {
+    Mode(String),
+    // the mode is computed by an expression, so it is only known at runtime
+    Dynamic,
+    // the mode that was active before the current one (`returnAfter` without `returnTo`)
+    Previous,
+}
+
+// where a transition is defined (used to report problems)
+#[derive(Clone, Debug)]
+enum TransitionSource {
+    // the index of a binding
+    Binding(usize),
+    // the commands or `returnAfter` of a mode
+    Mode(String),
+}
+
+#[derive(Debug)]
+pub(crate) struct ModeGraph {
+    // all modes, excluding capture modes (which are only entered while capturing keys)
+    modes: BTreeSet<String>,
+    default: String,
+    // the triggers (e.g. key sequences) of each transition
+    edges: BTreeMap<(String, ModeTarget), BTreeSet<String>>,
+    // transitions to a mode that is not defined: the command and its target
+    undefined: Vec<(TransitionSource, String, String)>,
+}
+
+// the mode a command switches to, if any
+fn command_target(command: &Command) -> Option<ModeTarget> {
+    return match command.command.as_str() {
+        "master-key.setMode" => match &command.args {
+            Value::Table(args, _) => match args.get("value") {
+                Some(Value::String(mode)) => Some(ModeTarget::Mode(mode.clone())),
+                _ => Some(ModeTarget::Dynamic),
+            },
+            _ => Some(ModeTarget::Dynamic),
+        },
+        "master-key.enterInsert" => Some(ModeTarget::Mode("insert".to_string())),
+        "master-key.enterNormal" => Some(ModeTarget::Mode("normal".to_string())),
+        _ => None,
+    };
+}
+
+// the name of a node or the label of an edge in a DOT graph
+fn dot_string(x: &str) -> String {
+    return format!("\"{}\"", x.replace('\\', "\\\\").replace('"', "\\\""));
+}
+
+impl ModeGraph {
+    pub(crate) fn new(modes: &Modes, bindings: &[Binding]) -> Self {
+        let mut graph = ModeGraph {
+            modes: modes
+                .map
+                .values()
+                .filter(|x| !x.capture)
+                .map(|x| x.name.clone())
+                .collect(),
+            default: modes.default.clone(),
+            edges: BTreeMap::new(),
+            undefined: Vec::new(),
+        };
+
+        for (i, bind) in bindings.iter().enumerate() {
+            let key = bind.key.join(" ");
+            // bindings are also active in modes that inherit them (via `extends` or
+            // `whenNoBinding.useMode`; see `insert_implicit_mode_bindings`)
+            let from: BTreeSet<&str> = modes
+                .map
+                .values()
+                .filter(|mode| {
+                    bind.mode.iter().any(|x| {
+                        x == &mode.name
+                            || modes.ancestors(&mode.name).contains(&x.as_str())
+                            || mode.whenNoBinding == WhenNoBinding::UseMode(x.clone())
+                    })
+                })
+                .map(|mode| mode.name.as_str())
+                .collect();
+            for command in &bind.commands {
+                graph.add_transitions(
+                    from.iter().copied(),
+                    command,
+                    &key,
+                    TransitionSource::Binding(i),
+                );
+            }
+        }
+
+        for mode in modes.map.values() {
+            let source = TransitionSource::Mode(mode.name.clone());
+            let from = std::iter::once(mode.name.as_str());
+            for command in &mode.onEnter {
+                graph.add_transitions(from.clone(), command, "onEnter", source.clone());
+            }
+            let typed = match &mode.whenNoBinding {
+                WhenNoBinding::Run(commands) => commands.iter().collect(),
+                WhenNoBinding::Rules(rules) => rules
+                    .iter()
+                    .flat_map(|rule| match &rule.action {
+                        WhenNoBinding::Run(commands) => commands.iter().collect(),
+                        _ => Vec::new(),
+                    })
+                    .collect(),
+                _ => Vec::new(),
+            };
+            for command in typed {
+                graph.add_transitions(from.clone(), command, "whenNoBinding", source.clone());
+            }
+            if mode.returnAfter.is_some() {
+                let to = match &mode.returnTo {
+                    Some(to) => ModeTarget::Mode(to.clone()),
+                    Option::None => ModeTarget::Previous,
+                };
+                graph.add_edge(&mode.name, to, "returnAfter");
+            }
+        }
+
+        return graph;
+    }
+
+    fn add_transitions<'a>(
+        &mut self,
+        from: impl Iterator<Item = &'a str>,
+        command: &Command,
+        trigger: &str,
+        source: TransitionSource,
+    ) {
+        let Some(to) = command_target(command) else {
+            return;
+        };
+        if let ModeTarget::Mode(mode) = &to
+            && !self.modes.contains(mode)
+        {
+            self.undefined
+                .push((source, command.command.clone(), mode.clone()));
+            return;
+        }
+        for mode in from {
+            self.add_edge(mode, to.clone(), trigger);
+        }
+    }
+
+    fn add_edge(&mut self, from: &str, to: ModeTarget, trigger: &str) {
+        if !self.modes.contains(from) {
+            return;
+        }
+        self.edges
+            .entry((from.to_string(), to))
+            .or_default()
+            .insert(trigger.to_string());
+    }
+
+    /// Warns about modes a user can never leave, modes that can never be entered and
+    /// changes to undefined modes. Problems are only reported for modes and bindings with a
+    /// known location (`spans` lists the location of each binding, if any), i.e. those of
+    /// the current file rather than of its `source`.
+    pub(crate) fn lint(
+        &self,
+        modes: &Modes,
+        spans: &[Option<Range<usize>>],
+        warnings: &mut Vec<ParseError>,
+    ) {
+        let mut report = |message: ParseError, span: Option<&Range<usize>>| {
+            if let Some(span) = span {
+                let result: Result<()> = Err(message).with_range(span);
+                warnings.push(result.unwrap_err());
+            }
+        };
+
+        for (source, command, mode) in &self.undefined {
+            let span = match source {
+                TransitionSource::Binding(i) => spans.get(*i).and_then(Option::as_ref),
+                TransitionSource::Mode(name) => modes.spans.get(name),
+            };
+            report(
+                wrn!("`{command}` switches to the undefined mode `{mode}`"),
+                span,
+            );
+        }
+
+        // a lone mode has nowhere to go
+        if self.modes.len() > 1 {
+            for mode in &self.modes {
+                let exits = self
+                    .edges
+                    .keys()
+                    .any(|(from, to)| from == mode && to != &ModeTarget::Mode(mode.clone()));
+                if !exits {
+                    report(
+                        wrn!(
+                            "no binding leaves the mode `{mode}`; once entered, the user \
+                             cannot switch to another mode"
+                        ),
+                        modes.spans.get(mode),
+                    );
+                }
+            }
+        }
+
+        // when a mode is computed at runtime, any mode could be reachable
+        if self.edges.keys().any(|(_, to)| to == &ModeTarget::Dynamic) {
+            return;
+        }
+        let mut reached = BTreeSet::from([self.default.as_str()]);
+        let mut queue = VecDeque::from([self.default.as_str()]);
+        while let Some(current) = queue.pop_front() {
+            for (from, to) in self.edges.keys() {
+                if let ModeTarget::Mode(to) = to
+                    && from == current
+                    && reached.insert(to.as_str())
+                {
+                    queue.push_back(to.as_str());
+                }
+            }
+        }
+        for mode in &self.modes {
+            if !reached.contains(mode.as_str()) {
+                report(
+                    wrn!(
+                        "the mode `{mode}` can never be entered; no binding switches to it \
+                         from the default mode `{}`",
+                        self.default
+                    ),
+                    modes.spans.get(mode),
+                );
+            }
+        }
+    }
+
+    /// Renders the graph in the [DOT](https://graphviz.org/doc/info/lang.html) format.
+    /// Each edge is labeled with the keys (or mode fields) that cause the transition.
+    pub(crate) fn dot(&self) -> String {
+        let mut result = String::from("digraph modes {\n");
+        for mode in &self.modes {
+            if mode == &self.default {
+                result.push_str(&format!("    {} [shape=doublecircle];\n", dot_string(mode)));
+            } else {
+                result.push_str(&format!("    {} [shape=circle];\n", dot_string(mode)));
+            }
+        }
+        for (target, name) in [
+            (ModeTarget::Dynamic, "(computed)"),
+            (ModeTarget::Previous, "(previous)"),
+        ] {
+            if self.edges.keys().any(|(_, to)| to == &target) {
+                result.push_str(&format!("    {} [shape=plaintext];\n", dot_string(name)));
+            }
+        }
+        for ((from, to), triggers) in &self.edges {
+            let to = match to {
+                ModeTarget::Mode(mode) => dot_string(mode),
+                ModeTarget::Dynamic => dot_string("(computed)"),
+                ModeTarget::Previous => dot_string("(previous)"),
+            };
+            let label = triggers.iter().cloned().collect::<Vec<_>>().join(", ");
+            result.push_str(&format!(
+                "    {} -> {to} [label={}];\n",
+                dot_string(from),
+                dot_string(&label)
+            ));
+        }
+        result.push_str("}\n");
+        return result;
+    }
+}
@@ -0,0 +1,278 @@
+#[allow(unused_imports)]
+use log::info;
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use toml::Spanned;
+
+use crate::bind::tree::TreeGroup;
+use crate::bind::{Binding, BindingDoc, BindingOutput};
+use crate::error::{ErrorContext, ParseError, Result, ResultVec};
+use crate::expression::Scope;
+use crate::key::canonical_sequence;
+use crate::util::Plural;
+use crate::{err, wrn};
+
+/// @bindingField prefix
+/// @description documentation for the key sequences that lead to other bindings
+///
+/// Every key sequence that starts a longer binding (e.g. `space g` of `space g s`) is a
+/// prefix. Unless a binding that runs [`master-key.prefix`](/commands/prefix) defines it,
+/// a prefix is generated automatically, and it is shown in the suggestion palette and the
+/// visual documentation as "prefix". A `[[prefix]]` entry names such a prefix, as in the
+/// group labels of which-key.
+///
+/// **Example**
+///
+/// ```toml
+/// [[prefix]]
+/// key = "space g"
+/// doc.name = "git"
+/// doc.description = "commands related to source control"
+///
+/// [[bind]]
+/// key = "space g s"
+/// command = "workbench.view.scm"
+/// ```
+///
+/// ## Fields
+///
+/// - `key`: the key sequence of the prefix, written in the notation of
+///   [`header.keyNotation`](/bindings/header)
+/// - `mode`: (defaults to the default mode) the mode, or modes, the prefix is documented for
+/// - `doc.name`: the name shown for the prefix
+/// - `doc.description`: a sentence or two about the bindings that start with the prefix
+///
+/// The entry is also listed in the textual documentation. It is a warning to document a
+/// prefix that no binding of the mode starts with. A prefix defined by a binding that
+/// runs `master-key.prefix` is documented by that binding's `doc` instead.
+#[derive(Deserialize, Clone, Debug)]
+pub struct PrefixDocInput {
+    key: Spanned<String>,
+    mode: Option<Spanned<Plural<String>>>,
+    doc: Option<PrefixDocTextInput>,
+    #[serde(flatten)]
+    other_fields: HashMap<String, toml::Value>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+struct PrefixDocTextInput {
+    name: Option<String>,
+    description: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct PrefixDoc {
+    // the canonical key presses of the prefix
+    pub(crate) key: Vec<String>,
+    // the key presses as written (shown in the documentation)
+    pub(crate) original_key: Vec<String>,
+    pub(crate) mode: Vec<String>,
+    pub(crate) doc: BindingDoc,
+    // the span of the `[[prefix]]` entry; `None` for entries of a `source` file
+    pub(crate) span: Option<Range<usize>>,
+}
+
+impl PrefixDoc {
+    pub(crate) fn new(
+        input: Option<Vec<Spanned<PrefixDocInput>>>,
+        scope: &Scope,
+        warnings: &mut Vec<ParseError>,
+    ) -> ResultVec<Vec<PrefixDoc>> {
+        let mut errors = Vec::new();
+        let mut result = Vec::new();
+        for item in input.into_iter().flatten() {
+            let span = item.span().clone();
+            let item = item.into_inner();
+
+            // warning about unknown fields
+            for (key, _) in &item.other_fields {
+                let err: Result<()> = Err(wrn!(
+                    "The field `{}` is unrecognized and will be ignored",
+                    key,
+                ))
+                .with_range(&span);
+                warnings.push(err.unwrap_err());
+            }
+
+            let key_span = item.key.span();
+            let key = match scope.key_notation.translate_key(&item.key, true, scope) {
+                Ok(x) if !x.trim().is_empty() => x,
+                Ok(_) => {
+                    let error: Result<()> =
+                        Err(err!("`key` must have at least one key press")).with_range(&key_span);
+                    errors.push(error.unwrap_err());
+                    continue;
+                }
+                Err(e) => {
+                    errors.push(e);
+                    continue;
+                }
+            };
+
+            let mode = match item.mode {
+                Some(mode) => {
+                    let mode_span = mode.span();
+                    let mode: Vec<String> = mode.into_inner().into();
+                    let undefined: Vec<_> = mode
+                        .iter()
+                        .filter(|x| !scope.modes.contains(x.as_str()))
+                        .map(|x| x.as_str())
+                        .collect();
+                    if undefined.len() > 0 {
+                        let error: Result<()> =
+                            Err(err!("Undefined mode(s): {}", undefined.join(", ")))
+                                .with_range(&mode_span);
+                        errors.push(error.unwrap_err());
+                        continue;
+                    }
+                    mode
+                }
+                Option::None => vec![scope.default_mode.clone()],
+            };
+
+            let text = item.doc.unwrap_or(PrefixDocTextInput {
+                name: None,
+                description: None,
+            });
+            result.push(PrefixDoc {
+                key: canonical_sequence(&key)
+                    .split(' ')
+                    .map(String::from)
+                    .collect(),
+                original_key: key.split_whitespace().map(String::from).collect(),
+                mode,
+                doc: BindingDoc {
+                    name: text.name.unwrap_or_default(),
+                    description: text.description.unwrap_or_default(),
+                    ..BindingDoc::default()
+                },
+                span: Some(span),
+            });
+        }
+
+        if errors.len() > 0 {
+            return Err(errors.into());
+        }
+        return Ok(result);
+    }
+
+    /// The documented groups of keys of `[tree]`; their modes are those of the bindings
+    /// that start with the keys of the group (see `tree_modes`)
+    pub(crate) fn from_tree(
+        groups: Vec<TreeGroup>,
+        scope: &mut Scope,
+    ) -> ResultVec<Vec<PrefixDoc>> {
+        let mut errors = Vec::new();
+        let mut result = Vec::new();
+        for group in groups {
+            let span = group.key.span();
+            let key = match scope.key_notation.translate_key(&group.key, false, scope) {
+                Ok(x) => x,
+                Err(e) => {
+                    errors.push(e);
+                    continue;
+                }
+            };
+            let doc = scope
+                .parse_asts(&group.doc)
+                .and_then(|_| BindingDoc::new(group.doc, true, scope));
+            let doc = match doc {
+                Ok(x) => x,
+                Err(mut e) => {
+                    errors.append(&mut e.errors);
+                    continue;
+                }
+            };
+            result.push(PrefixDoc {
+                key: canonical_sequence(&key)
+                    .split(' ')
+                    .map(String::from)
+                    .collect(),
+                original_key: key.split_whitespace().map(String::from).collect(),
+                mode: Vec::new(),
+                doc,
+                span: Some(span),
+            });
+        }
+
+        if errors.len() > 0 {
+            return Err(errors.into());
+        }
+        return Ok(result);
+    }
+
+    // the modes of the groups of `[tree]`: every mode of a binding that starts with the
+    // keys of the group
+    pub(crate) fn tree_modes(prefixes: &mut [PrefixDoc], bind: &[Binding]) {
+        for prefix in prefixes.iter_mut().filter(|x| x.mode.is_empty()) {
+            let len = prefix.key.len();
+            for bind_item in bind {
+                if bind_item.key.len() > len && bind_item.key[..len] == prefix.key[..] {
+                    for mode in &bind_item.mode {
+                        if !prefix.mode.contains(mode) {
+                            prefix.mode.push(mode.clone());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // rewrite the key presses of the prefix (see `Binding::map_keys`)
+    pub(crate) fn map_keys(&mut self, f: impl Fn(&str) -> String) {
+        self.key = self.key.iter().map(|k| f(k)).collect();
+        self.original_key = self.original_key.iter().map(|k| f(k)).collect();
+    }
+
+    /// Names the generated prefix bindings of `key_bind` documented by `prefixes`, and
+    /// warns about the entries of this file that document a prefix no binding starts with
+    pub(crate) fn attach(
+        prefixes: &[PrefixDoc],
+        key_bind: &mut [BindingOutput],
+        warnings: &mut Vec<ParseError>,
+    ) {
+        let mut docs = HashMap::new();
+        for prefix in prefixes {
+            for mode in &prefix.mode {
+                docs.insert((prefix.key.join(" "), mode.as_str()), &prefix.doc);
+            }
+        }
+
+        // every prefix is the prefix of some binding's key press
+        let mut produced = HashSet::new();
+        for output in key_bind.iter_mut() {
+            match output {
+                BindingOutput::Prefix { args, .. } => {
+                    if let Some(doc) = docs.get(&(args.key.clone(), args.mode.as_str())) {
+                        args.name = doc.name.clone();
+                        args.description = doc.description.clone();
+                    }
+                    produced.insert((args.key.clone(), args.mode.clone()));
+                }
+                BindingOutput::Do { args, .. } => {
+                    produced.insert((args.prefix.clone(), args.mode.clone()));
+                }
+                BindingOutput::Ignore { .. } => (),
+            }
+        }
+
+        for prefix in prefixes {
+            let Some(span) = &prefix.span else {
+                continue;
+            };
+            let key = prefix.key.join(" ");
+            for mode in &prefix.mode {
+                if !produced.contains(&(key.clone(), mode.clone())) {
+                    let error: Result<()> = Err(wrn!(
+                        "No binding of mode `{mode}` starts with the prefix `{key}`"
+                    ))
+                    .with_range(span);
+                    warnings.push(error.unwrap_err());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,255 @@
+#[allow(unused_imports)]
+use log::info;
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::ops::Range;
+use toml::Spanned;
+
+use crate::bind::Binding;
+use crate::bind::prefix::Prefix;
+use crate::bind::validation::valid_key_binding_str;
+use crate::error::{ErrorContext, ParseError, Result, ResultVec};
+use crate::key::canonical_press;
+use crate::{err, wrn};
+
+/// @bindingField remap
+/// @description substitute one key press for another across all bindings
+///
+/// A remap replaces a key press with another key press in every binding of the file,
+/// including those of its `source`. This makes it easy to adjust a handful of keys of a
+/// preset without redefining its bindings.
+///
+/// **Example**
+///
+/// ```toml
+/// [header]
+/// version = "2.2.0"
+/// source = "Larkin"
+///
+/// # move the leader key
+/// [[remap]]
+/// from = "space"
+/// to = ","
+///
+/// # swap `;` and `:`
+/// [[remap]]
+/// from = ";"
+/// to = "shift+;"
+///
+/// [[remap]]
+/// from = "shift+;"
+/// to = ";"
+/// ```
+///
+/// ## Fields
+///
+/// - `from`: a single key press (e.g. `space` or `shift+;`)
+/// - `to`: the key press that replaces `from`
+///
+/// A key press only matches `from` when it has exactly the same modifiers (in any order or
+/// case): `from = "k"` replaces `k` but not `ctrl+k`. All remaps are applied at once, so
+/// two entries can swap a pair of keys. They apply to `bind.key` and to the prefixes listed
+/// in `bind.prefixes`, before any [`layout`](/bindings/header) is applied. It is an error
+/// for a remap to give two bindings the same key sequence in the same mode (and with the
+/// same `when` clause).
+#[derive(Deserialize, Clone, Debug)]
+pub struct RemapInput {
+    from: Spanned<String>,
+    to: Spanned<String>,
+    #[serde(flatten)]
+    other_fields: HashMap<String, toml::Value>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Remap {
+    // the key press each (canonical) key press is replaced with, and the span of the
+    // `[[remap]]` entry that defines it
+    keys: HashMap<String, (String, Range<usize>)>,
+}
+
+// the key sequences, modes and `when` clause that identify a binding
+type BindingIds = Vec<(String, String, Option<String>)>;
+
+impl Remap {
+    pub(crate) fn new(
+        input: Option<Vec<Spanned<RemapInput>>>,
+        warnings: &mut Vec<ParseError>,
+    ) -> ResultVec<Remap> {
+        let mut errors = Vec::new();
+        let mut keys: HashMap<String, (String, Range<usize>)> = HashMap::new();
+        for item in input.into_iter().flatten() {
+            let span = item.span().clone();
+            let item = item.into_inner();
+
+            // warning about unknown fields
+            for (key, _) in &item.other_fields {
+                let err: Result<()> = Err(wrn!(
+                    "The field `{}` is unrecognized and will be ignored",
+                    key,
+                ))
+                .with_range(&span);
+                warnings.push(err.unwrap_err());
+            }
+
+            let mut valid = true;
+            for (name, press) in [("from", &item.from), ("to", &item.to)] {
+                let result = if press.as_ref().trim().contains(char::is_whitespace) {
+                    Err(err!("`{name}` must be a single key press").into())
+                } else {
+                    valid_key_binding_str(press.as_ref().trim())
+                };
+                if let Err(e) = result.with_range(&press.span()) {
+                    errors.push(e);
+                    valid = false;
+                }
+            }
+            if !valid {
+                continue;
+            }
+
+            let from = canonical_press(item.from.as_ref().trim());
+            if let Some((_, old_span)) = keys.get(&from) {
+                let errs: Vec<Result<()>> = vec![
+                    Err(err!(
+                        "`{from}` is remapped more than once; also remapped at "
+                    ))
+                    .with_range(&span)
+                    .with_ref_range(old_span),
+                    Err(err!(
+                        "`{from}` is remapped more than once; also remapped at "
+                    ))
+                    .with_range(old_span)
+                    .with_ref_range(&span),
+                ];
+                errors.extend(errs.into_iter().map(Result::unwrap_err));
+                continue;
+            }
+            let to = canonical_press(item.to.as_ref().trim());
+            keys.insert(from, (to, span));
+        }
+
+        if errors.len() > 0 {
+            return Err(errors.into());
+        }
+        return Ok(Remap { keys });
+    }
+
+    // the key press that replaces `press`, along with the span of its `[[remap]]` entry
+    fn press(&self, press: &str) -> Option<&(String, Range<usize>)> {
+        return self.keys.get(&canonical_press(press));
+    }
+
+    /// Replaces each key press of a sequence of key presses (e.g. `space f`)
+    pub(crate) fn sequence(&self, keys: &str) -> String {
+        return keys
+            .split_whitespace()
+            .map(|press| match self.press(press) {
+                Some((to, _)) => to.clone(),
+                Option::None => press.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+    }
+
+    // the key sequences (including any explicit prefixes) a binding is triggered by, in
+    // each of its modes
+    fn binding_ids(bind: &Binding) -> BindingIds {
+        let prefixes = match &bind.prefixes {
+            Prefix::Any(false) => vec![String::new()],
+            Prefix::AnyOf(x) => x.clone(),
+            // these cannot collide until all prefixes are known
+            Prefix::Any(true) | Prefix::AllBut(_) => Vec::new(),
+        };
+        let key = bind.key.join(" ");
+        let mut result = Vec::new();
+        for prefix in prefixes {
+            let seq = format!("{prefix} {key}").trim().to_string();
+            for mode in &bind.mode {
+                result.push((seq.clone(), mode.clone(), bind.when.clone()));
+            }
+        }
+        return result;
+    }
+
+    /// Rewrites the keys of all bindings (see `Binding::map_keys`), reporting any two
+    /// bindings that the rewrite gives the same key sequence. `spans` lists the location of
+    /// each binding, if known.
+    pub(crate) fn apply(
+        &self,
+        bind: &mut [Binding],
+        spans: &[Option<Range<usize>>],
+    ) -> ResultVec<()> {
+        if self.keys.is_empty() {
+            return Ok(());
+        }
+
+        let mut old_ids = Vec::new();
+        // the span of a `[[remap]]` entry applied to each binding
+        let mut remap_spans = Vec::new();
+        for bind_item in bind.iter_mut() {
+            old_ids.push(Remap::binding_ids(bind_item));
+            let presses = bind_item.key.iter().chain(match &bind_item.prefixes {
+                Prefix::AnyOf(x) | Prefix::AllBut(x) => x.iter(),
+                Prefix::Any(_) => [].iter(),
+            });
+            let remap_span = presses
+                .flat_map(|x| x.split_whitespace())
+                .find_map(|press| self.press(press))
+                .map(|(_, span)| span.clone());
+            remap_spans.push(remap_span);
+            bind_item.map_keys(|k| self.sequence(k));
+        }
+
+        let mut bound: HashMap<_, Vec<usize>> = HashMap::new();
+        for (i, bind_item) in bind.iter().enumerate() {
+            for id in Remap::binding_ids(bind_item) {
+                bound.entry(id).or_default().push(i);
+            }
+        }
+        let mut collisions: Vec<_> = bound
+            .into_iter()
+            .flat_map(|(id, indices)| {
+                let mut pairs = Vec::new();
+                for (n, i) in indices.iter().enumerate() {
+                    for j in &indices[(n + 1)..] {
+                        // bindings that collided before the remap are reported elsewhere
+                        if i != j && !(old_ids[*i].contains(&id) && old_ids[*j].contains(&id)) {
+                            pairs.push((id.clone(), *i, *j));
+                        }
+                    }
+                }
+                pairs
+            })
+            .collect();
+        collisions.sort_by_key(|(_, i, j)| (*i, *j));
+
+        let mut errors = Vec::new();
+        let span_of = |i: usize, other: usize| {
+            return spans[i]
+                .clone()
+                .or(remap_spans[i].clone())
+                .or(remap_spans[other].clone());
+        };
+        for ((seq, mode, _), i, j) in collisions {
+            let (span_i, span_j) = (span_of(i, j), span_of(j, i));
+            let message = format!(
+                "After applying `[[remap]]`, `{seq}` is bound more than once in mode \
+                 `{mode}`; also bound at "
+            );
+            let errs: Vec<Result<()>> = vec![
+                Err(err!("{message}"))
+                    .with_range(&span_i)
+                    .with_ref_range(&span_j),
+                Err(err!("{message}"))
+                    .with_range(&span_j)
+                    .with_ref_range(&span_i),
+            ];
+            errors.extend(errs.into_iter().map(Result::unwrap_err));
+        }
+        if errors.len() > 0 {
+            return Err(errors.into());
+        }
+        return Ok(());
+    }
+}
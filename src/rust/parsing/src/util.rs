@@ -1,6 +1,7 @@
 #[allow(unused_imports)]
 use log::info;
 
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use toml::Spanned;
@@ -57,22 +58,22 @@ impl<T: Merging + Clone> Merging for HashMap<String, T> {
     }
 }
 
-// impl<T: Merging + Clone> Merging for IndexMap<String, T> {
-//     fn coalesce(self, new: Self) -> Self {
-//         return new;
-//     }
-//     fn merge(self, new: Self) -> Self {
-//         let (mut to_merge, to_append): (IndexMap<_, _>, IndexMap<_, _>) =
-//             new.into_iter().partition(|(k, _)| self.get(k).is_some());
-//         let pairs = self
-//             .into_iter()
-//             .map(|(k, v)| match to_merge.shift_remove(&k) {
-//                 Some(new_v) => (k, v.merge(new_v)),
-//                 Option::None => (k, v),
-//             });
-//         return pairs.chain(to_append.into_iter()).collect();
-//     }
-// }
+impl<T: Merging + Clone> Merging for IndexMap<String, T> {
+    fn coalesce(self, new: Self) -> Self {
+        return new;
+    }
+    fn merge(self, new: Self) -> Self {
+        let (mut to_merge, to_append): (IndexMap<_, _>, IndexMap<_, _>) =
+            new.into_iter().partition(|(k, _)| self.get(k).is_some());
+        let pairs = self
+            .into_iter()
+            .map(|(k, v)| match to_merge.shift_remove(&k) {
+                Some(new_v) => (k, v.merge(new_v)),
+                Option::None => (k, v),
+            });
+        return pairs.chain(to_append.into_iter()).collect();
+    }
+}
 
 impl<T: Merging> Merging for Spanned<T> {
     fn merge(self, new: Self) -> Self {
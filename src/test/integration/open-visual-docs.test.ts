@@ -29,6 +29,11 @@ test.describe('Visual Documentation', () => {
         };
     }
 
+    // keys are labeled as on the current platform (see `display_keys`)
+    const mac = process.platform === 'darwin';
+    const ctrl = (key: string) => mac ? `⌃${key}` : `Ctrl+${key}`;
+    const alt = (key: string) => mac ? `⌥${key}` : `Alt+${key}`;
+
     async function checkKey(
         label: string,
         name: string,
@@ -73,8 +78,8 @@ test.describe('Visual Documentation', () => {
     if (process.env.CI !== 'true') {
         test('Layout toggles by command', async ({ workbox }) => {
             await checkKey('I', 'insert mode', '1', getKey(2, 8));
-            await checkKey('^I', 'magic insert', '1', getKey(2, 8, 'top'));
-            await checkKey('^O', 'magic outsert', '1', getKey(2, 9, 'top'));
+            await checkKey(ctrl('I'), 'magic insert', '1', getKey(2, 8, 'top'));
+            await checkKey(ctrl('O'), 'magic outsert', '1', getKey(2, 9, 'top'));
 
             await runCommand(workbox, 'Master Key: Toggle Visual Doc Modifiers');
 
@@ -84,9 +89,9 @@ test.describe('Visual Documentation', () => {
                 if (text === 'evil insert') break;
                 await workbox.waitForTimeout(100);
             }
-            await checkKey('⌥I', 'evil insert', '1', key);
+            await checkKey(alt('I'), 'evil insert', '1', key);
             await checkKey('I', 'insert mode', '1', getKey(2, 8));
-            await checkKey('⌥O', '', 'none', getKey(2, 9, 'top'));
+            await checkKey(alt('O'), '', 'none', getKey(2, 9, 'top'));
         });
     }
 });